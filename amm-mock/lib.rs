@@ -0,0 +1,52 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+/// stands in for an amm in e2e tests, `calculate_exchange` answers at a settable fixed rate
+#[ink::contract]
+pub mod amm_mock {
+    #[derive(scale::Decode, scale::Encode, Clone, Copy, Debug, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Currency {
+        D9,
+        USDT,
+    }
+
+    #[derive(scale::Decode, scale::Encode, Clone, Copy, Debug, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct Direction(Currency, Currency);
+
+    #[derive(scale::Decode, scale::Encode, Debug, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Error {
+        NoRate,
+    }
+
+    #[ink(storage)]
+    pub struct AmmMock {
+        rate: Balance,
+    }
+
+    impl AmmMock {
+        #[ink(constructor)]
+        pub fn new(rate: Balance) -> Self {
+            Self { rate }
+        }
+
+        /// `amount` times the rate whatever the direction, a zero rate fails like a dry pool
+        #[ink(message)]
+        pub fn calculate_exchange(
+            &self,
+            _direction: Direction,
+            amount: Balance
+        ) -> Result<Balance, Error> {
+            if self.rate == 0 {
+                return Err(Error::NoRate);
+            }
+            Ok(amount.saturating_mul(self.rate))
+        }
+
+        #[ink(message)]
+        pub fn set_rate(&mut self, rate: Balance) {
+            self.rate = rate;
+        }
+    }
+}
@@ -148,6 +148,10 @@ pub enum Error {
     CallRuntimeFailed,
     EcdsaRecoveryFailed,
     WithdrawalAmountExceedsBalance,
+    /// the session already has a burn checkpoint
+    AlreadyCheckpointed,
+    /// checkpoints can only be taken for the current session
+    SessionNotCurrent,
 }
 
 impl From<EnvError> for Error {
@@ -10,6 +10,10 @@ pub mod d9_burn_mining {
     use ink::storage::Mapping;
     use sp_arithmetic::Perbill;
     use sp_arithmetic::Perquintill;
+
+    /// ancestors beyond this depth don't receive withdrawal boosts
+    pub const MAX_BOOST_DEPTH: usize = 10;
+
     #[ink(storage)]
     pub struct D9burnMining {
         ///total amount of tokens burned so far globally
@@ -23,6 +27,17 @@ pub mod d9_burn_mining {
         /// set it here to easily adjust for testing for unit, e2e tests and test network
         pub day_milliseconds: Timestamp,
         pub admin: AccountId,
+        /// boost credited by descendants' withdrawals, paid out with the next withdrawal
+        ancestor_boosts: Mapping<AccountId, Balance>,
+    }
+
+    #[ink(event)]
+    pub struct BoostCredited {
+        #[ink(topic)]
+        ancestor: AccountId,
+        #[ink(topic)]
+        descendant: AccountId,
+        amount: Balance,
     }
 
     impl D9burnMining {
@@ -36,6 +51,7 @@ pub mod d9_burn_mining {
                 burn_minimum,
                 day_milliseconds,
                 admin: Self::env().caller(),
+                ancestor_boosts: Default::default(),
             }
         }
 
@@ -69,6 +85,12 @@ pub mod d9_burn_mining {
             self.accounts.get(&account_id)
         }
 
+        /// boost waiting to be paid with `account_id`'s next withdrawal
+        #[ink(message)]
+        pub fn get_ancestor_boost(&self, account_id: AccountId) -> Balance {
+            self.ancestor_boosts.get(&account_id).unwrap_or(0)
+        }
+
         /// burn funcion callable by ownly master contract
         ///
         /// does the necessary checks then calls the internal burn function `_burn`
@@ -137,10 +159,18 @@ pub mod d9_burn_mining {
                 return Err(Error::WithdrawalNotAllowed);
             }
 
+            // coefficients are no longer accrued, only settled for accounts that still carry them
             let referral_boost =
                 self._calculate_referral_boost_reward(account.referral_boost_coefficients);
+            let ancestor_boost = self._settle_ancestor_boost(
+                account_id,
+                account.balance_due,
+                base_extraction.saturating_add(referral_boost),
+            );
 
-            let total_withdrawal = base_extraction.saturating_add(referral_boost);
+            let total_withdrawal = base_extraction
+                .saturating_add(referral_boost)
+                .saturating_add(ancestor_boost);
 
             // Update the account's details
             let new_time = self.env().block_timestamp();
@@ -156,7 +186,7 @@ pub mod d9_burn_mining {
             let maybe_ancestors = self.get_ancestors(account_id);
             if maybe_ancestors.is_some() {
                 let ancestors = maybe_ancestors.unwrap();
-                self._update_ancestors_coefficents(base_extraction, &ancestors);
+                self._credit_ancestor_boosts(account_id, base_extraction, &ancestors);
             }
             {
                 if total_withdrawal > old_balance_due {
@@ -253,31 +283,48 @@ pub mod d9_burn_mining {
 
             direct_referral_boost.saturating_add(indirect_referral_boost)
         }
-        //todo what is last_burn used for
-        fn _update_ancestors_coefficents(&mut self, allowance: Balance, ancestors: &[AccountId]) {
-            let parent = ancestors[0];
-            let mut account = self
-                .accounts
-                .get(&parent)
-                .unwrap_or_else(|| Account::new(self.env().block_timestamp()));
-            // add allowance to parent's x coefficient in R_a = base_extraction_rate + x0.1 + y0.01
-            account.referral_boost_coefficients.0 = account
-                .referral_boost_coefficients
-                .0
-                .saturating_add(allowance);
-            self.accounts.insert(parent, &account);
-
-            for ancestor in ancestors.iter().skip(1) {
-                let mut ancestor_account = self
-                    .accounts
-                    .get(&ancestor)
-                    .unwrap_or_else(|| Account::new(self.env().block_timestamp()));
-                // add allowance to ancestor's y coefficient in R_a = base_extraction_rate + x0.1 + y0.01
-                ancestor_account.referral_boost_coefficients.1 = ancestor_account
-                    .referral_boost_coefficients
-                    .1
-                    .saturating_add(allowance);
-                self.accounts.insert(ancestor, &ancestor_account);
+        /// pay out as much of the stored ancestor boost as `balance_due` leaves room for after
+        /// `other_withdrawal`, keeping the remainder for a later withdrawal
+        fn _settle_ancestor_boost(
+            &mut self,
+            account_id: AccountId,
+            balance_due: Balance,
+            other_withdrawal: Balance,
+        ) -> Balance {
+            let stored = self.get_ancestor_boost(account_id);
+            let paid = stored.min(balance_due.saturating_sub(other_withdrawal));
+            let remainder = stored.saturating_sub(paid);
+            if remainder == 0 {
+                self.ancestor_boosts.remove(account_id);
+            } else {
+                self.ancestor_boosts.insert(account_id, &remainder);
+            }
+            paid
+        }
+        /// credit 10% of `allowance` to the parent and 1% to each deeper ancestor, up to `MAX_BOOST_DEPTH`
+        fn _credit_ancestor_boosts(
+            &mut self,
+            descendant: AccountId,
+            allowance: Balance,
+            ancestors: &[AccountId],
+        ) {
+            for (depth, ancestor) in ancestors.iter().take(MAX_BOOST_DEPTH).enumerate() {
+                let boost_percent = if depth == 0 {
+                    Perbill::from_percent(10)
+                } else {
+                    Perbill::from_percent(1)
+                };
+                let amount = boost_percent.mul_floor(allowance);
+                if amount == 0 {
+                    continue;
+                }
+                let boost = self.get_ancestor_boost(*ancestor).saturating_add(amount);
+                self.ancestor_boosts.insert(ancestor, &boost);
+                self.env().emit_event(BoostCredited {
+                    ancestor: *ancestor,
+                    descendant,
+                    amount,
+                });
             }
         }
 
@@ -408,5 +455,64 @@ pub mod d9_burn_mining {
                 24_000000000000 + 100_000_000_000_000 + 10_000_000_000_000
             );
         }
+
+        #[ink::test]
+        fn ancestor_boosts_split_parent_and_deeper() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut d9_burn_mining = D9burnMining::new(accounts.alice, 100);
+            let ancestors = [accounts.bob, accounts.charlie, accounts.django];
+            d9_burn_mining._credit_ancestor_boosts(accounts.eve, 1_000_000, &ancestors);
+            d9_burn_mining._credit_ancestor_boosts(accounts.eve, 1_000_000, &ancestors);
+
+            assert_eq!(d9_burn_mining.get_ancestor_boost(accounts.bob), 200_000);
+            assert_eq!(d9_burn_mining.get_ancestor_boost(accounts.charlie), 20_000);
+            assert_eq!(d9_burn_mining.get_ancestor_boost(accounts.django), 20_000);
+            assert_eq!(d9_burn_mining.get_ancestor_boost(accounts.eve), 0);
+            assert_eq!(ink::env::test::recorded_events().count(), 6);
+        }
+
+        #[ink::test]
+        fn ancestor_boost_is_only_consumed_up_to_balance_due() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut d9_burn_mining = D9burnMining::new(accounts.alice, 100);
+            d9_burn_mining._credit_ancestor_boosts(accounts.eve, 1_000_000, &[accounts.bob]);
+
+            // room for the whole boost
+            let paid = d9_burn_mining._settle_ancestor_boost(accounts.bob, 500_000, 300_000);
+            assert_eq!(paid, 100_000);
+            assert_eq!(d9_burn_mining.get_ancestor_boost(accounts.bob), 0);
+
+            // capped by balance_due, the rest stays stored
+            d9_burn_mining._credit_ancestor_boosts(accounts.eve, 1_000_000, &[accounts.bob]);
+            let paid = d9_burn_mining._settle_ancestor_boost(accounts.bob, 500_000, 460_000);
+            assert_eq!(paid, 40_000);
+            assert_eq!(d9_burn_mining.get_ancestor_boost(accounts.bob), 60_000);
+
+            // nothing due beyond the other withdrawal, nothing consumed
+            let paid = d9_burn_mining._settle_ancestor_boost(accounts.bob, 500_000, 600_000);
+            assert_eq!(paid, 0);
+            assert_eq!(d9_burn_mining.get_ancestor_boost(accounts.bob), 60_000);
+        }
+
+        #[ink::test]
+        fn ancestor_boosts_stop_at_max_depth() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut d9_burn_mining = D9burnMining::new(accounts.alice, 100);
+            let ancestors: Vec<AccountId> = (0..MAX_BOOST_DEPTH as u8 + 2)
+                .map(|i| AccountId::from([i; 32]))
+                .collect();
+            d9_burn_mining._credit_ancestor_boosts(accounts.eve, 1_000_000, &ancestors);
+
+            assert_eq!(d9_burn_mining.get_ancestor_boost(ancestors[0]), 100_000);
+            assert_eq!(
+                d9_burn_mining.get_ancestor_boost(ancestors[MAX_BOOST_DEPTH - 1]),
+                10_000
+            );
+            assert_eq!(
+                d9_burn_mining.get_ancestor_boost(ancestors[MAX_BOOST_DEPTH]),
+                0
+            );
+            assert_eq!(ink::env::test::recorded_events().count(), MAX_BOOST_DEPTH);
+        }
     }
 }
@@ -64,6 +64,12 @@ pub trait D9ChainExtension {
         vote_delegator: AccountId,
         voting_interests: u64,
     ) -> Result<(), RuntimeError>;
+
+    /// (node, era points earned) for the nodes that produced blocks in `session_index`
+    #[ink(extension = 10)]
+    fn get_session_era_points(
+        session_index: u32,
+    ) -> Result<Vec<(AccountId, u32)>, RuntimeError>;
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
@@ -78,6 +84,7 @@ pub enum RuntimeError {
     ErrorGettingUserVoteRatioForCandidate,
     ErrorGettingCurrentValidators,
     ErrorAddingVotingInterests,
+    ErrorGettingEraPoints,
 }
 
 impl From<scale::Error> for RuntimeError {
@@ -99,6 +106,7 @@ impl ink::env::chain_extension::FromStatusCode for RuntimeError {
             7 => Err(Self::ErrorGettingUserVoteRatioForCandidate),
             8 => Err(Self::ErrorGettingCurrentValidators),
             9 => Err(Self::ErrorAddingVotingInterests),
+            10 => Err(Self::ErrorGettingEraPoints),
             _ => panic!("encountered unknown status code"),
         }
     }
@@ -6,7 +6,7 @@ mod cross_chain_transfer {
 
     use ink::env::{
         call::{ build_call, ExecutionInput, Selector },
-        hash::{ HashOutput, Keccak256 },
+        hash::{ Blake2x256, HashOutput, Keccak256 },
         hash_encoded,
     };
     use ink::prelude::string::String;
@@ -24,6 +24,8 @@ mod cross_chain_transfer {
         usdt_contract: AccountId,
         transactions: Mapping<String, Transaction>,
         transaction_admins: Vec<AccountId>,
+        /// commit transaction id => transfer commitment, see `compute_commitment`
+        commitments: Mapping<String, [u8; 32]>,
     }
 
     #[ink(event)]
@@ -32,8 +34,10 @@ mod cross_chain_transfer {
         pub transaction_id: String,
         #[ink(topic)]
         pub from_address: AccountId,
-        #[ink(topic)]
+        /// event data only, the commitment took its topic slot
         pub amount: u128,
+        #[ink(topic)]
+        pub commitment: [u8; 32],
     }
 
     #[ink(event)]
@@ -113,6 +117,7 @@ mod cross_chain_transfer {
                 usdt_contract,
                 transactions: Mapping::new(),
                 transaction_admins: Vec::new(),
+                commitments: Mapping::new(),
             }
         }
         #[ink(message)]
@@ -250,13 +255,24 @@ mod cross_chain_transfer {
                 timestamp: self.env().block_timestamp(),
             };
 
+            let commitment = Self::compute_commitment(
+                &transaction_id,
+                from_address,
+                to_address,
+                amount,
+                Chain::TRON,
+                transaction.timestamp
+            );
+
             self.increase_transaction_nonce(from_address);
             self.transactions.insert(transaction_id.clone(), &transaction);
+            self.commitments.insert(transaction_id.clone(), &commitment);
 
             self.env().emit_event(CommitCreated {
                 transaction_id: transaction_id.clone(),
                 from_address,
                 amount,
+                commitment,
             });
             Ok(transaction_id)
         }
@@ -303,6 +319,65 @@ mod cross_chain_transfer {
             Ok(tx_id)
         }
 
+        /// commitment recorded by `asset_commit`, none for dispatches and older commits
+        #[ink(message)]
+        pub fn get_commitment(&self, transfer_id: String) -> Option<[u8; 32]> {
+            self.commitments.get(&transfer_id)
+        }
+
+        /// recompute a commitment from the transfer fields and compare, see `compute_commitment`
+        #[ink(message)]
+        #[allow(clippy::too_many_arguments)]
+        pub fn verify_commitment(
+            &self,
+            transfer_id: String,
+            sender: AccountId,
+            recipient_chain_address: [u8; 21],
+            amount: Balance,
+            chain_id: Chain,
+            created_at: Timestamp,
+            commitment: [u8; 32]
+        ) -> bool {
+            Self::compute_commitment(
+                &transfer_id,
+                sender,
+                recipient_chain_address,
+                amount,
+                chain_id,
+                created_at
+            ) == commitment
+        }
+
+        /// blake2_256 of the SCALE encoded tuple
+        /// `(transfer_id, sender, recipient_chain_address, amount, chain_id, created_at)`:
+        ///
+        /// - transfer_id: compact length prefix followed by the utf-8 bytes
+        /// - sender: 32 bytes
+        /// - recipient_chain_address: 21 bytes, no prefix
+        /// - amount: u128 little endian
+        /// - chain_id: 1 byte destination chain index, D9 = 0, TRON = 1
+        /// - created_at: u64 little endian milliseconds
+        fn compute_commitment(
+            transfer_id: &String,
+            sender: AccountId,
+            recipient_chain_address: [u8; 21],
+            amount: Balance,
+            chain_id: Chain,
+            created_at: Timestamp
+        ) -> [u8; 32] {
+            let encodable = (
+                transfer_id,
+                sender,
+                recipient_chain_address,
+                amount,
+                chain_id,
+                created_at,
+            );
+            let mut output = <Blake2x256 as HashOutput>::Type::default();
+            hash_encoded::<Blake2x256, _>(&encodable, &mut output);
+            output
+        }
+
         #[ink(message)]
         pub fn change_controller(&mut self, new_controller: AccountId) {
             assert_eq!(self.super_admin, self.env().caller());
@@ -485,6 +560,90 @@ mod cross_chain_transfer {
 
             println!("address: {:?}", hex::encode(address));
         }
+
+        #[ink::test]
+        fn commitment_matches_fixed_vector() {
+            let transfer_id = String::from("tx-1");
+            let sender = AccountId::from([0x1; 32]);
+            let recipient = [0x41; 21];
+            let (amount, created_at) = (1_000_000, 1_700_000_000_000);
+
+            // pinned so relayers can check their encoding byte for byte
+            let encoded = (&transfer_id, sender, recipient, amount, Chain::TRON, created_at).encode();
+            assert_eq!(
+                hex::encode(&encoded),
+                "1074782d31\
+                 0101010101010101010101010101010101010101010101010101010101010101\
+                 414141414141414141414141414141414141414141\
+                 40420f00000000000000000000000000\
+                 01\
+                 0068e5cf8b010000"
+            );
+            let commitment = CrossChainTransfer::compute_commitment(
+                &transfer_id,
+                sender,
+                recipient,
+                amount,
+                Chain::TRON,
+                created_at
+            );
+            assert_eq!(
+                hex::encode(commitment),
+                "1fa5da3ad4b7675b5ac2de18299e3bd4f4d20e22e0e56453c85b58e9e5423d49"
+            );
+
+            let cross_chain_transfer = CrossChainTransfer::new(AccountId::from([0x2; 32]));
+            assert!(
+                cross_chain_transfer.verify_commitment(
+                    transfer_id.clone(),
+                    sender,
+                    recipient,
+                    amount,
+                    Chain::TRON,
+                    created_at,
+                    commitment
+                )
+            );
+        }
+
+        #[ink::test]
+        fn commitment_rejects_changed_fields() {
+            let cross_chain_transfer = CrossChainTransfer::new(AccountId::from([0x2; 32]));
+            let transfer_id = String::from("tx-1");
+            let sender = AccountId::from([0x1; 32]);
+            let commitment = CrossChainTransfer::compute_commitment(
+                &transfer_id,
+                sender,
+                [0x41; 21],
+                1_000_000,
+                Chain::TRON,
+                1_700_000_000_000
+            );
+            // one unit more
+            assert!(
+                !cross_chain_transfer.verify_commitment(
+                    transfer_id.clone(),
+                    sender,
+                    [0x41; 21],
+                    1_000_001,
+                    Chain::TRON,
+                    1_700_000_000_000,
+                    commitment
+                )
+            );
+            // same fields, other chain
+            assert!(
+                !cross_chain_transfer.verify_commitment(
+                    transfer_id,
+                    sender,
+                    [0x41; 21],
+                    1_000_000,
+                    Chain::D9,
+                    1_700_000_000_000,
+                    commitment
+                )
+            );
+        }
     }
 
     /// This is how you'd write end-to-end (E2E) or integration tests for ink! contracts.
@@ -0,0 +1,33 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+/// stands in for a mining pool from before `compute_session_reward` in e2e tests. only the
+/// legacy `update_pool_and_retrieve` is there, answering with a settable reward pool
+#[ink::contract]
+pub mod legacy_mining_pool_mock {
+    #[derive(scale::Decode, scale::Encode, Debug, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Error {
+        NoRewardPool,
+    }
+
+    #[ink(storage)]
+    pub struct LegacyMiningPoolMock {
+        reward_pool: Balance,
+    }
+
+    impl LegacyMiningPoolMock {
+        #[ink(constructor)]
+        pub fn new(reward_pool: Balance) -> Self {
+            Self { reward_pool }
+        }
+
+        /// the reward pool whatever the session
+        #[ink(message)]
+        pub fn update_pool_and_retrieve(
+            &mut self,
+            _session_index: u32
+        ) -> Result<Balance, Error> {
+            Ok(self.reward_pool)
+        }
+    }
+}
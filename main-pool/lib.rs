@@ -1,7 +1,7 @@
 #![cfg_attr(not(feature = "std"), no_std, no_main)]
 use d9_burn_common::{ActionRecord, BurnPortfolio, D9Environment, Error};
 #[ink::contract(env = D9Environment)]
-mod d9_main_pool {
+pub mod d9_main_pool {
     use core::result;
 
     use super::*;
@@ -22,6 +22,8 @@ mod d9_main_pool {
         total_amount_burned: Balance,
         node_reward_contract: AccountId,
         mining_pool: AccountId,
+        /// `total_amount_burned` snapshotted by the first `checkpoint_session` call of each session
+        burned_at_session: Mapping<u32, Balance>,
     }
 
     #[ink(event)]
@@ -61,6 +63,7 @@ mod d9_main_pool {
                 portfolios: Default::default(),
                 total_amount_burned: Default::default(),
                 mining_pool,
+                burned_at_session: Default::default(),
             }
         }
         #[ink(message)]
@@ -214,6 +217,35 @@ mod d9_main_pool {
             self.total_amount_burned
         }
 
+        /// snapshot the total burned for the current session. anyone can call it, only the first
+        /// call per session is recorded
+        #[ink(message)]
+        pub fn checkpoint_session(&mut self, session_index: u32) -> Result<Balance, Error> {
+            let current_session_index = self
+                .env()
+                .extension()
+                .get_current_session_index()
+                .map_err(|_| Error::SomeEnvironmentError)?;
+            if session_index != current_session_index {
+                return Err(Error::SessionNotCurrent);
+            }
+            self.record_session_checkpoint(session_index)
+        }
+
+        #[ink(message)]
+        pub fn get_burned_at_session(&self, session_index: u32) -> Option<Balance> {
+            self.burned_at_session.get(session_index)
+        }
+
+        fn record_session_checkpoint(&mut self, session_index: u32) -> Result<Balance, Error> {
+            if self.burned_at_session.contains(session_index) {
+                return Err(Error::AlreadyCheckpointed);
+            }
+            self.burned_at_session
+                .insert(session_index, &self.total_amount_burned);
+            Ok(self.total_amount_burned)
+        }
+
         #[ink(message)]
         pub fn get_portfolio(&self, account_id: AccountId) -> Option<BurnPortfolio> {
             self.portfolios.get(&account_id)
@@ -384,7 +416,44 @@ mod d9_main_pool {
     #[cfg(test)]
     mod tests {
         /// Imports all the definitions from the outer scope so we can use them here.
-        use d9_main_pool::*;
+        use super::*;
+
+        fn default_main_pool() -> D9MainPool {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            D9MainPool::new(accounts.alice, vec![], accounts.bob, accounts.charlie)
+        }
+
+        #[ink::test]
+        fn session_checkpoint_is_taken_once() {
+            let mut main_pool = default_main_pool();
+            main_pool.total_amount_burned = 1_000;
+            assert_eq!(main_pool.record_session_checkpoint(7), Ok(1_000));
+
+            main_pool.total_amount_burned = 2_000;
+            assert_eq!(
+                main_pool.record_session_checkpoint(7),
+                Err(Error::AlreadyCheckpointed)
+            );
+            // first caller wins
+            assert_eq!(main_pool.get_burned_at_session(7), Some(1_000));
+            assert_eq!(main_pool.get_burned_at_session(8), None);
+        }
+
+        #[ink::test]
+        fn session_checkpoints_never_decrease() {
+            let mut main_pool = default_main_pool();
+            let mut previous = 0;
+            for session_index in 1..5u32 {
+                main_pool.total_amount_burned = main_pool
+                    .total_amount_burned
+                    .saturating_add(session_index as Balance * 100);
+                main_pool.record_session_checkpoint(session_index).unwrap();
+                let checkpoint = main_pool.get_burned_at_session(session_index).unwrap();
+                assert!(checkpoint >= previous);
+                previous = checkpoint;
+            }
+            assert_eq!(previous, 1_000);
+        }
     }
     #[cfg(all(test, feature = "e2e-tests"))]
     mod e2e_tests {
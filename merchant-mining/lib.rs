@@ -6,12 +6,14 @@ pub use d9_chain_extension::D9Environment;
 mod d9_merchant_mining {
     use super::*;
     use ink::env::call::{build_call, ExecutionInput, Selector};
-    use ink::env::Error as EnvError;
-    use ink::prelude::vec::Vec;
+    use ink::env::hash::{Blake2x256, HashOutput};
+    use ink::env::{hash_encoded, Error as EnvError};
+    use ink::prelude::{vec, vec::Vec};
     use ink::selector_bytes;
     use ink::storage::Mapping;
     use scale::{Decode, Encode};
-    use sp_arithmetic::Perbill;
+    use sp_arithmetic::helpers_128bit::multiply_by_rational_with_rounding;
+    use sp_arithmetic::{Perbill, Rounding};
 
     #[ink(storage)]
     pub struct D9MerchantMining {
@@ -19,12 +21,87 @@ mod d9_merchant_mining {
         /// rewards system accounts
         merchant_expiry: Mapping<AccountId, Timestamp>,
         accounts: Mapping<AccountId, Account>,
+        /// monthly fee of the basic tier while `tiers` has no entry for it
         subscription_fee: Balance,
         usdt_contract: AccountId,
         amm_contract: AccountId,
         mining_pool: AccountId,
         milliseconds_day: Timestamp,
         admin: AccountId,
+        /// accounts imported from the legacy merchant mining contract
+        migrated_from_legacy: Mapping<AccountId, ()>,
+        /// proposed admin, must call `accept_admin` to take over
+        pending_admin: Option<AccountId>,
+        /// (usdt threshold where the tier starts, green points multiplier), ascending from 0
+        green_points_curve: Vec<(Balance, u32)>,
+        /// use `green_points_curve` instead of the flat 100x multiplier
+        green_points_curve_enabled: bool,
+        /// usdt the amm must hold before d9 is converted, 0 disables the check
+        min_amm_usdt_liquidity: Balance,
+        /// (red points settled before green points were added, accrual start for the rest)
+        settled_red_points: Mapping<AccountId, (Balance, Timestamp)>,
+        /// gas forwarded to each `CallTarget`, unset targets use `CallTarget::default_gas_limit`
+        call_gas_limits: Mapping<u8, u64>,
+        /// expiry each merchant was last reminded about
+        last_reminded_expiry: Mapping<AccountId, Timestamp>,
+        /// (beneficiary, delegate) pairs allowed to redeem on the beneficiary's behalf
+        redeem_delegates: Mapping<(AccountId, AccountId), bool>,
+        /// index => every account that ever subscribed, in first subscription order. it only
+        /// grows, one entry per merchant, and is read in pages by `get_active_merchants_paged`
+        merchant_list: Mapping<u32, AccountId>,
+        /// entries in `merchant_list`, the index of the next one
+        merchant_count: u32,
+        /// share of a payment sent to the merchant, the rest funds green points
+        merchant_share_permille: u32,
+        /// red points accrue per unit of this length, 0 accrues per whole `milliseconds_day`
+        accrual_unit_ms: Timestamp,
+        /// (merchant, index) => (amount, paid_at, resulting_expiry), indices run from 0 to
+        /// `subscription_payment_count - 1`
+        subscription_payments: Mapping<(AccountId, u32), (Balance, Timestamp, Timestamp)>,
+        subscription_payment_count: Mapping<AccountId, u32>,
+        total_subscription_revenue: Balance,
+        /// payment id => (merchant, usdt) for payments whose d9 conversion failed. the usdt
+        /// stays here until `retry_conversion` converts it and forwards it to the mining pool
+        failed_conversions: Mapping<[u8; 32], (AccountId, Balance)>,
+        /// number of failed conversions so far, the nonce of the next payment id
+        failed_conversion_count: u64,
+        /// d9 redemptions also add voting interests for the redeeming user
+        grant_votes_on_redeem: bool,
+        /// green points a consumer may receive per day, 0 disables the cap
+        max_points_per_day: Balance,
+        /// green points a consumer may receive from one merchant per day, 0 disables the cap
+        max_points_per_merchant_per_day: Balance,
+        /// (consumer, day index) => green points issued to the consumer that day
+        points_issued_per_day: Mapping<(AccountId, u64), Balance>,
+        /// (consumer, merchant, day index) => green points issued by the merchant that day
+        points_issued_per_merchant_day: Mapping<(AccountId, AccountId, u64), Balance>,
+        /// green points held by all accounts, the redeemable liability of the mining pool
+        total_outstanding_green_points: Balance,
+        /// grants that would take `total_outstanding_green_points` above this fail, 0 disables
+        /// the cap
+        max_total_green_points: Balance,
+        /// queue index => (merchant, d9) the mining pool didn't accept, the d9 stays here until
+        /// `flush_pending_forwards` delivers it
+        pending_pool_forwards: Mapping<u32, (AccountId, Balance)>,
+        /// index of the oldest queued forward
+        pending_pool_forward_head: u32,
+        /// index the next deferred forward is queued under
+        pending_pool_forward_count: u32,
+        /// (merchant, receipt seq) => (consumer, usdt paid, usdt refunded so far) for payments
+        /// sent to the merchant
+        payment_receipts: Mapping<(AccountId, u32), (AccountId, Balance, Balance)>,
+        /// receipts issued per merchant, the seq of the next one
+        receipt_count: Mapping<AccountId, u32>,
+        /// green points clawed back by refunds that the account no longer held, taken out of
+        /// its future grants
+        refund_debt: Mapping<AccountId, Balance>,
+        /// last time each account paid, was paid, redeemed or subscribed. kept beside
+        /// `accounts` so the `Account` layout doesn't change
+        last_activity: Mapping<AccountId, Timestamp>,
+        /// `SubscriptionTier` => (monthly fee, green point threshold)
+        tiers: Mapping<u8, (Balance, Balance)>,
+        /// how far below the amm quote taken just before a d9 conversion the swap may fill
+        conversion_slippage_permille: u32,
     }
 
     #[derive(Decode, Encode, Clone)]
@@ -74,6 +151,77 @@ mod d9_merchant_mining {
     #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
     #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
     pub struct Direction(Currency, Currency);
+
+    /// largest page returned by `get_subscription_payments`
+    pub const MAX_SUBSCRIPTION_PAYMENT_PAGE: u32 = 50;
+
+    /// largest page returned by `get_active_merchants_paged`
+    pub const MAX_MERCHANT_PAGE: u32 = 100;
+
+    /// ancestors looked at for referral bonuses, the ones further up get nothing
+    pub const MAX_ANCESTOR_DEPTH: usize = 20;
+
+    /// green points needed to subscribe to the basic tier until `set_tier` says otherwise
+    pub const DEFAULT_SUBSCRIPTION_POINT_THRESHOLD: Balance = 100_000_000;
+
+    /// subscription plans, each with its own monthly fee and green point threshold
+    #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum SubscriptionTier {
+        Basic = 0,
+        Pro = 1,
+    }
+
+    /// mining pool quote for a d9 redemption, price protection included
+    #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct RedeemPreview {
+        pub d9_amount: Balance,
+        pub current_rate: Balance,
+        pub highest_rate: Balance,
+        pub effective_rate: Balance,
+        pub protection_applied: bool,
+    }
+
+    /// layout version of `AccountView`, bumped whenever a field is added or changes meaning
+    pub const ACCOUNT_VIEW_VERSION: u32 = 1;
+
+    /// an account with the values `redeem` would act on, computed at call time
+    #[derive(Decode, Encode, Clone)]
+    #[cfg_attr(feature = "std", derive(Debug, PartialEq, Eq, scale_info::TypeInfo))]
+    pub struct AccountView {
+        pub view_version: u32,
+        pub account: Account,
+        /// red points a redemption would consume, ignoring the lockout
+        pub redeemable_red_points: Balance,
+        /// usdt value of `redeemable_red_points`
+        pub redeemable_usdt: Balance,
+        /// milliseconds until the redeem lockout ends, 0 if redeeming is open
+        pub lockout_remaining: Timestamp,
+        pub merchant_expiry: Option<Timestamp>,
+        pub is_active_merchant: bool,
+    }
+
+    /// contracts called by merchant mining, each with its own gas budget
+    #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum CallTarget {
+        Usdt,
+        Amm,
+        MiningPool,
+    }
+
+    impl CallTarget {
+        /// a gas limit of 0 forwards all remaining gas
+        fn default_gas_limit(&self) -> u64 {
+            match self {
+                CallTarget::Usdt => 10_000_000_000,
+                CallTarget::Amm => 50_000_000_000,
+                // the mining pool calls the amm in turn
+                CallTarget::MiningPool => 100_000_000_000,
+            }
+        }
+    }
     // data to return to user
     #[derive(Decode, Encode)]
     #[cfg_attr(
@@ -87,10 +235,20 @@ mod d9_merchant_mining {
         )
     )]
     pub struct GreenPointsResult {
+        result_version: u32,
         merchant: Balance,
         consumer: Balance,
+        /// usdt the amm paid for a d9 payment, none for everything else
+        usdt_received: Option<Balance>,
     }
 
+    /// layout version of `GreenPointsResult`, bumped whenever a field is added or changes meaning
+    pub const GREEN_POINTS_RESULT_VERSION: u32 = 1;
+
+    /// scale of `D9MerchantPaymentSent::effective_rate`. the rate is taken in base units, and
+    /// with d9 at 12 decimals and usdt at 2 a smaller scale rounds every real rate to 0
+    pub const EFFECTIVE_RATE_PRECISION: Balance = 1_000_000_000_000_000_000;
+
     #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
     #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
     pub enum Error {
@@ -112,6 +270,7 @@ mod d9_merchant_mining {
         ConvertingToD9,
         SendUSDTToMerchant,
         SendingD9ToMiningPool,
+        /// unused, subscriptions are swapped instead of sent to the amm
         SendingUSDTToAMM,
         GettingUSDTFromAMM,
         RedeemD9TransferFailed,
@@ -136,6 +295,33 @@ mod d9_merchant_mining {
         CrossContractCallErrorGettingEstimate,
         NoAccountCantCreateMerchantAccount,
         PointsInsufficientToCreateMerchantAccount,
+        InvalidMillisecondsDay,
+        AccountAlreadyExists,
+        ImportBatchTooLarge,
+        RedeemUSDTTransferFailed,
+        SelfDealingNotAllowed,
+        NotPendingAdmin,
+        InvalidGreenPointsCurve,
+        AMMLiquidityTooLow,
+        ReminderBatchTooLarge,
+        NotRedeemDelegate,
+        InvalidMerchantShare,
+        InvalidAccrualUnit,
+        NoFailedConversion,
+        OnlyMerchantOrAdmin,
+        PointsVelocityExceeded,
+        /// the amm's swap fee isn't the one the caller expected
+        AmmFeeChanged,
+        /// the grant would take the outstanding green points above `max_total_green_points`
+        GlobalPointCapReached,
+        NoReceiptFound,
+        /// the refund is larger than what is left of the receipt's payment
+        RefundExceedsPayment,
+        NothingToRefund,
+        /// `subscribe` named a tier that has no fee set
+        UnknownSubscriptionTier,
+        InvalidSubscriptionFee,
+        InvalidSlippage,
     }
 
     impl From<EnvError> for Error {
@@ -168,6 +354,24 @@ mod d9_merchant_mining {
         expiry: Timestamp,
     }
 
+    /// referral bonuses of a redemption by `account_id`. `parent` is the nearest ancestor with
+    /// an account, `ancestors` the ones above it that got the smaller share
+    #[ink(event)]
+    pub struct AncestorsCredited {
+        #[ink(topic)]
+        account_id: AccountId,
+        parent: AccountId,
+        ancestors: Vec<AccountId>,
+    }
+
+    /// a merchant subscription lapses within the reminder window
+    #[ink(event)]
+    pub struct ExpiryReminder {
+        #[ink(topic)]
+        merchant: AccountId,
+        expiry: Timestamp,
+    }
+
     #[ink(event)]
     pub struct D9Redeemed {
         #[ink(topic)]
@@ -176,6 +380,82 @@ mod d9_merchant_mining {
         redeemed_d9: Balance,
     }
 
+    /// the usdt of a payment is held for `retry_conversion`
+    #[ink(event)]
+    pub struct ConversionFailed {
+        #[ink(topic)]
+        payment_id: [u8; 32],
+        #[ink(topic)]
+        merchant: AccountId,
+        usdt: Balance,
+    }
+
+    /// the mining pool refused a forward, the d9 is queued for `flush_pending_forwards`
+    #[ink(event)]
+    pub struct PoolForwardDeferred {
+        index: u32,
+        #[ink(topic)]
+        merchant: AccountId,
+        d9: Balance,
+    }
+
+    #[ink(event)]
+    pub struct PoolForwardFlushed {
+        index: u32,
+        #[ink(topic)]
+        merchant: AccountId,
+        d9: Balance,
+    }
+
+    #[ink(event)]
+    pub struct ConversionRetried {
+        #[ink(topic)]
+        payment_id: [u8; 32],
+        #[ink(topic)]
+        merchant: AccountId,
+        d9: Balance,
+    }
+
+    /// a payment to `merchant` was recorded under `receipt_seq` for `refund_payment`
+    #[ink(event)]
+    pub struct PaymentReceiptIssued {
+        #[ink(topic)]
+        merchant: AccountId,
+        #[ink(topic)]
+        consumer: AccountId,
+        receipt_seq: u32,
+        usdt: Balance,
+    }
+
+    #[ink(event)]
+    pub struct PaymentRefunded {
+        #[ink(topic)]
+        merchant: AccountId,
+        #[ink(topic)]
+        consumer: AccountId,
+        receipt_seq: Option<u32>,
+        /// usdt returned to the consumer, the merchant's share of the refunded amount
+        usdt_refunded: Balance,
+        consumer_points_clawed_back: Balance,
+        merchant_points_clawed_back: Balance,
+    }
+
+    /// points were added to an account that has red points waiting for `redeem`
+    #[ink(event)]
+    pub struct PendingRedemptionAvailable {
+        #[ink(topic)]
+        account_id: AccountId,
+        red_points: Balance,
+    }
+
+    #[ink(event)]
+    pub struct USDTRedeemed {
+        #[ink(topic)]
+        account_id: AccountId,
+        #[ink(topic)]
+        redeemed_usdt: Balance,
+    }
+
     // event for creation of green points
     #[ink(event)]
     pub struct GreenPointsTransaction {
@@ -193,6 +473,12 @@ mod d9_merchant_mining {
         consumer: AccountId,
         #[ink(topic)]
         amount: Balance,
+        /// usdt the amm paid for `amount`
+        usdt_received: Balance,
+        /// usdt base units per d9 base unit, scaled by `EFFECTIVE_RATE_PRECISION`
+        effective_rate: Balance,
+        /// d9 kept by the amm as its swap fee, 0 if the amm didn't report its fee
+        amm_fee_estimate: Balance,
     }
     #[ink(event)]
     pub struct USDTMerchantPaymentSent {
@@ -213,6 +499,20 @@ mod d9_merchant_mining {
         amount: Balance,
     }
 
+    #[ink(event)]
+    pub struct MerchantShareChanged {
+        merchant_share_permille: u32,
+    }
+
+    /// emitted when the usdt contract delivered less than the requested amount
+    #[ink(event)]
+    pub struct FeeOnTransferDetected {
+        #[ink(topic)]
+        sender: AccountId,
+        requested: Balance,
+        received: Balance,
+    }
+
     // a struct associated with the GreenPointsTransaction event
     #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
     #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
@@ -238,37 +538,111 @@ mod d9_merchant_mining {
                 accounts: Default::default(),
                 subscription_fee: 1000,
                 milliseconds_day: 86_400_000,
+                migrated_from_legacy: Default::default(),
+                pending_admin: None,
+                // 100x up to 1,000 usdt, 50x up to 10,000 usdt, 10x beyond
+                green_points_curve: vec![(0, 100), (100_000, 50), (1_000_000, 10)],
+                green_points_curve_enabled: false,
+                min_amm_usdt_liquidity: 0,
+                settled_red_points: Default::default(),
+                call_gas_limits: Default::default(),
+                last_reminded_expiry: Default::default(),
+                redeem_delegates: Default::default(),
+                merchant_list: Default::default(),
+                merchant_count: 0,
+                merchant_share_permille: 840,
+                accrual_unit_ms: 0,
+                subscription_payments: Default::default(),
+                subscription_payment_count: Default::default(),
+                total_subscription_revenue: 0,
+                failed_conversions: Default::default(),
+                failed_conversion_count: 0,
+                grant_votes_on_redeem: false,
+                max_points_per_day: 0,
+                max_points_per_merchant_per_day: 0,
+                points_issued_per_day: Default::default(),
+                points_issued_per_merchant_day: Default::default(),
+                total_outstanding_green_points: 0,
+                max_total_green_points: 0,
+                pending_pool_forwards: Default::default(),
+                pending_pool_forward_head: 0,
+                pending_pool_forward_count: 0,
+                payment_receipts: Default::default(),
+                receipt_count: Default::default(),
+                refund_debt: Default::default(),
+                last_activity: Default::default(),
+                tiers: Default::default(),
+                conversion_slippage_permille: 10,
             }
         }
 
         // old main xssaidD9aqTCqsbLn1ncF2gtZyr4MreBXzXT8fquLZfcMrB
-        /// create merchant account subscription
+        /// create merchant account subscription on the `SubscriptionTier` numbered `tier`
         #[ink(message)]
-        pub fn subscribe(&mut self, usdt_amount: Balance) -> Result<Timestamp, Error> {
+        pub fn subscribe(&mut self, usdt_amount: Balance, tier: u8) -> Result<Timestamp, Error> {
             let merchant_id = self.env().caller();
-            if usdt_amount < self.subscription_fee {
-                return Err(Error::InsufficientPayment);
-            }
-            let _ = self.check_subscription_permissibility(merchant_id)?;
+            let monthly_fee = self.check_tier_subscription(merchant_id, usdt_amount, tier)?;
             let _ = self.validate_usdt_transfer(merchant_id, usdt_amount)?;
-            let _ = self.receive_usdt_from_user(merchant_id, usdt_amount)?;
-            let send_usdt_result = self.contract_sends_usdt_to(self.amm_contract, usdt_amount);
-            if send_usdt_result.is_err() {
-                return Err(Error::SendingUSDTToAMM);
-            }
+            let usdt_amount = self.receive_usdt_from_user(merchant_id, usdt_amount)?;
+            // a failed swap reverts the call, which also hands the usdt back to the merchant
+            let d9_amount = self.convert_to_d9(usdt_amount)?;
+            self.call_mining_pool_to_process(merchant_id, d9_amount)?;
 
-            let update_expiry_result = self.update_subscription(merchant_id, usdt_amount);
+            let update_expiry_result =
+                self.update_subscription(merchant_id, usdt_amount, monthly_fee);
 
             update_expiry_result
         }
 
+        /// the tier's monthly fee, once `merchant_id` may subscribe to it with `usdt_amount`
+        fn check_tier_subscription(
+            &self,
+            merchant_id: AccountId,
+            usdt_amount: Balance,
+            tier: u8,
+        ) -> Result<Balance, Error> {
+            let (monthly_fee, point_threshold) =
+                self.get_tier(tier).ok_or(Error::UnknownSubscriptionTier)?;
+            if usdt_amount < monthly_fee {
+                return Err(Error::InsufficientPayment);
+            }
+            self.check_subscription_permissibility(merchant_id, point_threshold)?;
+            Ok(monthly_fee)
+        }
+
+        /// (monthly fee, green point threshold) of `tier`, none for a tier that isn't set up
+        #[ink(message)]
+        pub fn get_tier(&self, tier: u8) -> Option<(Balance, Balance)> {
+            self.tiers.get(tier).or_else(|| {
+                (tier == SubscriptionTier::Basic as u8)
+                    .then_some((self.subscription_fee, DEFAULT_SUBSCRIPTION_POINT_THRESHOLD))
+            })
+        }
+
+        #[ink(message)]
+        pub fn set_tier(
+            &mut self,
+            tier: SubscriptionTier,
+            monthly_fee: Balance,
+            point_threshold: Balance,
+        ) -> Result<(), Error> {
+            self.only_admin()?;
+            if monthly_fee == 0 {
+                return Err(Error::InvalidSubscriptionFee);
+            }
+            self.tiers
+                .insert(tier as u8, &(monthly_fee, point_threshold));
+            Ok(())
+        }
+
         ///create/update subscription, returns new expiry `Timestamp` Result
         fn update_subscription(
             &mut self,
             account_id: AccountId,
             amount: Balance,
+            monthly_fee: Balance,
         ) -> Result<Timestamp, Error> {
-            let months = amount.saturating_div(self.subscription_fee) as Timestamp;
+            let months = amount.saturating_div(monthly_fee) as Timestamp;
             if months == 0 {
                 return Err(Error::InsufficientPayment);
             }
@@ -281,10 +655,16 @@ mod d9_merchant_mining {
                         expiry
                     }
                 }
-                None => self.env().block_timestamp(),
+                None => {
+                    self.merchant_list.insert(self.merchant_count, &account_id);
+                    self.merchant_count = self.merchant_count.saturating_add(1);
+                    self.env().block_timestamp()
+                }
             };
             let new_expiry = current_expiry.saturating_add(months.saturating_mul(one_month));
             self.merchant_expiry.insert(account_id.clone(), &new_expiry);
+            self.touch(account_id);
+            self.record_subscription_payment(account_id, amount, new_expiry);
             self.env().emit_event(SubscriptionExtended {
                 account_id,
                 usdt: amount,
@@ -293,44 +673,228 @@ mod d9_merchant_mining {
             Ok(new_expiry)
         }
 
+        fn record_subscription_payment(
+            &mut self,
+            merchant: AccountId,
+            amount: Balance,
+            resulting_expiry: Timestamp,
+        ) {
+            let index = self.get_subscription_payment_count(merchant);
+            self.subscription_payments.insert(
+                (merchant, index),
+                &(amount, self.env().block_timestamp(), resulting_expiry),
+            );
+            self.subscription_payment_count
+                .insert(merchant, &index.saturating_add(1));
+            self.total_subscription_revenue =
+                self.total_subscription_revenue.saturating_add(amount);
+        }
+
+        #[ink(message)]
+        pub fn get_subscription_payment_count(&self, merchant: AccountId) -> u32 {
+            self.subscription_payment_count.get(&merchant).unwrap_or(0)
+        }
+
+        /// (amount, paid_at, resulting_expiry) of `merchant`'s payments from index `start`, oldest
+        /// first. `limit` is capped at `MAX_SUBSCRIPTION_PAYMENT_PAGE`
+        #[ink(message)]
+        pub fn get_subscription_payments(
+            &self,
+            merchant: AccountId,
+            start: u32,
+            limit: u32,
+        ) -> Vec<(Balance, Timestamp, Timestamp)> {
+            let end = start
+                .saturating_add(limit.min(MAX_SUBSCRIPTION_PAYMENT_PAGE))
+                .min(self.get_subscription_payment_count(merchant));
+            (start..end)
+                .filter_map(|index| self.subscription_payments.get((merchant, index)))
+                .collect()
+        }
+
+        /// usdt received in subscription payments since this was introduced
+        #[ink(message)]
+        pub fn get_total_subscription_revenue(&self) -> Balance {
+            self.total_subscription_revenue
+        }
+
         ///withdraw a certain amount of d9 that has been converted into red points
         #[ink(message)]
         pub fn redeem_d9(&mut self) -> Result<Balance, Error> {
-            //get account
+            self.redeem(Currency::D9)
+        }
+
+        ///withdraw red points in the chosen currency
+        ///
+        /// this is the only way red points are paid out. payments that grant green points settle
+        /// the red points accrued so far and emit `PendingRedemptionAvailable` instead of redeeming
+        #[ink(message)]
+        pub fn redeem(&mut self, currency: Currency) -> Result<Balance, Error> {
+            let caller = self.env().caller();
+            self.redeem_for_account(caller, currency)
+        }
+
+        /// redeem d9 on behalf of `beneficiary`, who receives the d9. the caller must be an
+        /// approved delegate of `beneficiary`
+        #[ink(message)]
+        pub fn redeem_d9_for(&mut self, beneficiary: AccountId) -> Result<Balance, Error> {
+            let caller = self.env().caller();
+            if !self.is_redeem_delegate(beneficiary, caller) {
+                return Err(Error::NotRedeemDelegate);
+            }
+            self.redeem_for_account(beneficiary, Currency::D9)
+        }
+
+        /// allow or disallow `delegate` to call `redeem_d9_for` for the caller
+        #[ink(message)]
+        pub fn approve_redeem_delegate(&mut self, delegate: AccountId, approved: bool) {
             let caller = self.env().caller();
+            self.touch(caller);
+            if approved {
+                self.redeem_delegates.insert((caller, delegate), &true);
+            } else {
+                self.redeem_delegates.remove((caller, delegate));
+            }
+        }
+
+        #[ink(message)]
+        pub fn is_redeem_delegate(&self, beneficiary: AccountId, delegate: AccountId) -> bool {
+            self.redeem_delegates
+                .get((beneficiary, delegate))
+                .unwrap_or(false)
+        }
+
+        fn redeem_for_account(
+            &mut self,
+            caller: AccountId,
+            currency: Currency,
+        ) -> Result<Balance, Error> {
+            //get account
             let maybe_account = self.accounts.get(&caller);
             if maybe_account.is_none() {
                 return Err(Error::NoAccountFound);
             }
             let mut account = maybe_account.unwrap();
+            let redeemable_red_points = self.prepare_redemption(caller, &account)?;
+            let disburse_result =
+                self.disburse(caller, &mut account, redeemable_red_points, currency);
+            self.accounts.insert(caller, &account);
+            return disburse_result;
+        }
+
+        /// red points `redeem` would pay out for `account`
+        fn prepare_redemption(
+            &self,
+            account_id: AccountId,
+            account: &Account,
+        ) -> Result<Balance, Error> {
             if account.green_points == 0 {
                 return Err(Error::NothingToRedeem);
             }
-            let redeemable_red_points = self.calc_total_redeemable_red_points(&account);
+            let redeemable_red_points = self.calc_total_redeemable_red_points(account_id, account);
             if redeemable_red_points == 0 {
                 return Err(Error::NothingToRedeem);
             }
-            let is_within_24_hr_lockout = match account.last_conversion {
+            if self.is_within_redeem_lockout(account) {
+                return Err(Error::NothingToRedeem);
+            }
+            Ok(redeemable_red_points)
+        }
+
+        fn red_points_to_usdt(red_points: Balance) -> Balance {
+            red_points.saturating_div(100)
+        }
+
+        #[ink(message)]
+        pub fn get_redeemable_red_points(&self, account_id: AccountId) -> Balance {
+            match self.accounts.get(&account_id) {
+                Some(account) => self.calc_total_redeemable_red_points(account_id, &account),
+                None => 0,
+            }
+        }
+
+        /// d9 `account_id` would receive from `redeem_d9` right now
+        #[ink(message)]
+        pub fn get_redeemable_d9(&self, account_id: AccountId) -> Result<RedeemPreview, Error> {
+            let account = self
+                .accounts
+                .get(&account_id)
+                .ok_or(Error::NoAccountFound)?;
+            let redeemable_red_points = self.prepare_redemption(account_id, &account)?;
+            self.mining_pool_preview_redeem(Self::red_points_to_usdt(redeemable_red_points))
+        }
+
+        fn is_within_redeem_lockout(&self, account: &Account) -> bool {
+            match account.last_conversion {
                 Some(last_conversion) => {
-                    let twenty_four_hours_prior =
-                        self.env().block_timestamp().saturating_sub(86_400_000);
-                    twenty_four_hours_prior < last_conversion
+                    let one_day_prior = self
+                        .env()
+                        .block_timestamp()
+                        .saturating_sub(self.milliseconds_day);
+                    one_day_prior < last_conversion
                 }
                 None => false,
-            };
-            if is_within_24_hr_lockout {
-                return Err(Error::NothingToRedeem);
             }
-            let disburse_result = self.disburse_d9(caller, &mut account, redeemable_red_points);
-            self.accounts.insert(caller, &account);
-            return disburse_result;
+        }
+
+        fn redeem_lockout_remaining(&self, account: &Account) -> Timestamp {
+            match account.last_conversion {
+                Some(last_conversion) if self.is_within_redeem_lockout(account) => last_conversion
+                    .saturating_add(self.milliseconds_day)
+                    .saturating_sub(self.env().block_timestamp()),
+                _ => 0,
+            }
+        }
+
+        /// (settled red points, timestamp from which the current green points accrue)
+        fn red_point_checkpoint(
+            &self,
+            account_id: AccountId,
+            account: &Account,
+        ) -> (Balance, Timestamp) {
+            self.settled_red_points
+                .get(&account_id)
+                .unwrap_or((0, account.last_conversion.unwrap_or(account.created_at)))
+        }
+
+        fn calc_time_based_red_points(&self, account_id: AccountId, account: &Account) -> Balance {
+            let (settled_red_points, accrual_start) =
+                self.red_point_checkpoint(account_id, account);
+            settled_red_points
+                .saturating_add(self.calc_red_points_from_time(account.green_points, accrual_start))
+        }
+
+        /// lock in the red points accrued so far, so green points added afterwards only accrue
+        /// from now on. the checkpoint moves by whole accrual units to keep partial units
+        fn settle_red_points(&mut self, account_id: AccountId, account: &Account) {
+            let (settled_red_points, accrual_start) =
+                self.red_point_checkpoint(account_id, account);
+            let accrued_red_points =
+                self.calc_red_points_from_time(account.green_points, accrual_start);
+            let accrual_unit = self.accrual_unit();
+            let whole_units = self
+                .env()
+                .block_timestamp()
+                .saturating_sub(accrual_start)
+                .saturating_div(accrual_unit);
+            let new_accrual_start =
+                accrual_start.saturating_add(whole_units.saturating_mul(accrual_unit));
+            self.settled_red_points.insert(
+                account_id,
+                &(
+                    settled_red_points.saturating_add(accrued_red_points),
+                    new_accrual_start,
+                ),
+            );
         }
 
         /// total redeemable red points will never be more than account's remaining green points
-        fn calc_total_redeemable_red_points(&self, account: &Account) -> Balance {
-            let last_redeem_timestamp = account.last_conversion.unwrap_or(account.created_at);
-            let time_based_red_points =
-                self.calc_red_points_from_time(account.green_points, last_redeem_timestamp);
+        fn calc_total_redeemable_red_points(
+            &self,
+            account_id: AccountId,
+            account: &Account,
+        ) -> Balance {
+            let time_based_red_points = self.calc_time_based_red_points(account_id, account);
             let relationship_based_red_points =
                 self.calc_red_points_from_relationships(account.relationship_factors);
             let total_red_points =
@@ -345,42 +909,105 @@ mod d9_merchant_mining {
             redeemable_red_points
         }
 
-        fn disburse_d9(
+        fn disburse(
             &mut self,
             recipient_id: AccountId,
             account: &mut Account,
             redeemable_red_points: Balance,
+            currency: Currency,
         ) -> Result<Balance, Error> {
-            //calculated red points => d9 conversion
-            let redeemable_usdt = redeemable_red_points.saturating_div(100);
-            let redeem_result = self.mining_pool_redeem(recipient_id, redeemable_usdt);
-            if redeem_result.is_err() {
-                return Err(Error::RedeemD9TransferFailed);
-            }
-            let d9_amount = redeem_result.unwrap();
+            //calculated red points => usdt value
+            let redeemable_usdt = Self::red_points_to_usdt(redeemable_red_points);
+            let amount = match currency {
+                Currency::D9 => {
+                    let redeem_result = self.mining_pool_redeem(recipient_id, redeemable_usdt);
+                    if redeem_result.is_err() {
+                        return Err(Error::RedeemD9TransferFailed);
+                    }
+                    let d9_amount = redeem_result.unwrap();
+                    self.grant_redeem_votes(recipient_id, d9_amount);
+                    d9_amount
+                }
+                Currency::USDT => self.disburse_usdt(recipient_id, redeemable_usdt)?,
+            };
             //update account
-            account.redeemed_d9 = account.redeemed_d9.saturating_add(d9_amount);
+            Self::credit_redemption(account, currency, amount);
 
             account.relationship_factors = (0, 0);
 
             //attempt to pay ancestors
             //calculate green => red points conversion
-            let last_redeem_timestamp = account.last_conversion.unwrap_or(account.created_at);
-            let time_based_red_points =
-                self.calc_red_points_from_time(account.green_points, last_redeem_timestamp);
+            let time_based_red_points = self.calc_time_based_red_points(recipient_id, account);
             if let Some(ancestors) = self.get_ancestors(recipient_id) {
-                let _ = self.update_ancestors_coefficients(&ancestors, time_based_red_points);
+                self.update_ancestors_coefficients(recipient_id, &ancestors, time_based_red_points);
             }
 
             account.last_conversion = Some(self.env().block_timestamp());
+            self.touch(recipient_id);
+            self.settled_red_points.remove(&recipient_id);
             account.green_points = account.green_points.saturating_sub(redeemable_red_points);
+            self.total_outstanding_green_points = self
+                .total_outstanding_green_points
+                .saturating_sub(redeemable_red_points);
 
-            self.env().emit_event(D9Redeemed {
-                account_id: recipient_id,
-                redeemed_d9: d9_amount,
-            });
+            match currency {
+                Currency::D9 => self.env().emit_event(D9Redeemed {
+                    account_id: recipient_id,
+                    redeemed_d9: amount,
+                }),
+                Currency::USDT => self.env().emit_event(USDTRedeemed {
+                    account_id: recipient_id,
+                    redeemed_usdt: amount,
+                }),
+            }
 
-            Ok(d9_amount)
+            Ok(amount)
+        }
+
+        /// votes for redeemed d9 at the mining pool's merchant rate, one per whole d9.
+        /// a failed grant doesn't fail the redemption. returns whether votes were requested
+        fn grant_redeem_votes(&self, account_id: AccountId, d9_amount: Balance) -> bool {
+            let votes = Self::calc_votes_from_d9(d9_amount);
+            if !self.grant_votes_on_redeem || votes == 0 {
+                return false;
+            }
+            let _ = self
+                .env()
+                .extension()
+                .add_voting_interests(account_id, votes);
+            true
+        }
+
+        fn calc_votes_from_d9(d9_amount: Balance) -> u64 {
+            let one_d9: Balance = 1_000_000_000_000;
+            d9_amount.saturating_div(one_d9) as u64
+        }
+
+        fn credit_redemption(account: &mut Account, currency: Currency, amount: Balance) {
+            match currency {
+                Currency::D9 => account.redeemed_d9 = account.redeemed_d9.saturating_add(amount),
+                Currency::USDT => {
+                    account.redeemed_usdt = account.redeemed_usdt.saturating_add(amount)
+                }
+            }
+        }
+
+        /// pull the d9 from the mining pool into this contract, swap it at the amm and pass on the usdt
+        fn disburse_usdt(
+            &mut self,
+            recipient_id: AccountId,
+            redeemable_usdt: Balance,
+        ) -> Result<Balance, Error> {
+            let redeem_result = self.mining_pool_redeem(self.env().account_id(), redeemable_usdt);
+            if redeem_result.is_err() {
+                return Err(Error::RedeemUSDTTransferFailed);
+            }
+            let usdt_amount = self
+                .convert_to_usdt(redeem_result.unwrap())
+                .map_err(|_| Error::GettingUSDTFromAMM)?;
+            self.contract_sends_usdt_to(recipient_id, usdt_amount)
+                .map_err(|_| Error::RedeemUSDTTransferFailed)?;
+            Ok(usdt_amount)
         }
 
         fn mining_pool_redeem(
@@ -390,7 +1017,7 @@ mod d9_merchant_mining {
         ) -> Result<Balance, Error> {
             let result = build_call::<D9Environment>()
                 .call(self.mining_pool)
-                .gas_limit(0)
+                .gas_limit(self.get_call_gas_limit(CallTarget::MiningPool))
                 .exec_input(
                     ExecutionInput::new(Selector::new(selector_bytes!("merchant_user_redeem_d9")))
                         .push_arg(user_account)
@@ -401,22 +1028,68 @@ mod d9_merchant_mining {
             result.unwrap()
         }
 
+        fn mining_pool_preview_redeem(
+            &self,
+            redeemable_usdt: Balance,
+        ) -> Result<RedeemPreview, Error> {
+            let result = build_call::<D9Environment>()
+                .call(self.mining_pool)
+                .gas_limit(self.get_call_gas_limit(CallTarget::MiningPool))
+                .exec_input(
+                    ExecutionInput::new(Selector::new(selector_bytes!("preview_redeem_d9")))
+                        .push_arg(redeemable_usdt),
+                )
+                .returns::<Result<RedeemPreview, Error>>()
+                .try_invoke()?
+                .map_err(|_| Error::ErrorGettingEstimate)?;
+            // the pool's error codes don't line up with ours
+            result.map_err(|_| Error::ErrorGettingEstimate)
+        }
+
         #[ink(message, payable)]
         pub fn give_green_points_d9(
             &mut self,
             consumer_id: AccountId,
+        ) -> Result<GreenPointsResult, Error> {
+            self.give_green_points_d9_internal(consumer_id, None)
+        }
+
+        /// like `give_green_points_d9`, but fails with `AmmFeeChanged` unless the amm still
+        /// charges `expected_fee_percent` when the d9 is priced
+        #[ink(message, payable)]
+        pub fn give_green_points_d9_with_fee(
+            &mut self,
+            consumer_id: AccountId,
+            expected_fee_percent: u32,
+        ) -> Result<GreenPointsResult, Error> {
+            self.give_green_points_d9_internal(consumer_id, Some(expected_fee_percent))
+        }
+
+        fn give_green_points_d9_internal(
+            &mut self,
+            consumer_id: AccountId,
+            expected_fee_percent: Option<u32>,
         ) -> Result<GreenPointsResult, Error> {
             let merchant_id = self.env().caller();
             self.validate_merchant(merchant_id)?;
+            Self::reject_self_dealing(merchant_id, consumer_id)?;
+            self.ensure_amm_liquidity()?;
             let d9_amount = self.env().transferred_value();
-            let usdt_amount = self.estimate_usdt(d9_amount)?;
+            let usdt_amount = match expected_fee_percent {
+                Some(expected_fee_percent) => {
+                    let (usdt_amount, fee_percent) = self.estimate_usdt_with_fee(d9_amount)?;
+                    Self::check_amm_fee(expected_fee_percent, fee_percent)?;
+                    usdt_amount
+                }
+                None => self.estimate_usdt(d9_amount)?,
+            };
             // Convert to USDT and delegate to give_green_points_internal
             let green_points_result_result =
                 self.give_green_points_internal(consumer_id, usdt_amount);
             if let Err(e) = green_points_result_result {
                 return Err(e);
             }
-            self.call_mining_pool_to_process(merchant_id, d9_amount)?;
+            self.forward_or_defer(merchant_id, d9_amount);
             green_points_result_result
         }
 
@@ -428,18 +1101,18 @@ mod d9_merchant_mining {
         ) -> Result<GreenPointsResult, Error> {
             let merchant_id = self.env().caller();
             self.validate_merchant(merchant_id)?;
+            Self::reject_self_dealing(merchant_id, consumer_id)?;
             self.validate_usdt_transfer(merchant_id, usdt_payment)?;
-            self.receive_usdt_from_user(merchant_id, usdt_payment)?;
+            let usdt_payment = self.receive_usdt_from_user(merchant_id, usdt_payment)?;
 
             // Delegate to give_green_points_internal
             let green_points_result_result =
                 self.give_green_points_internal(consumer_id, usdt_payment);
+            // returning the error reverts the usdt pull above, so there is nothing to refund
             if let Err(e) = green_points_result_result {
-                self.contract_sends_usdt_to(merchant_id, usdt_payment)?;
                 return Err(e);
             }
-            let d9_amount = self.convert_to_d9(usdt_payment)?;
-            self.call_mining_pool_to_process(merchant_id, d9_amount)?;
+            self.convert_and_forward(merchant_id, usdt_payment)?;
             self.env().emit_event(GivePointsUSDT {
                 consumer: consumer_id,
                 merchant: merchant_id,
@@ -453,11 +1126,11 @@ mod d9_merchant_mining {
             consumer_id: AccountId,
             amount: Balance,
         ) -> Result<GreenPointsResult, Error> {
+            Self::reject_self_dealing(self.env().caller(), consumer_id)?;
             // Calculate green points
-            let usdt_amount_to_green = amount.saturating_mul(100).saturating_div(16);
-            let consumer_green_points = self.calculate_green_points(usdt_amount_to_green);
-            let merchant_green_points =
-                Perbill::from_rational(16u32, 100u32).mul_floor(consumer_green_points);
+            let (consumer_green_points, merchant_green_points) =
+                self.calc_granted_green_points(amount);
+            self.record_points_velocity(consumer_id, self.env().caller(), consumer_green_points)?;
 
             // Update accounts
             let add_consumer_points_result =
@@ -483,8 +1156,10 @@ mod d9_merchant_mining {
             });
 
             Ok(GreenPointsResult {
+                result_version: GREEN_POINTS_RESULT_VERSION,
                 merchant: merchant_green_points,
                 consumer: consumer_green_points,
+                usdt_received: None,
             })
         }
 
@@ -496,8 +1171,9 @@ mod d9_merchant_mining {
         ) -> Result<GreenPointsResult, Error> {
             let consumer_id = self.env().caller();
             let _ = self.validate_merchant(merchant_id)?;
+            Self::reject_self_dealing(merchant_id, consumer_id)?;
             let _ = self.validate_usdt_transfer(consumer_id, usdt_amount)?;
-            let _ = self.receive_usdt_from_user(consumer_id, usdt_amount);
+            let usdt_amount = self.receive_usdt_from_user(consumer_id, usdt_amount)?;
             self.env().emit_event(USDTMerchantPaymentSent {
                 merchant: merchant_id,
                 consumer: consumer_id,
@@ -519,6 +1195,8 @@ mod d9_merchant_mining {
             if let Err(e) = validate_merchant {
                 return Err(e);
             }
+            Self::reject_self_dealing(merchant_id, payer)?;
+            self.ensure_amm_liquidity()?;
 
             //convert to usdt
             let conversion_result = self.convert_to_usdt(d9_amount);
@@ -529,12 +1207,76 @@ mod d9_merchant_mining {
             //process payments
             let usdt_amount = conversion_result.unwrap();
 
-            self.env().emit_event(D9MerchantPaymentSent {
-                merchant: merchant_id,
-                consumer: payer,
-                amount: d9_amount,
-            });
-            self.finish_processing_payment(payer, merchant_id, usdt_amount)
+            let amm_fee_percent = self.get_amm_fee_percent();
+            self.emit_d9_payment(merchant_id, payer, d9_amount, usdt_amount, amm_fee_percent);
+            let mut green_points_result =
+                self.finish_processing_payment(payer, merchant_id, usdt_amount)?;
+            green_points_result.usdt_received = Some(usdt_amount);
+            Ok(green_points_result)
+        }
+
+        fn emit_d9_payment(
+            &self,
+            merchant: AccountId,
+            consumer: AccountId,
+            d9_amount: Balance,
+            usdt_received: Balance,
+            amm_fee_percent: u32,
+        ) {
+            let (effective_rate, amm_fee_estimate) =
+                Self::calc_d9_payment_figures(d9_amount, usdt_received, amm_fee_percent);
+            self.env().emit_event(D9MerchantPaymentSent {
+                merchant,
+                consumer,
+                amount: d9_amount,
+                usdt_received,
+                effective_rate,
+                amm_fee_estimate,
+            });
+        }
+
+        /// (usdt per d9 scaled by `EFFECTIVE_RATE_PRECISION`, d9 taken as the amm fee). the amm
+        /// takes its fee from the d9 input before pricing
+        fn calc_d9_payment_figures(
+            d9_amount: Balance,
+            usdt_received: Balance,
+            amm_fee_percent: u32,
+        ) -> (Balance, Balance) {
+            let effective_rate = multiply_by_rational_with_rounding(
+                usdt_received,
+                EFFECTIVE_RATE_PRECISION,
+                d9_amount,
+                Rounding::Down,
+            )
+            .unwrap_or(0);
+            let amm_fee_estimate = Perbill::from_percent(amm_fee_percent).mul_floor(d9_amount);
+            (effective_rate, amm_fee_estimate)
+        }
+
+        /// swap fee of the amm in percent, 0 when it can't be read
+        fn get_amm_fee_percent(&self) -> u32 {
+            self.read_amm_fee_percent().unwrap_or(0)
+        }
+
+        fn read_amm_fee_percent(&self) -> Result<u32, Error> {
+            let result = build_call::<D9Environment>()
+                .call(self.amm_contract)
+                .gas_limit(self.get_call_gas_limit(CallTarget::Amm))
+                .exec_input(ExecutionInput::new(Selector::new(selector_bytes!(
+                    "get_fee_percent"
+                ))))
+                .returns::<u32>()
+                .try_invoke()?;
+            result.map_err(|_| Error::CrossContractCallErrorGettingEstimate)
+        }
+
+        /// the estimate of `estimate_usdt` already has the swap fee taken out, so it only holds
+        /// while the amm's fee is the one the caller priced the payment with
+        fn check_amm_fee(expected_fee_percent: u32, fee_percent: u32) -> Result<(), Error> {
+            if fee_percent != expected_fee_percent {
+                return Err(Error::AmmFeeChanged);
+            }
+            Ok(())
         }
 
         fn finish_processing_payment(
@@ -544,545 +1286,2902 @@ mod d9_merchant_mining {
             usdt_amount: Balance,
         ) -> Result<GreenPointsResult, Error> {
             //send usdt to merchant
-            let eighty_four_percent = Perbill::from_rational(84u32, 100u32);
-            let merchant_payment = eighty_four_percent.mul_floor(usdt_amount);
+            let (merchant_payment, merchant_usdt_to_green) = self.split_payment(usdt_amount);
 
             let send_usdt_result = self.contract_sends_usdt_to(merchant_id, merchant_payment);
             if send_usdt_result.is_err() {
                 return Err(Error::SendUSDTToMerchant);
             }
 
-            //process green points
-            let merchant_usdt_to_green = usdt_amount.saturating_sub(merchant_payment);
-            let merchant_green_points = self.calculate_green_points(merchant_usdt_to_green);
-            let consumer_green_points = self.calculate_green_points(usdt_amount);
-            //update accounts
-            let add_merchant_points_result =
-                self.add_green_points(merchant_id, merchant_green_points, false);
-            if let Err(e) = add_merchant_points_result {
-                return Err(e);
-            }
-            let add_consumer_points_result =
-                self.add_green_points(consumer_id, consumer_green_points, true);
-            if let Err(e) = add_consumer_points_result {
-                return Err(e);
-            }
+            let green_points_result =
+                self.mint_payment_points(consumer_id, merchant_id, usdt_amount)?;
 
-            // convert usdt to d9
-            let conversion_result = self.convert_to_d9(merchant_usdt_to_green);
-            if let Err(e) = conversion_result {
-                return Err(e);
-            }
-            let d9_amount = conversion_result.unwrap();
+            // convert usdt to d9 and send to mining pool
+            self.convert_and_forward(merchant_id, merchant_usdt_to_green)?;
 
-            //send to mining pool
-            let _ = self.call_mining_pool_to_process(merchant_id, d9_amount)?;
+            self.record_receipt(merchant_id, consumer_id, usdt_amount);
 
             // self.credit_pool(d9_amount);
             self.env().emit_event(GreenPointsTransaction {
                 merchant: GreenPointsCreated {
                     account_id: merchant_id,
-                    green_points: merchant_green_points,
+                    green_points: green_points_result.merchant,
                 },
                 consumer: GreenPointsCreated {
                     account_id: consumer_id,
-                    green_points: consumer_green_points,
+                    green_points: green_points_result.consumer,
                 },
             });
 
-            Ok(GreenPointsResult {
-                merchant: merchant_green_points,
-                consumer: consumer_green_points,
-            })
+            Ok(green_points_result)
         }
 
+        /// green points both parties would earn from a `usdt_amount` payment right now. nothing
+        /// is transferred or stored
         #[ink(message)]
-        pub fn get_expiry(&self, account_id: AccountId) -> Result<Timestamp, Error> {
-            let expiry = self.merchant_expiry.get(&account_id);
-            match expiry {
-                Some(expiry) => Ok(expiry),
-                None => Err(Error::NoMerchantAccountFound),
+        pub fn preview_payment_points(&self, usdt_amount: Balance) -> GreenPointsResult {
+            let (consumer_green_points, merchant_green_points) =
+                self.calc_payment_points(usdt_amount);
+            GreenPointsResult {
+                result_version: GREEN_POINTS_RESULT_VERSION,
+                merchant: merchant_green_points,
+                consumer: consumer_green_points,
+                usdt_received: None,
             }
         }
 
-        #[ink(message)]
-        /// get account details
-        pub fn get_account(&self, account_id: AccountId) -> Option<Account> {
-            self.accounts.get(&account_id)
+        /// (consumer, merchant) green points of a payment. the curve is applied to the whole
+        /// payment, the merchant earns the share of it that isn't paid out to them
+        fn calc_payment_points(&self, usdt_amount: Balance) -> (Balance, Balance) {
+            let funding_permille = 1000u32.saturating_sub(self.merchant_share_permille);
+            let consumer_green_points = self.calculate_green_points(usdt_amount);
+            let merchant_green_points =
+                Perbill::from_rational(funding_permille, 1000).mul_floor(consumer_green_points);
+            (consumer_green_points, merchant_green_points)
         }
 
-        #[ink(message)]
-        pub fn change_amm_contract(&mut self, new_amm_contract: AccountId) -> Result<(), Error> {
-            self.only_admin()?;
-            self.amm_contract = new_amm_contract;
-            Ok(())
+        /// credit the green points of a payment to both parties
+        fn mint_payment_points(
+            &mut self,
+            consumer_id: AccountId,
+            merchant_id: AccountId,
+            usdt_amount: Balance,
+        ) -> Result<GreenPointsResult, Error> {
+            let green_points_result = self.preview_payment_points(usdt_amount);
+            self.record_points_velocity(consumer_id, merchant_id, green_points_result.consumer)?;
+            self.add_green_points(merchant_id, green_points_result.merchant, false)?;
+            self.add_green_points(consumer_id, green_points_result.consumer, true)?;
+            Ok(green_points_result)
+        }
+
+        fn record_receipt(&mut self, merchant: AccountId, consumer: AccountId, usdt: Balance) {
+            let receipt_seq = self.receipt_count.get(merchant).unwrap_or(0);
+            self.payment_receipts
+                .insert((merchant, receipt_seq), &(consumer, usdt, 0));
+            self.receipt_count
+                .insert(merchant, &receipt_seq.saturating_add(1));
+            self.env().emit_event(PaymentReceiptIssued {
+                merchant,
+                consumer,
+                receipt_seq,
+                usdt,
+            });
         }
 
+        /// (consumer, usdt paid, usdt refunded so far) of a payment to `merchant`
         #[ink(message)]
-        pub fn change_mining_pool(&mut self, new_mining_pool: AccountId) -> Result<(), Error> {
-            self.only_admin()?;
-            self.mining_pool = new_mining_pool;
-            Ok(())
+        pub fn get_receipt(
+            &self,
+            merchant: AccountId,
+            receipt_seq: u32,
+        ) -> Option<(AccountId, Balance, Balance)> {
+            self.payment_receipts.get((merchant, receipt_seq))
         }
 
         #[ink(message)]
-        pub fn get_mining_pool(&self) -> AccountId {
-            self.mining_pool
+        pub fn get_refund_debt(&self, account_id: AccountId) -> Balance {
+            self.refund_debt.get(account_id).unwrap_or(0)
         }
 
-        /// Modifies the code which is used to execute calls to this contract address (`AccountId`).
-        ///
-        /// We use this to upgrade the contract logic. We don't do any authorization here, any caller
-        /// can execute this method. In a production contract you would do some authorization here.
+        /// refund `usdt_amount` of a payment to the caller. the green points the payment
+        /// issued at the current multiplier are taken back from both sides, and the caller's
+        /// share of the amount is pulled from them and sent to `consumer`, so the caller
+        /// has to approve it first. with `receipt_seq` the refund is checked against what is
+        /// left of that payment
         #[ink(message)]
-        pub fn set_code(&mut self, code_hash: [u8; 32]) {
-            let caller = self.env().caller();
-            assert!(caller == self.admin, "Only admin can set code hash.");
-            ink::env::set_code_hash(&code_hash).unwrap_or_else(|err| {
-                panic!(
-                    "Failed to `set_code_hash` to {:?} due to {:?}",
-                    code_hash, err
-                )
+        pub fn refund_payment(
+            &mut self,
+            consumer: AccountId,
+            usdt_amount: Balance,
+            receipt_seq: Option<u32>,
+        ) -> Result<(), Error> {
+            let merchant = self.env().caller();
+            self.validate_merchant(merchant)?;
+            Self::reject_self_dealing(merchant, consumer)?;
+            let (consumer_points, merchant_points) =
+                self.apply_refund(merchant, consumer, usdt_amount, receipt_seq)?;
+
+            let (merchant_payment, _) = self.split_payment(usdt_amount);
+            self.validate_usdt_transfer(merchant, merchant_payment)?;
+            let received = self.receive_usdt_from_user(merchant, merchant_payment)?;
+            self.contract_sends_usdt_to(consumer, received)?;
+            self.env().emit_event(PaymentRefunded {
+                merchant,
+                consumer,
+                receipt_seq,
+                usdt_refunded: received,
+                consumer_points_clawed_back: consumer_points,
+                merchant_points_clawed_back: merchant_points,
             });
-            ink::env::debug_println!("Switched code hash to {:?}.", code_hash);
+            Ok(())
         }
 
-        fn check_subscription_permissibility(&self, account_id: AccountId) -> Result<(), Error> {
-            let account_option = self.accounts.get(&account_id);
-            if account_option.is_none() {
-                return Err(Error::NoAccountCantCreateMerchantAccount);
+        /// the points side of `refund_payment`. (consumer points, merchant points) clawed back,
+        /// including what was turned into refund debt
+        fn apply_refund(
+            &mut self,
+            merchant: AccountId,
+            consumer: AccountId,
+            usdt_amount: Balance,
+            receipt_seq: Option<u32>,
+        ) -> Result<(Balance, Balance), Error> {
+            if usdt_amount == 0 {
+                return Err(Error::NothingToRefund);
             }
-            let account = account_option.unwrap();
-            let threshold_points: Balance = 100_000_000;
-            if account.green_points < threshold_points {
-                return Err(Error::PointsInsufficientToCreateMerchantAccount);
+            if let Some(receipt_seq) = receipt_seq {
+                let (receipt_consumer, paid, refunded) = self
+                    .payment_receipts
+                    .get((merchant, receipt_seq))
+                    .filter(|(receipt_consumer, _, _)| *receipt_consumer == consumer)
+                    .ok_or(Error::NoReceiptFound)?;
+                let refunded = refunded.saturating_add(usdt_amount);
+                if refunded > paid {
+                    return Err(Error::RefundExceedsPayment);
+                }
+                self.payment_receipts
+                    .insert((merchant, receipt_seq), &(receipt_consumer, paid, refunded));
             }
-            Ok(())
+            let (consumer_points, merchant_points) = self.calc_payment_points(usdt_amount);
+            self.claw_back_green_points(consumer, consumer_points);
+            self.claw_back_green_points(merchant, merchant_points);
+            Ok((consumer_points, merchant_points))
         }
 
-        fn validate_usdt_transfer(&self, account: AccountId, amount: Balance) -> Result<(), Error> {
-            let check_balance_result = self.validate_usdt_balance(account, amount);
-            if check_balance_result.is_err() {
-                return Err(Error::UserUSDTBalanceInsufficient);
+        /// take `points` from the account's green points. whatever it no longer holds, e.g.
+        /// because it was redeemed, becomes refund debt. accrual isn't settled first, so red
+        /// points accrue as if the clawed back points were never held
+        fn claw_back_green_points(&mut self, account_id: AccountId, points: Balance) {
+            let mut deducted = 0;
+            if let Some(mut account) = self.accounts.get(&account_id) {
+                deducted = points.min(account.green_points);
+                account.green_points = account.green_points.saturating_sub(deducted);
+                self.accounts.insert(account_id, &account);
+                self.touch(account_id);
             }
-            let check_allowance_result = self.validate_usdt_allowance(account, amount);
-            if let Err(e) = check_allowance_result {
-                return Err(e);
+            self.total_outstanding_green_points =
+                self.total_outstanding_green_points.saturating_sub(deducted);
+            let shortfall = points.saturating_sub(deducted);
+            if shortfall > 0 {
+                let debt = self.get_refund_debt(account_id).saturating_add(shortfall);
+                self.refund_debt.insert(account_id, &debt);
             }
-            Ok(())
         }
 
-        fn validate_usdt_balance(
-            &self,
-            account_id: AccountId,
-            amount: Balance,
-        ) -> Result<(), Error> {
-            let usdt_balance = build_call::<D9Environment>()
-                .call(self.usdt_contract)
-                .gas_limit(0)
-                .exec_input(
-                    ExecutionInput::new(Selector::new(selector_bytes!("PSP22::balance_of")))
-                        .push_arg(account_id),
-                )
-                .returns::<Balance>()
-                .invoke();
-            if usdt_balance < amount {
-                return Err(Error::UserUSDTBalanceInsufficient);
+        /// points of a grant left after paying down the account's refund debt
+        fn pay_down_refund_debt(&mut self, account_id: AccountId, amount: Balance) -> Balance {
+            let debt = self.get_refund_debt(account_id);
+            if debt == 0 {
+                return amount;
             }
-            Ok(())
+            let repaid = debt.min(amount);
+            let remaining_debt = debt.saturating_sub(repaid);
+            if remaining_debt == 0 {
+                self.refund_debt.remove(account_id);
+            } else {
+                self.refund_debt.insert(account_id, &remaining_debt);
+            }
+            amount.saturating_sub(repaid)
         }
 
-        pub fn validate_usdt_allowance(
-            &self,
-            owner: AccountId,
-            amount: Balance,
+        /// convert `usdt` to d9 and forward it to the mining pool for `merchant_id`. a failed
+        /// conversion doesn't fail the payment, the usdt is held under a payment id instead
+        fn convert_and_forward(
+            &mut self,
+            merchant_id: AccountId,
+            usdt: Balance,
         ) -> Result<(), Error> {
-            let allowance = build_call::<D9Environment>()
-                .call(self.usdt_contract)
-                .gas_limit(0)
-                .exec_input(
-                    ExecutionInput::new(Selector::new(selector_bytes!("PSP22::allowance")))
-                        .push_arg(owner)
-                        .push_arg(self.env().account_id()),
-                )
-                .returns::<Balance>()
-                .invoke();
-            if allowance < amount {
-                return Err(Error::InsufficientAllowance);
+            match self.convert_to_d9(usdt) {
+                Ok(d9_amount) => {
+                    self.forward_or_defer(merchant_id, d9_amount);
+                    Ok(())
+                }
+                Err(_) => {
+                    self.record_failed_conversion(merchant_id, usdt);
+                    Ok(())
+                }
             }
-            Ok(())
         }
 
-        /// make sure it is a valid merchant account and their subscription is not expired
-        fn validate_merchant(&self, account_id: AccountId) -> Result<(), Error> {
-            let merchant_expiry_option: Option<Timestamp> = self.merchant_expiry.get(&account_id);
-            if merchant_expiry_option.is_none() {
-                return Err(Error::NoMerchantAccountFound);
-            }
-            let merchant_expiry = merchant_expiry_option.unwrap();
-            if merchant_expiry < self.env().block_timestamp() {
-                return Err(Error::MerchantAccountExpired);
+        /// a payment doesn't fail with the mining pool, e.g. while it is being upgraded. the d9
+        /// is queued for `flush_pending_forwards` instead
+        fn forward_or_defer(&mut self, merchant_id: AccountId, d9_amount: Balance) {
+            if self
+                .call_mining_pool_to_process(merchant_id, d9_amount)
+                .is_err()
+            {
+                self.defer_pool_forward(merchant_id, d9_amount);
             }
-            Ok(())
         }
 
-        fn convert_to_d9(&mut self, amount: Balance) -> Result<Balance, Error> {
-            let grant_allowance_result = self.grant_amm_allowance(amount);
-            if grant_allowance_result.is_err() {
-                return Err(Error::GrantingAllowanceFailed);
+        fn defer_pool_forward(&mut self, merchant: AccountId, d9: Balance) -> u32 {
+            let index = self.pending_pool_forward_count;
+            self.pending_pool_forward_count = self.pending_pool_forward_count.saturating_add(1);
+            self.pending_pool_forwards.insert(index, &(merchant, d9));
+            self.env().emit_event(PoolForwardDeferred {
+                index,
+                merchant,
+                d9,
+            });
+            index
+        }
+
+        /// retry up to `limit` queued forwards, oldest first. stops at the first one the mining
+        /// pool still refuses. callable by anyone, returns the number delivered
+        #[ink(message)]
+        pub fn flush_pending_forwards(&mut self, limit: u32) -> u32 {
+            self.flush_pending_forwards_with(limit, |contract, merchant, d9| {
+                contract.call_mining_pool_to_process(merchant, d9)
+            })
+        }
+
+        fn flush_pending_forwards_with<F>(&mut self, limit: u32, mut forward: F) -> u32
+        where
+            F: FnMut(&Self, AccountId, Balance) -> Result<(), Error>,
+        {
+            let mut flushed = 0;
+            while flushed < limit
+                && self.pending_pool_forward_head < self.pending_pool_forward_count
+            {
+                let index = self.pending_pool_forward_head;
+                if let Some((merchant, d9)) = self.pending_pool_forwards.get(index) {
+                    if forward(self, merchant, d9).is_err() {
+                        break;
+                    }
+                    self.pending_pool_forwards.remove(index);
+                    self.env().emit_event(PoolForwardFlushed {
+                        index,
+                        merchant,
+                        d9,
+                    });
+                    flushed += 1;
+                }
+                self.pending_pool_forward_head = index.saturating_add(1);
             }
-            let d9_amount = self.amm_get_d9(amount)?;
+            flushed
+        }
 
-            Ok(d9_amount)
+        /// (merchant, d9) queued under `index`
+        #[ink(message)]
+        pub fn get_pending_pool_forward(&self, index: u32) -> Option<(AccountId, Balance)> {
+            self.pending_pool_forwards.get(index)
         }
 
-        fn contract_sends_usdt_to(
-            &self,
-            recipient: AccountId,
-            amount: Balance,
-        ) -> Result<(), Error> {
-            build_call::<D9Environment>()
-                .call(self.usdt_contract)
-                .gas_limit(0)
-                .exec_input(
-                    ExecutionInput::new(Selector::new(selector_bytes!("PSP22::transfer")))
-                        .push_arg(recipient)
-                        .push_arg(amount)
-                        .push_arg([0u8]),
-                )
-                .returns::<Result<(), Error>>()
-                .invoke()
+        /// (oldest queued index, next index), the queue is empty when they are equal
+        #[ink(message)]
+        pub fn get_pending_pool_forward_range(&self) -> (u32, u32) {
+            (
+                self.pending_pool_forward_head,
+                self.pending_pool_forward_count,
+            )
         }
 
-        pub fn receive_usdt_from_user(
-            &self,
-            sender: AccountId,
-            amount: Balance,
-        ) -> Result<(), Error> {
-            build_call::<D9Environment>()
-                .call(self.usdt_contract)
-                .gas_limit(0)
-                .exec_input(
-                    ExecutionInput::new(Selector::new(selector_bytes!("PSP22::transfer_from")))
-                        .push_arg(sender)
-                        .push_arg(self.env().account_id())
-                        .push_arg(amount)
-                        .push_arg([0u8]),
-                )
-                .returns::<Result<(), Error>>()
-                .invoke()
+        fn record_failed_conversion(&mut self, merchant: AccountId, usdt: Balance) -> [u8; 32] {
+            let payment_id = Self::conversion_payment_id(merchant, self.failed_conversion_count);
+            self.failed_conversion_count = self.failed_conversion_count.saturating_add(1);
+            self.failed_conversions
+                .insert(payment_id, &(merchant, usdt));
+            self.env().emit_event(ConversionFailed {
+                payment_id,
+                merchant,
+                usdt,
+            });
+            payment_id
         }
-        //xjyLYnZBRhYYjUKjCp8UiHnmcjHmkPfRSBxTiLLMoEwtzwp
-        //d40a697875ef7a24aaed19ab41e1395675a1d84a5ddbc78a5a342e87c2d580f6
-        //89151c651f568f7ae1f1156c3409d329bd5ccfc0eb9fc29b38b25d8b8bf831fe <- factor fix
 
-        fn grant_amm_allowance(&mut self, amount: Balance) -> Result<(), Error> {
-            let call_result = build_call::<D9Environment>()
-                .call(self.usdt_contract)
-                .gas_limit(0)
-                .exec_input(
-                    ExecutionInput::new(Selector::new(selector_bytes!("PSP22::approve")))
-                        .push_arg(self.amm_contract)
-                        .push_arg(amount),
-                )
-                .returns::<Result<(), Error>>()
-                .try_invoke()?;
-            call_result.unwrap()
+        /// blake2_256 of the SCALE encoded (merchant, nonce)
+        pub fn conversion_payment_id(merchant: AccountId, nonce: u64) -> [u8; 32] {
+            let mut output = <Blake2x256 as HashOutput>::Type::default();
+            hash_encoded::<Blake2x256, _>(&(merchant, nonce), &mut output);
+            output
         }
 
-        ///convert received usdt to d9 which will go to mining pool
-        fn amm_get_d9(&self, amount: Balance) -> Result<Balance, Error> {
-            let call_result = build_call::<D9Environment>()
-                .call(self.amm_contract)
-                .gas_limit(0)
-                .exec_input(
-                    ExecutionInput::new(Selector::new(selector_bytes!("get_d9"))).push_arg(amount),
-                )
-                .returns::<Result<Balance, Error>>()
-                .try_invoke()?;
-            call_result.unwrap()
+        /// (merchant, usdt) held for a failed conversion
+        #[ink(message)]
+        pub fn get_failed_conversion(&self, payment_id: [u8; 32]) -> Option<(AccountId, Balance)> {
+            self.failed_conversions.get(payment_id)
         }
 
-        /// call amm contract to get usdt, which will go to merchant
+        #[ink(message)]
+        pub fn get_failed_conversion_count(&self) -> u64 {
+            self.failed_conversion_count
+        }
 
-        fn convert_to_usdt(&self, amount: Balance) -> Result<Balance, Error> {
-            let result = build_call::<D9Environment>()
-                .call(self.amm_contract)
-                .gas_limit(0)
-                .transferred_value(amount)
-                .exec_input(ExecutionInput::new(Selector::new(selector_bytes!(
-                    "get_usdt"
-                ))))
-                .returns::<Result<Balance, Error>>()
-                .try_invoke()?;
-            result.unwrap()
+        /// convert the usdt held under `payment_id` and forward the d9 to the mining pool.
+        /// callable by the payment's merchant or the admin
+        #[ink(message)]
+        pub fn retry_conversion(&mut self, payment_id: [u8; 32]) -> Result<Balance, Error> {
+            let (merchant_id, usdt) = self
+                .failed_conversions
+                .get(payment_id)
+                .ok_or(Error::NoFailedConversion)?;
+            let caller = self.env().caller();
+            if caller != merchant_id && caller != self.admin {
+                return Err(Error::OnlyMerchantOrAdmin);
+            }
+            self.failed_conversions.remove(payment_id);
+            let d9_amount = self.convert_to_d9(usdt)?;
+            self.call_mining_pool_to_process(merchant_id, d9_amount)?;
+            self.env().emit_event(ConversionRetried {
+                payment_id,
+                merchant: merchant_id,
+                d9: d9_amount,
+            });
+            Ok(d9_amount)
         }
 
-        fn estimate_usdt(&self, amount: Balance) -> Result<Balance, Error> {
-            let direction = Direction(Currency::D9, Currency::USDT);
-            // this result is to catch any error in calling originating from the environment
-            let cross_contract_call_result = build_call::<D9Environment>()
-                .call(self.amm_contract)
-                .gas_limit(0)
-                .exec_input(
-                    ExecutionInput::new(Selector::new(selector_bytes!("estimate_exchange")))
-                        .push_arg(direction)
-                        .push_arg(amount),
-                )
-                .returns::<Result<(Balance, Balance), Error>>()
-                .try_invoke();
-            // this result will return the value or some error from the contract itself
-            if cross_contract_call_result.is_err() {
-                return Err(Error::CrossContractCallErrorGettingEstimate);
-            }
-            let method_call_result = cross_contract_call_result.unwrap();
-            if method_call_result.is_err() {
-                return Err(Error::ErrorGettingEstimate);
-            }
-            let something = method_call_result.unwrap();
-            if something.is_err() {
-                return Err(Error::ErrorGettingEstimate);
+        /// non-expired merchants among `merchant_list[start..start + len]` with their expiry.
+        /// `len` is capped at `MAX_MERCHANT_PAGE`, so a page can hold fewer entries than asked for
+        #[ink(message)]
+        pub fn get_active_merchants_paged(
+            &self,
+            start: u32,
+            len: u32,
+        ) -> Vec<(AccountId, Timestamp)> {
+            let now = self.env().block_timestamp();
+            let end = start
+                .saturating_add(len.min(MAX_MERCHANT_PAGE))
+                .min(self.merchant_count);
+            (start..end)
+                .filter_map(|index| self.merchant_list.get(index))
+                .filter_map(|merchant| {
+                    self.merchant_expiry
+                        .get(merchant)
+                        .filter(|expiry| *expiry >= now)
+                        .map(|expiry| (merchant, expiry))
+                })
+                .collect()
+        }
+
+        #[ink(message)]
+        pub fn get_merchant_count(&self) -> u32 {
+            self.merchant_count
+        }
+
+        #[ink(message)]
+        pub fn get_expiry(&self, account_id: AccountId) -> Result<Timestamp, Error> {
+            let expiry = self.merchant_expiry.get(&account_id);
+            match expiry {
+                Some(expiry) => Ok(expiry),
+                None => Err(Error::NoMerchantAccountFound),
             }
-            let usdt_balance = something.unwrap().1;
-            Ok(usdt_balance)
         }
 
-        /// function to restrict access to admin
-        fn only_admin(&self) -> Result<(), Error> {
-            let caller = self.env().caller();
-            if caller != self.admin {
-                return Err(Error::OnlyAdmin);
+        /// emit `ExpiryReminder` for merchants expiring within 7 days, once per expiry value.
+        /// anyone can call it, at most 50 accounts per call
+        #[ink(message)]
+        pub fn emit_expiry_reminders(&mut self, accounts: Vec<AccountId>) -> Result<(), Error> {
+            if accounts.len() > 50 {
+                return Err(Error::ReminderBatchTooLarge);
+            }
+            let now = self.env().block_timestamp();
+            let horizon = now.saturating_add(self.milliseconds_day.saturating_mul(7));
+            for merchant in accounts {
+                let Some(expiry) = self.merchant_expiry.get(&merchant) else {
+                    continue;
+                };
+                if expiry < now || expiry > horizon {
+                    continue;
+                }
+                if self.last_reminded_expiry.get(&merchant) == Some(expiry) {
+                    continue;
+                }
+                self.last_reminded_expiry.insert(merchant, &expiry);
+                self.env().emit_event(ExpiryReminder { merchant, expiry });
             }
             Ok(())
         }
 
         #[ink(message)]
-        pub fn change_admin(&mut self, new_admin: AccountId) -> Result<(), Error> {
-            self.only_admin()?;
-            self.admin = new_admin;
-            Ok(())
+        /// get account details
+        pub fn get_account(&self, account_id: AccountId) -> Option<Account> {
+            self.accounts.get(&account_id)
         }
 
-        ///get green points from usdt amount
-        fn calculate_green_points(&self, amount: Balance) -> Balance {
-            amount.saturating_mul(100)
+        /// like `get_account`, with a fresh zeroed account for unknown ids
+        #[ink(message)]
+        pub fn get_account_or_default(&self, account_id: AccountId) -> Account {
+            self.accounts
+                .get(&account_id)
+                .unwrap_or_else(|| Account::new(self.env().block_timestamp()))
         }
 
-        /// base rate calculation is based on time.acceleration is based on ancestors
+        /// `get_account` plus the redemption and merchant figures derived from it
+        #[ink(message)]
+        pub fn get_account_v2(&self, account_id: AccountId) -> Option<AccountView> {
+            let account = self.accounts.get(&account_id)?;
+            let redeemable_red_points = self.calc_total_redeemable_red_points(account_id, &account);
+            let merchant_expiry = self.merchant_expiry.get(&account_id);
+            let now = self.env().block_timestamp();
+            Some(AccountView {
+                view_version: ACCOUNT_VIEW_VERSION,
+                redeemable_red_points,
+                redeemable_usdt: Self::red_points_to_usdt(redeemable_red_points),
+                lockout_remaining: self.redeem_lockout_remaining(&account),
+                merchant_expiry,
+                is_active_merchant: merchant_expiry.map_or(false, |expiry| expiry >= now),
+                account,
+            })
+        }
+
+        /// account data in the shape expected by `import_legacy_account`
         ///
-        /// 1 red point = 1 green point
-        fn calc_red_points_from_time(
+        /// (account_id, green_points, created_at, last_conversion)
+        #[ink(message)]
+        pub fn export_account(
             &self,
+            account_id: AccountId,
+        ) -> Option<(AccountId, Balance, Timestamp, Option<Timestamp>)> {
+            self.accounts.get(&account_id).map(|account| {
+                (
+                    account_id,
+                    account.green_points,
+                    account.created_at,
+                    account.last_conversion,
+                )
+            })
+        }
+
+        /// create an account from data exported by the legacy merchant mining contract
+        #[ink(message)]
+        pub fn import_legacy_account(
+            &mut self,
+            account_id: AccountId,
             green_points: Balance,
-            last_redeem_timestamp: Timestamp,
-        ) -> Balance {
-            // rate green points => red points
-            let transmutation_rate = Perbill::from_rational(1u32, 2000u32);
+            created_at: Timestamp,
+            last_conversion: Option<Timestamp>,
+        ) -> Result<(), Error> {
+            self.only_admin()?;
+            self.import_account(account_id, green_points, created_at, last_conversion)
+        }
 
-            let days_since_last_redeem =
-                self.env()
-                    .block_timestamp()
-                    .saturating_sub(last_redeem_timestamp)
-                    .saturating_div(self.milliseconds_day) as Balance;
+        /// batched `import_legacy_account`, at most 25 accounts per call
+        #[ink(message)]
+        pub fn import_legacy_accounts(
+            &mut self,
+            legacy_accounts: Vec<(AccountId, Balance, Timestamp, Option<Timestamp>)>,
+        ) -> Result<(), Error> {
+            self.only_admin()?;
+            if legacy_accounts.len() > 25 {
+                return Err(Error::ImportBatchTooLarge);
+            }
+            for (account_id, green_points, created_at, last_conversion) in legacy_accounts {
+                self.import_account(account_id, green_points, created_at, last_conversion)?;
+            }
+            Ok(())
+        }
 
-            let base_red_points = transmutation_rate
-                .mul_floor(green_points)
-                .saturating_mul(days_since_last_redeem);
+        #[ink(message)]
+        pub fn is_migrated_from_legacy(&self, account_id: AccountId) -> bool {
+            self.migrated_from_legacy.contains(&account_id)
+        }
 
-            base_red_points
+        fn import_account(
+            &mut self,
+            account_id: AccountId,
+            green_points: Balance,
+            created_at: Timestamp,
+            last_conversion: Option<Timestamp>,
+        ) -> Result<(), Error> {
+            if self.accounts.contains(&account_id)
+                || self.migrated_from_legacy.contains(&account_id)
+            {
+                return Err(Error::AccountAlreadyExists);
+            }
+            let mut account = Account::new(created_at);
+            account.green_points = green_points;
+            // migrated points are existing liability, they count but aren't capped
+            self.total_outstanding_green_points = self
+                .total_outstanding_green_points
+                .saturating_add(green_points);
+            account.last_conversion = last_conversion;
+            self.accounts.insert(account_id, &account);
+            self.migrated_from_legacy.insert(account_id, &());
+            Ok(())
         }
 
-        /// acceleration rate calculation is based on ancestors
-        ///
-        /// 10% for parent, 1% for each ancestor
-        fn calc_red_points_from_relationships(
-            &self,
-            // red_points: Balance,
-            referral_coefficients: (Balance, Balance),
-        ) -> Balance {
-            let total_red_points = referral_coefficients
-                .0
-                .saturating_add(referral_coefficients.1);
-            total_red_points
+        #[ink(message)]
+        pub fn change_amm_contract(&mut self, new_amm_contract: AccountId) -> Result<(), Error> {
+            self.only_admin()?;
+            self.amm_contract = new_amm_contract;
+            Ok(())
         }
 
-        /// send some amount to the mining pool
-        fn call_mining_pool_to_process(
-            &self,
-            merchant_id: AccountId,
-            amount: Balance,
-        ) -> Result<(), Error> {
-            let _ = build_call::<D9Environment>()
-                .call(self.mining_pool)
-                .gas_limit(0) // replace with an appropriate gas limit
-                .transferred_value(amount)
-                .exec_input(
-                    ExecutionInput::new(Selector::new(ink::selector_bytes!(
-                        "process_merchant_payment"
-                    )))
-                    .push_arg(merchant_id),
-                )
-                .returns::<Result<(), Error>>()
-                .try_invoke()?;
+        #[ink(message)]
+        pub fn change_mining_pool(&mut self, new_mining_pool: AccountId) -> Result<(), Error> {
+            self.only_admin()?;
+            self.mining_pool = new_mining_pool;
             Ok(())
         }
 
-        pub fn get_ancestors(&self, account_id: AccountId) -> Option<Vec<AccountId>> {
-            let result = self.env().extension().get_ancestors(account_id);
-            match result {
-                Ok(ancestors) => ancestors,
-                Err(_) => None,
-            }
+        #[ink(message)]
+        pub fn get_mining_pool(&self) -> AccountId {
+            self.mining_pool
         }
 
-        fn add_green_points(
+        /// gas forwarded to calls on `target`, 0 means unlimited
+        #[ink(message)]
+        pub fn get_call_gas_limit(&self, target: CallTarget) -> u64 {
+            self.call_gas_limits
+                .get(target as u8)
+                .unwrap_or_else(|| target.default_gas_limit())
+        }
+
+        #[ink(message)]
+        pub fn set_call_gas_limit(
             &mut self,
-            account_id: AccountId,
-            amount: Balance,
-            is_consumer: bool,
+            target: CallTarget,
+            gas_limit: u64,
         ) -> Result<(), Error> {
-            let mut account = self
-                .accounts
-                .get(&account_id)
-                .unwrap_or(Account::new(self.env().block_timestamp()));
-            let redeemable_red_points = self.calc_total_redeemable_red_points(&account);
-            let twenty_four_hours_prior = self.env().block_timestamp().saturating_sub(86_400_000);
-            let permit_based_on_last_conversion: bool = match account.last_conversion {
-                Some(last_conversion) => last_conversion < twenty_four_hours_prior,
-                None => true,
-            };
+            self.only_admin()?;
+            self.call_gas_limits.insert(target as u8, &gas_limit);
+            Ok(())
+        }
 
-            if redeemable_red_points > 0 && permit_based_on_last_conversion && is_consumer {
-                let disburse_result =
-                    self.disburse_d9(account_id, &mut account, redeemable_red_points);
-                if let Err(e) = disburse_result {
-                    return Err(e);
-                }
+        /// length of a day used for subscription and red point accrual math
+        #[ink(message)]
+        pub fn get_milliseconds_day(&self) -> Timestamp {
+            self.milliseconds_day
+        }
+
+        #[ink(message)]
+        pub fn set_milliseconds_day(&mut self, milliseconds_day: Timestamp) -> Result<(), Error> {
+            self.only_admin()?;
+            if milliseconds_day == 0 {
+                return Err(Error::InvalidMillisecondsDay);
             }
-            account.green_points = account.green_points.saturating_add(amount);
-            self.accounts.insert(account_id, &account);
+            self.milliseconds_day = milliseconds_day;
             Ok(())
         }
 
-        /// update referral coefficients for predecessor accounts
-        fn update_ancestors_coefficients(
-            &mut self,
-            ancestors: &[AccountId],
-            withdraw_amount: Balance,
-        ) {
-            //modify parent
-            let parent = ancestors.first();
-            if let Some(parent) = parent {
-                if let Some(mut account) = self.accounts.get(parent) {
-                    let ten_percent = Perbill::from_rational(1u32, 10u32);
-                    let parent_bonus = ten_percent.mul_floor(withdraw_amount);
-                    account.relationship_factors.0 =
-                        account.relationship_factors.0.saturating_add(parent_bonus);
-                    account.relationship_factors = account.relationship_factors;
-                    self.accounts.insert(parent, &account);
-                }
+        #[ink(message)]
+        pub fn get_accrual_unit_ms(&self) -> Timestamp {
+            self.accrual_unit_ms
+        }
+
+        /// accrue red points per `accrual_unit_ms` instead of per day, at the same daily rate.
+        /// 0 goes back to daily accrual
+        #[ink(message)]
+        pub fn set_accrual_unit_ms(&mut self, accrual_unit_ms: Timestamp) -> Result<(), Error> {
+            self.only_admin()?;
+            if accrual_unit_ms > self.milliseconds_day {
+                return Err(Error::InvalidAccrualUnit);
             }
+            self.accrual_unit_ms = accrual_unit_ms;
+            Ok(())
+        }
 
-            //modify others
-            for ancestor in ancestors.iter().skip(1) {
-                if let Some(mut account) = self.accounts.get(ancestor) {
-                    let one_percent = Perbill::from_rational(1u32, 100u32);
-                    let ancestor_bonus: Balance = one_percent.mul_floor(withdraw_amount);
-                    account.relationship_factors.1 = account
-                        .relationship_factors
-                        .1
-                        .saturating_add(ancestor_bonus);
-                    account.relationship_factors = account.relationship_factors;
-                    self.accounts.insert(ancestor, &account);
-                }
+        /// length of one red point accrual step, never longer than a day
+        fn accrual_unit(&self) -> Timestamp {
+            if self.accrual_unit_ms == 0 || self.accrual_unit_ms >= self.milliseconds_day {
+                return self.milliseconds_day;
             }
+            self.accrual_unit_ms
         }
-    }
 
-    /// Unit tests in Rust are normally defined within such a `#[cfg(test)]`
-    /// module and test functions are marked with a `#[test]` attribute.
-    /// The below code is technically just normal Rust code.
-    #[cfg(test)]
-    mod tests {
-        use core::default;
+        #[ink(message)]
+        pub fn get_min_amm_usdt_liquidity(&self) -> Balance {
+            self.min_amm_usdt_liquidity
+        }
 
-        /// Imports all the definitions from the outer scope so we can use them here.
-        use super::*;
-        use ink::env::test::{set_caller, set_value_transferred};
-        use ink::env::DefaultEnvironment;
-        static ONE_MONTH_MILLISECONDS: Timestamp = 86_400_000 * 30;
+        #[ink(message)]
+        pub fn get_merchant_share_permille(&self) -> u32 {
+            self.merchant_share_permille
+        }
 
-        /// prepare default accounts and contract address for tests
-        fn init_accounts() -> ink::env::test::DefaultAccounts<ink::env::DefaultEnvironment> {
-            let default_accounts: ink::env::test::DefaultAccounts<ink::env::DefaultEnvironment> =
-                ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
-            default_accounts
+        /// share of merchant payments paid out to the merchant, 500..=990
+        #[ink(message)]
+        pub fn set_merchant_share_permille(
+            &mut self,
+            merchant_share_permille: u32,
+        ) -> Result<(), Error> {
+            self.only_admin()?;
+            if !(500..=990).contains(&merchant_share_permille) {
+                return Err(Error::InvalidMerchantShare);
+            }
+            self.merchant_share_permille = merchant_share_permille;
+            self.env().emit_event(MerchantShareChanged {
+                merchant_share_permille,
+            });
+            Ok(())
         }
 
-        /// setup env values for caller/callee
-        fn init_calling_env(caller: AccountId) {
-            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(caller);
+        /// (merchant payment, green points funding) of a payment, always summing to `usdt_amount`
+        fn split_payment(&self, usdt_amount: Balance) -> (Balance, Balance) {
+            let merchant_payment =
+                Perbill::from_rational(self.merchant_share_permille, 1000).mul_floor(usdt_amount);
+            (
+                merchant_payment,
+                usdt_amount.saturating_sub(merchant_payment),
+            )
+        }
 
-            let contract_address = ink::env::account_id::<ink::env::DefaultEnvironment>();
-            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(caller);
+        /// (consumer, merchant) green points for `amount` usdt of green points funding. the
+        /// funding is scaled back up to the payment it is the points slice of
+        fn calc_granted_green_points(&self, amount: Balance) -> (Balance, Balance) {
+            let funding_permille = 1000u32.saturating_sub(self.merchant_share_permille);
+            let usdt_amount_to_green = amount
+                .saturating_mul(1000)
+                .saturating_div(funding_permille as Balance);
+            self.calc_payment_points(usdt_amount_to_green)
         }
 
-        /// gives everything as default setup
-        fn default_setup() -> (
-            ink::env::test::DefaultAccounts<ink::env::DefaultEnvironment>,
-            D9MerchantMining,
-        ) {
-            // init accounts
-            let default_accounts = init_accounts();
+        #[ink(message)]
+        pub fn set_min_amm_usdt_liquidity(&mut self, min_liquidity: Balance) -> Result<(), Error> {
+            self.only_admin()?;
+            self.min_amm_usdt_liquidity = min_liquidity;
+            Ok(())
+        }
 
-            //build contract
-            let contract = D9MerchantMining::new(
-                default_accounts.alice,
-                default_accounts.bob,
-                default_accounts.charlie,
-            );
-            (default_accounts, contract)
+        #[ink(message)]
+        pub fn get_conversion_slippage_permille(&self) -> u32 {
+            self.conversion_slippage_permille
         }
 
-        ///sets blocktime stamp and moves chain forwards by one block
-        fn set_block_time(init_time: Timestamp) {
-            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(init_time);
-            ink::env::test::advance_block::<ink::env::DefaultEnvironment>();
+        /// slippage allowed on d9 conversions, 0..=1000 permille below the pre-swap quote
+        #[ink(message)]
+        pub fn set_conversion_slippage_permille(
+            &mut self,
+            slippage_permille: u32,
+        ) -> Result<(), Error> {
+            self.only_admin()?;
+            if slippage_permille > 1000 {
+                return Err(Error::InvalidSlippage);
+            }
+            self.conversion_slippage_permille = slippage_permille;
+            Ok(())
         }
 
-        ///moves block forward by `move_forward_by` in milliseconds and moves chain forwards by one block
-        fn move_time_forward(move_forward_by: Timestamp) {
-            let current_block_time: Timestamp =
-                ink::env::block_timestamp::<ink::env::DefaultEnvironment>();
-            let _ = ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(
-                current_block_time + move_forward_by,
-            );
-            let _ = ink::env::test::advance_block::<ink::env::DefaultEnvironment>();
+        #[ink(message)]
+        pub fn get_green_points_curve(&self) -> (Vec<(Balance, u32)>, bool) {
+            (
+                self.green_points_curve.clone(),
+                self.green_points_curve_enabled,
+            )
         }
 
-        #[ink::test]
-        fn redeem_d9() {
-            let (default_accounts, mut contract) = default_setup();
+        /// tiers must start at 0 and have strictly increasing thresholds
+        #[ink(message)]
+        pub fn set_green_points_curve(&mut self, curve: Vec<(Balance, u32)>) -> Result<(), Error> {
+            self.only_admin()?;
+            let starts_at_zero = curve.first().map_or(false, |tier| tier.0 == 0);
+            let is_ascending = curve.windows(2).all(|tiers| tiers[0].0 < tiers[1].0);
+            if !starts_at_zero || !is_ascending {
+                return Err(Error::InvalidGreenPointsCurve);
+            }
+            self.green_points_curve = curve;
+            Ok(())
+        }
 
-            //prep contract calling env
+        #[ink(message)]
+        pub fn get_grant_votes_on_redeem(&self) -> bool {
+            self.grant_votes_on_redeem
+        }
+
+        #[ink(message)]
+        pub fn set_grant_votes_on_redeem(&mut self, enabled: bool) -> Result<(), Error> {
+            self.only_admin()?;
+            self.grant_votes_on_redeem = enabled;
+            Ok(())
+        }
+
+        /// (max_points_per_day, max_points_per_merchant_per_day), 0 means no cap
+        #[ink(message)]
+        pub fn get_points_velocity_limits(&self) -> (Balance, Balance) {
+            (
+                self.max_points_per_day,
+                self.max_points_per_merchant_per_day,
+            )
+        }
+
+        #[ink(message)]
+        pub fn set_points_velocity_limits(
+            &mut self,
+            max_points_per_day: Balance,
+            max_points_per_merchant_per_day: Balance,
+        ) -> Result<(), Error> {
+            self.only_admin()?;
+            self.max_points_per_day = max_points_per_day;
+            self.max_points_per_merchant_per_day = max_points_per_merchant_per_day;
+            Ok(())
+        }
+
+        /// (total_outstanding_green_points, max_total_green_points), a max of 0 means no cap
+        #[ink(message)]
+        pub fn get_green_points_cap(&self) -> (Balance, Balance) {
+            (
+                self.total_outstanding_green_points,
+                self.max_total_green_points,
+            )
+        }
+
+        #[ink(message)]
+        pub fn set_max_total_green_points(
+            &mut self,
+            max_total_green_points: Balance,
+        ) -> Result<(), Error> {
+            self.only_admin()?;
+            self.max_total_green_points = max_total_green_points;
+            Ok(())
+        }
+
+        /// green points issued to `consumer` since the start of the current day
+        #[ink(message)]
+        pub fn get_points_issued_today(&self, consumer: AccountId) -> Balance {
+            self.points_issued_per_day
+                .get((consumer, self.current_day_index()))
+                .unwrap_or(0)
+        }
+
+        fn current_day_index(&self) -> u64 {
+            self.env().block_timestamp() / self.milliseconds_day
+        }
+
+        /// counts `points` against the consumer's daily caps, fails instead of clamping
+        fn record_points_velocity(
+            &mut self,
+            consumer: AccountId,
+            merchant: AccountId,
+            points: Balance,
+        ) -> Result<(), Error> {
+            // no limits, so nothing to count against
+            if self.max_points_per_day == 0 && self.max_points_per_merchant_per_day == 0 {
+                return Ok(());
+            }
+            let day = self.current_day_index();
+            let issued_today = self
+                .points_issued_per_day
+                .get((consumer, day))
+                .unwrap_or(0)
+                .saturating_add(points);
+            if self.max_points_per_day != 0 && issued_today > self.max_points_per_day {
+                return Err(Error::PointsVelocityExceeded);
+            }
+            let issued_by_merchant = self
+                .points_issued_per_merchant_day
+                .get((consumer, merchant, day))
+                .unwrap_or(0)
+                .saturating_add(points);
+            if self.max_points_per_merchant_per_day != 0
+                && issued_by_merchant > self.max_points_per_merchant_per_day
+            {
+                return Err(Error::PointsVelocityExceeded);
+            }
+            self.points_issued_per_day
+                .insert((consumer, day), &issued_today);
+            self.points_issued_per_merchant_day
+                .insert((consumer, merchant, day), &issued_by_merchant);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn set_green_points_curve_enabled(&mut self, enabled: bool) -> Result<(), Error> {
+            self.only_admin()?;
+            self.green_points_curve_enabled = enabled;
+            Ok(())
+        }
+
+        /// Modifies the code which is used to execute calls to this contract address (`AccountId`).
+        ///
+        /// We use this to upgrade the contract logic. We don't do any authorization here, any caller
+        /// can execute this method. In a production contract you would do some authorization here.
+        #[ink(message)]
+        pub fn set_code(&mut self, code_hash: [u8; 32]) {
+            let caller = self.env().caller();
+            assert!(caller == self.admin, "Only admin can set code hash.");
+            ink::env::set_code_hash(&code_hash).unwrap_or_else(|err| {
+                panic!(
+                    "Failed to `set_code_hash` to {:?} due to {:?}",
+                    code_hash, err
+                )
+            });
+            ink::env::debug_println!("Switched code hash to {:?}.", code_hash);
+        }
+
+        fn check_subscription_permissibility(
+            &self,
+            account_id: AccountId,
+            threshold_points: Balance,
+        ) -> Result<(), Error> {
+            let account_option = self.accounts.get(&account_id);
+            if account_option.is_none() {
+                return Err(Error::NoAccountCantCreateMerchantAccount);
+            }
+            let account = account_option.unwrap();
+            if account.green_points < threshold_points {
+                return Err(Error::PointsInsufficientToCreateMerchantAccount);
+            }
+            Ok(())
+        }
+
+        fn validate_usdt_transfer(&self, account: AccountId, amount: Balance) -> Result<(), Error> {
+            let check_balance_result = self.validate_usdt_balance(account, amount);
+            if check_balance_result.is_err() {
+                return Err(Error::UserUSDTBalanceInsufficient);
+            }
+            let check_allowance_result = self.validate_usdt_allowance(account, amount);
+            if let Err(e) = check_allowance_result {
+                return Err(e);
+            }
+            Ok(())
+        }
+
+        fn validate_usdt_balance(
+            &self,
+            account_id: AccountId,
+            amount: Balance,
+        ) -> Result<(), Error> {
+            let usdt_balance = self.get_usdt_balance(account_id);
+            if usdt_balance < amount {
+                return Err(Error::UserUSDTBalanceInsufficient);
+            }
+            Ok(())
+        }
+
+        pub fn validate_usdt_allowance(
+            &self,
+            owner: AccountId,
+            amount: Balance,
+        ) -> Result<(), Error> {
+            let allowance = build_call::<D9Environment>()
+                .call(self.usdt_contract)
+                .gas_limit(self.get_call_gas_limit(CallTarget::Usdt))
+                .exec_input(
+                    ExecutionInput::new(Selector::new(selector_bytes!("PSP22::allowance")))
+                        .push_arg(owner)
+                        .push_arg(self.env().account_id()),
+                )
+                .returns::<Balance>()
+                .invoke();
+            if allowance < amount {
+                return Err(Error::InsufficientAllowance);
+            }
+            Ok(())
+        }
+
+        /// a merchant can't be the consumer of its own transaction, that would mint both sides
+        /// of the points
+        fn reject_self_dealing(
+            merchant_id: AccountId,
+            consumer_id: AccountId,
+        ) -> Result<(), Error> {
+            if merchant_id == consumer_id {
+                return Err(Error::SelfDealingNotAllowed);
+            }
+            Ok(())
+        }
+
+        /// make sure it is a valid merchant account and their subscription is not expired
+        fn validate_merchant(&self, account_id: AccountId) -> Result<(), Error> {
+            let merchant_expiry_option: Option<Timestamp> = self.merchant_expiry.get(&account_id);
+            if merchant_expiry_option.is_none() {
+                return Err(Error::NoMerchantAccountFound);
+            }
+            let merchant_expiry = merchant_expiry_option.unwrap();
+            if merchant_expiry < self.env().block_timestamp() {
+                return Err(Error::MerchantAccountExpired);
+            }
+            Ok(())
+        }
+
+        fn convert_to_d9(&mut self, amount: Balance) -> Result<Balance, Error> {
+            let grant_allowance_result = self.grant_amm_allowance(amount);
+            if grant_allowance_result.is_err() {
+                return Err(Error::GrantingAllowanceFailed);
+            }
+            let quote = self.quote_d9(amount)?;
+            let min_d9_out = Self::calc_min_d9_out(quote, self.conversion_slippage_permille);
+            let d9_amount = self.amm_get_d9(amount, min_d9_out)?;
+
+            Ok(d9_amount)
+        }
+
+        /// least d9 a conversion quoted at `quote` may return
+        fn calc_min_d9_out(quote: Balance, slippage_permille: u32) -> Balance {
+            let slippage = Perbill::from_rational(slippage_permille.min(1000), 1000);
+            quote.saturating_sub(slippage.mul_ceil(quote))
+        }
+
+        fn contract_sends_usdt_to(
+            &self,
+            recipient: AccountId,
+            amount: Balance,
+        ) -> Result<(), Error> {
+            build_call::<D9Environment>()
+                .call(self.usdt_contract)
+                .gas_limit(self.get_call_gas_limit(CallTarget::Usdt))
+                .exec_input(
+                    ExecutionInput::new(Selector::new(selector_bytes!("PSP22::transfer")))
+                        .push_arg(recipient)
+                        .push_arg(amount)
+                        .push_arg([0u8]),
+                )
+                .returns::<Result<(), Error>>()
+                .invoke()
+        }
+
+        fn get_usdt_balance(&self, account_id: AccountId) -> Balance {
+            build_call::<D9Environment>()
+                .call(self.usdt_contract)
+                .gas_limit(self.get_call_gas_limit(CallTarget::Usdt))
+                .exec_input(
+                    ExecutionInput::new(Selector::new(selector_bytes!("PSP22::balance_of")))
+                        .push_arg(account_id),
+                )
+                .returns::<Balance>()
+                .invoke()
+        }
+
+        /// pulls usdt from `sender` and returns the amount that actually arrived.
+        ///
+        /// the contract balance is measured around `transfer_from` so a fee-on-transfer
+        /// token can't credit more than was received.
+        pub fn receive_usdt_from_user(
+            &self,
+            sender: AccountId,
+            amount: Balance,
+        ) -> Result<Balance, Error> {
+            let balance_before = self.get_usdt_balance(self.env().account_id());
+            build_call::<D9Environment>()
+                .call(self.usdt_contract)
+                .gas_limit(self.get_call_gas_limit(CallTarget::Usdt))
+                .exec_input(
+                    ExecutionInput::new(Selector::new(selector_bytes!("PSP22::transfer_from")))
+                        .push_arg(sender)
+                        .push_arg(self.env().account_id())
+                        .push_arg(amount)
+                        .push_arg([0u8]),
+                )
+                .returns::<Result<(), Error>>()
+                .invoke()?;
+            let balance_after = self.get_usdt_balance(self.env().account_id());
+            Ok(self.settle_received_usdt(sender, amount, balance_before, balance_after))
+        }
+
+        /// amount credited for a transfer given the contract balance before and after it
+        fn settle_received_usdt(
+            &self,
+            sender: AccountId,
+            requested: Balance,
+            balance_before: Balance,
+            balance_after: Balance,
+        ) -> Balance {
+            let received = balance_after.saturating_sub(balance_before).min(requested);
+            if received < requested {
+                self.env().emit_event(FeeOnTransferDetected {
+                    sender,
+                    requested,
+                    received,
+                });
+            }
+            received
+        }
+        //xjyLYnZBRhYYjUKjCp8UiHnmcjHmkPfRSBxTiLLMoEwtzwp
+        //d40a697875ef7a24aaed19ab41e1395675a1d84a5ddbc78a5a342e87c2d580f6
+        //89151c651f568f7ae1f1156c3409d329bd5ccfc0eb9fc29b38b25d8b8bf831fe <- factor fix
+
+        fn grant_amm_allowance(&mut self, amount: Balance) -> Result<(), Error> {
+            let call_result = build_call::<D9Environment>()
+                .call(self.usdt_contract)
+                .gas_limit(self.get_call_gas_limit(CallTarget::Usdt))
+                .exec_input(
+                    ExecutionInput::new(Selector::new(selector_bytes!("PSP22::approve")))
+                        .push_arg(self.amm_contract)
+                        .push_arg(amount),
+                )
+                .returns::<Result<(), Error>>()
+                .try_invoke()?;
+            call_result.unwrap()
+        }
+
+        ///convert received usdt to d9 which will go to mining pool
+        fn amm_get_d9(&self, amount: Balance, min_d9_out: Balance) -> Result<Balance, Error> {
+            let call_result = build_call::<D9Environment>()
+                .call(self.amm_contract)
+                .gas_limit(self.get_call_gas_limit(CallTarget::Amm))
+                .exec_input(
+                    ExecutionInput::new(Selector::new(selector_bytes!("get_d9_and_forward")))
+                        .push_arg(amount)
+                        .push_arg(min_d9_out)
+                        .push_arg(self.env().account_id()),
+                )
+                .returns::<Result<Balance, Error>>()
+                .try_invoke()?;
+            call_result.unwrap()
+        }
+
+        /// d9 the amm would pay for `amount` usdt right now, net of its fee
+        fn quote_d9(&self, amount: Balance) -> Result<Balance, Error> {
+            let direction = Direction(Currency::USDT, Currency::D9);
+            let call_result = build_call::<D9Environment>()
+                .call(self.amm_contract)
+                .gas_limit(self.get_call_gas_limit(CallTarget::Amm))
+                .exec_input(
+                    ExecutionInput::new(Selector::new(selector_bytes!("calculate_exchange")))
+                        .push_arg(direction)
+                        .push_arg(amount),
+                )
+                .returns::<Result<Balance, Error>>()
+                .try_invoke()
+                .map_err(|_| Error::CrossContractCallErrorGettingEstimate)?;
+            call_result
+                .map_err(|_| Error::CrossContractCallErrorGettingEstimate)?
+                .map_err(|_| Error::ErrorGettingEstimate)
+        }
+
+        /// reject d9 conversions early when the amm's usdt side is too thin to fill them
+        fn ensure_amm_liquidity(&self) -> Result<(), Error> {
+            if self.min_amm_usdt_liquidity == 0 {
+                return Ok(());
+            }
+            let (_, usdt_reserve) = self.get_amm_reserves()?;
+            Self::check_amm_liquidity(usdt_reserve, self.min_amm_usdt_liquidity)
+        }
+
+        fn check_amm_liquidity(usdt_reserve: Balance, min_liquidity: Balance) -> Result<(), Error> {
+            if usdt_reserve < min_liquidity {
+                return Err(Error::AMMLiquidityTooLow);
+            }
+            Ok(())
+        }
+
+        /// (d9, usdt) held by the amm
+        fn get_amm_reserves(&self) -> Result<(Balance, Balance), Error> {
+            let reserves = build_call::<D9Environment>()
+                .call(self.amm_contract)
+                .gas_limit(self.get_call_gas_limit(CallTarget::Amm))
+                .exec_input(ExecutionInput::new(Selector::new(selector_bytes!(
+                    "get_currency_reserves"
+                ))))
+                .returns::<(Balance, Balance)>()
+                .try_invoke()?;
+            reserves.map_err(|_| Error::CrossContractCallErrorGettingEstimate)
+        }
+
+        /// call amm contract to get usdt, which will go to merchant
+
+        fn convert_to_usdt(&self, amount: Balance) -> Result<Balance, Error> {
+            let result = build_call::<D9Environment>()
+                .call(self.amm_contract)
+                .gas_limit(self.get_call_gas_limit(CallTarget::Amm))
+                .transferred_value(amount)
+                .exec_input(ExecutionInput::new(Selector::new(selector_bytes!(
+                    "get_usdt"
+                ))))
+                .returns::<Result<Balance, Error>>()
+                .try_invoke()?;
+            result.unwrap()
+        }
+
+        fn estimate_usdt(&self, amount: Balance) -> Result<Balance, Error> {
+            let direction = Direction(Currency::D9, Currency::USDT);
+            // this result is to catch any error in calling originating from the environment
+            let cross_contract_call_result = build_call::<D9Environment>()
+                .call(self.amm_contract)
+                .gas_limit(self.get_call_gas_limit(CallTarget::Amm))
+                .exec_input(
+                    ExecutionInput::new(Selector::new(selector_bytes!("estimate_exchange")))
+                        .push_arg(direction)
+                        .push_arg(amount),
+                )
+                .returns::<Result<(Balance, Balance), Error>>()
+                .try_invoke();
+            // this result will return the value or some error from the contract itself
+            if cross_contract_call_result.is_err() {
+                return Err(Error::CrossContractCallErrorGettingEstimate);
+            }
+            let method_call_result = cross_contract_call_result.unwrap();
+            if method_call_result.is_err() {
+                return Err(Error::ErrorGettingEstimate);
+            }
+            let something = method_call_result.unwrap();
+            if something.is_err() {
+                return Err(Error::ErrorGettingEstimate);
+            }
+            let usdt_balance = something.unwrap().1;
+            Ok(usdt_balance)
+        }
+
+        /// (usdt estimate, amm fee percent), read in the same transaction so the fee can't
+        /// change between the two
+        fn estimate_usdt_with_fee(&self, amount: Balance) -> Result<(Balance, u32), Error> {
+            let usdt_amount = self.estimate_usdt(amount)?;
+            let fee_percent = self.read_amm_fee_percent()?;
+            Ok((usdt_amount, fee_percent))
+        }
+
+        /// function to restrict access to admin
+        fn only_admin(&self) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if caller != self.admin {
+                return Err(Error::OnlyAdmin);
+            }
+            Ok(())
+        }
+
+        /// first step of an admin transfer, `new_admin` has to accept
+        #[ink(message)]
+        pub fn propose_admin(&mut self, new_admin: AccountId) -> Result<(), Error> {
+            self.only_admin()?;
+            self.pending_admin = Some(new_admin);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn accept_admin(&mut self) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if self.pending_admin != Some(caller) {
+                return Err(Error::NotPendingAdmin);
+            }
+            self.admin = caller;
+            self.pending_admin = None;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_pending_admin(&self) -> Option<AccountId> {
+            self.pending_admin
+        }
+
+        ///get green points from usdt amount
+        fn calculate_green_points(&self, amount: Balance) -> Balance {
+            if self.green_points_curve_enabled {
+                return self.calculate_green_points_curved(amount);
+            }
+            amount.saturating_mul(100)
+        }
+
+        /// each tier's multiplier applies only to the part of `amount` inside that tier
+        fn calculate_green_points_curved(&self, amount: Balance) -> Balance {
+            let mut green_points: Balance = 0;
+            for (index, (tier_start, multiplier)) in self.green_points_curve.iter().enumerate() {
+                if amount <= *tier_start {
+                    break;
+                }
+                let tier_end = self
+                    .green_points_curve
+                    .get(index + 1)
+                    .map_or(Balance::MAX, |next_tier| next_tier.0);
+                let amount_in_tier = amount.min(tier_end).saturating_sub(*tier_start);
+                green_points = green_points
+                    .saturating_add(amount_in_tier.saturating_mul(*multiplier as Balance));
+            }
+            green_points
+        }
+
+        /// base rate calculation is based on time.acceleration is based on ancestors
+        ///
+        /// 1 red point = 1 green point
+        fn calc_red_points_from_time(
+            &self,
+            green_points: Balance,
+            last_redeem_timestamp: Timestamp,
+        ) -> Balance {
+            // rate green points => red points
+            let transmutation_rate = Perbill::from_rational(1u32, 2000u32);
+
+            let accrual_unit = self.accrual_unit();
+            let units_since_last_redeem = self
+                .env()
+                .block_timestamp()
+                .saturating_sub(last_redeem_timestamp)
+                .saturating_div(accrual_unit) as Balance;
+
+            if accrual_unit == self.milliseconds_day {
+                return transmutation_rate
+                    .mul_floor(green_points)
+                    .saturating_mul(units_since_last_redeem);
+            }
+            // the daily rate scaled down to the accrual unit
+            multiply_by_rational_with_rounding(
+                green_points,
+                units_since_last_redeem.saturating_mul(accrual_unit as Balance),
+                2000u128.saturating_mul(self.milliseconds_day as Balance),
+                Rounding::Down,
+            )
+            .unwrap_or(Balance::MAX)
+        }
+
+        /// acceleration rate calculation is based on ancestors
+        ///
+        /// 10% for parent, 1% for each ancestor
+        fn calc_red_points_from_relationships(
+            &self,
+            // red_points: Balance,
+            referral_coefficients: (Balance, Balance),
+        ) -> Balance {
+            let total_red_points = referral_coefficients
+                .0
+                .saturating_add(referral_coefficients.1);
+            total_red_points
+        }
+
+        /// send some amount to the mining pool
+        fn call_mining_pool_to_process(
+            &self,
+            merchant_id: AccountId,
+            amount: Balance,
+        ) -> Result<(), Error> {
+            let result = build_call::<D9Environment>()
+                .call(self.mining_pool)
+                .gas_limit(self.get_call_gas_limit(CallTarget::MiningPool))
+                .transferred_value(amount)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!(
+                        "process_merchant_payment"
+                    )))
+                    .push_arg(merchant_id),
+                )
+                .returns::<Result<(), Error>>()
+                .try_invoke();
+            Self::settle_pool_forward(result)
+        }
+
+        /// only a call that reached the pool and was accepted counts as delivered;
+        /// a refusal from the pool is a failed forward so the caller can queue it
+        fn settle_pool_forward(
+            result: ink::env::Result<ink::MessageResult<Result<(), Error>>>,
+        ) -> Result<(), Error> {
+            match result {
+                Ok(Ok(Ok(()))) => Ok(()),
+                Err(e) => Err(e.into()),
+                _ => Err(Error::SendingD9ToMiningPool),
+            }
+        }
+
+        pub fn get_ancestors(&self, account_id: AccountId) -> Option<Vec<AccountId>> {
+            let result = self.env().extension().get_ancestors(account_id);
+            match result {
+                Ok(ancestors) => ancestors,
+                Err(_) => None,
+            }
+        }
+
+        fn add_green_points(
+            &mut self,
+            account_id: AccountId,
+            amount: Balance,
+            is_consumer: bool,
+        ) -> Result<(), Error> {
+            let mut account = self
+                .accounts
+                .get(&account_id)
+                .unwrap_or(Account::new(self.env().block_timestamp()));
+            // payments never redeem on the user's behalf, the accrued red points are settled
+            // and wait for an explicit `redeem`
+            self.settle_red_points(account_id, &account);
+            let redeemable_red_points = self.calc_total_redeemable_red_points(account_id, &account);
+            if redeemable_red_points > 0 && !self.is_within_redeem_lockout(&account) && is_consumer
+            {
+                self.env().emit_event(PendingRedemptionAvailable {
+                    account_id,
+                    red_points: redeemable_red_points,
+                });
+            }
+            let amount = self.pay_down_refund_debt(account_id, amount);
+            self.issue_green_points(amount)?;
+            account.green_points = account.green_points.saturating_add(amount);
+            self.accounts.insert(account_id, &account);
+            self.touch(account_id);
+            Ok(())
+        }
+
+        fn touch(&mut self, account_id: AccountId) {
+            self.last_activity
+                .insert(account_id, &self.env().block_timestamp());
+        }
+
+        /// last activity of `account_id`. accounts untouched since activity was first tracked
+        /// report their last redemption, or their creation
+        #[ink(message)]
+        pub fn get_last_activity(&self, account_id: AccountId) -> Option<Timestamp> {
+            self.last_activity.get(account_id).or_else(|| {
+                self.accounts
+                    .get(&account_id)
+                    .map(|account| account.last_conversion.unwrap_or(account.created_at))
+            })
+        }
+
+        fn issue_green_points(&mut self, amount: Balance) -> Result<(), Error> {
+            let outstanding = self.total_outstanding_green_points.saturating_add(amount);
+            if self.max_total_green_points != 0 && outstanding > self.max_total_green_points {
+                return Err(Error::GlobalPointCapReached);
+            }
+            self.total_outstanding_green_points = outstanding;
+            Ok(())
+        }
+
+        /// update referral coefficients for predecessor accounts
+        /// ancestors without an account are skipped, so the parent bonus goes to the nearest
+        /// ancestor that has one and the ancestor bonuses to those above it
+        fn update_ancestors_coefficients(
+            &mut self,
+            account_id: AccountId,
+            ancestors: &[AccountId],
+            withdraw_amount: Balance,
+        ) {
+            let mut eligible = ancestors
+                .iter()
+                .take(MAX_ANCESTOR_DEPTH)
+                .filter_map(|ancestor| {
+                    self.accounts
+                        .get(ancestor)
+                        .map(|account| (*ancestor, account))
+                })
+                .collect::<Vec<_>>()
+                .into_iter();
+
+            //modify parent
+            let Some((parent, mut account)) = eligible.next() else {
+                return;
+            };
+            let ten_percent = Perbill::from_rational(1u32, 10u32);
+            let parent_bonus = ten_percent.mul_floor(withdraw_amount);
+            account.relationship_factors.0 =
+                account.relationship_factors.0.saturating_add(parent_bonus);
+            self.accounts.insert(parent, &account);
+
+            //modify others
+            let mut credited = Vec::new();
+            for (ancestor, mut account) in eligible {
+                let one_percent = Perbill::from_rational(1u32, 100u32);
+                let ancestor_bonus: Balance = one_percent.mul_floor(withdraw_amount);
+                account.relationship_factors.1 = account
+                    .relationship_factors
+                    .1
+                    .saturating_add(ancestor_bonus);
+                self.accounts.insert(ancestor, &account);
+                credited.push(ancestor);
+            }
+            self.env().emit_event(AncestorsCredited {
+                account_id,
+                parent,
+                ancestors: credited,
+            });
+        }
+    }
+
+    /// Unit tests in Rust are normally defined within such a `#[cfg(test)]`
+    /// module and test functions are marked with a `#[test]` attribute.
+    /// The below code is technically just normal Rust code.
+    #[cfg(test)]
+    mod tests {
+        use core::default;
+
+        /// Imports all the definitions from the outer scope so we can use them here.
+        use super::*;
+        use ink::env::test::{set_caller, set_value_transferred};
+        use ink::env::DefaultEnvironment;
+        static ONE_MONTH_MILLISECONDS: Timestamp = 86_400_000 * 30;
+
+        /// prepare default accounts and contract address for tests
+        fn init_accounts() -> ink::env::test::DefaultAccounts<ink::env::DefaultEnvironment> {
+            let default_accounts: ink::env::test::DefaultAccounts<ink::env::DefaultEnvironment> =
+                ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            default_accounts
+        }
+
+        /// setup env values for caller/callee
+        fn init_calling_env(caller: AccountId) {
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(caller);
+
+            let contract_address = ink::env::account_id::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(caller);
+        }
+
+        /// gives everything as default setup
+        fn default_setup() -> (
+            ink::env::test::DefaultAccounts<ink::env::DefaultEnvironment>,
+            D9MerchantMining,
+        ) {
+            // init accounts
+            let default_accounts = init_accounts();
+
+            //build contract
+            let contract = D9MerchantMining::new(
+                default_accounts.alice,
+                default_accounts.bob,
+                default_accounts.charlie,
+            );
+            (default_accounts, contract)
+        }
+
+        ///sets blocktime stamp and moves chain forwards by one block
+        fn set_block_time(init_time: Timestamp) {
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(init_time);
+            ink::env::test::advance_block::<ink::env::DefaultEnvironment>();
+        }
+
+        ///moves block forward by `move_forward_by` in milliseconds and moves chain forwards by one block
+        fn move_time_forward(move_forward_by: Timestamp) {
+            let current_block_time: Timestamp =
+                ink::env::block_timestamp::<ink::env::DefaultEnvironment>();
+            let _ = ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(
+                current_block_time + move_forward_by,
+            );
+            let _ = ink::env::test::advance_block::<ink::env::DefaultEnvironment>();
+        }
+
+        #[ink::test]
+        fn call_gas_limits_default_per_target_and_are_admin_only() {
+            let (accounts, mut contract) = default_setup();
+            assert_eq!(
+                contract.get_call_gas_limit(CallTarget::Usdt),
+                CallTarget::Usdt.default_gas_limit()
+            );
+            assert!(contract.set_call_gas_limit(CallTarget::Amm, 0).is_ok());
+            assert_eq!(contract.get_call_gas_limit(CallTarget::Amm), 0);
+            assert_eq!(
+                contract.get_call_gas_limit(CallTarget::MiningPool),
+                CallTarget::MiningPool.default_gas_limit()
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(
+                contract.set_call_gas_limit(CallTarget::Usdt, 1),
+                Err(Error::OnlyAdmin)
+            );
+        }
+
+        #[ink::test]
+        fn redeem_d9() {
+            let (default_accounts, mut contract) = default_setup();
+
+            //prep contract calling env
+            init_calling_env(default_accounts.alice);
+            let account: Account = Account {
+                green_points: 200000000,
+                relationship_factors: (0, 0),
+                last_conversion: None,
+                redeemed_usdt: 0,
+                redeemed_d9: 0,
+                created_at: 0,
+            };
+            set_block_time(0);
+            contract.accounts.insert(default_accounts.alice, &account);
+            move_time_forward(100_000_000);
+
+            //redeem d9
+            set_caller::<DefaultEnvironment>(default_accounts.alice);
+            let redemption_result = contract.redeem_d9();
+            println!("green_points_result: {:?}", redemption_result);
+            assert!(redemption_result.is_ok());
+        }
+
+        #[ink::test]
+        fn redeem_respects_lockout_for_both_currencies() {
+            let (default_accounts, mut contract) = default_setup();
+            set_block_time(ONE_MONTH_MILLISECONDS);
+            let mut account = Account::new(0);
+            account.green_points = 200_000_000;
+            account.last_conversion = Some(ONE_MONTH_MILLISECONDS - 1_000);
+            contract.accounts.insert(default_accounts.bob, &account);
+
+            set_caller::<DefaultEnvironment>(default_accounts.bob);
+            assert_eq!(contract.redeem(Currency::D9), Err(Error::NothingToRedeem));
+            assert_eq!(contract.redeem(Currency::USDT), Err(Error::NothingToRedeem));
+            // the preview refuses the same way instead of quoting a locked redemption
+            assert_eq!(
+                contract.get_redeemable_d9(default_accounts.bob),
+                Err(Error::NothingToRedeem)
+            );
+            set_caller::<DefaultEnvironment>(default_accounts.charlie);
+            assert_eq!(contract.redeem(Currency::USDT), Err(Error::NoAccountFound));
+        }
+
+        #[ink::test]
+        fn account_view_matches_redemption() {
+            let (default_accounts, mut contract) = default_setup();
+            set_block_time(ONE_MONTH_MILLISECONDS);
+            let now = ink::env::block_timestamp::<DefaultEnvironment>();
+            let mut account = Account::new(0);
+            account.green_points = 200_000_000;
+            contract.accounts.insert(default_accounts.bob, &account);
+            contract
+                .merchant_expiry
+                .insert(default_accounts.bob, &(now + ONE_MONTH_MILLISECONDS));
+
+            let view = contract.get_account_v2(default_accounts.bob).unwrap();
+            let redeemable_red_points = contract
+                .prepare_redemption(default_accounts.bob, &account)
+                .unwrap();
+            assert_eq!(view.view_version, ACCOUNT_VIEW_VERSION);
+            assert_eq!(view.account, account);
+            assert_eq!(view.redeemable_red_points, redeemable_red_points);
+            assert_eq!(
+                view.redeemable_usdt,
+                D9MerchantMining::red_points_to_usdt(redeemable_red_points)
+            );
+            assert_eq!(view.lockout_remaining, 0);
+            assert_eq!(view.merchant_expiry, Some(now + ONE_MONTH_MILLISECONDS));
+            assert!(view.is_active_merchant);
+
+            // an hour after a redemption the lockout has 23 hours left
+            account.last_conversion = Some(now - 3_600_000);
+            contract.accounts.insert(default_accounts.bob, &account);
+            let view = contract.get_account_v2(default_accounts.bob).unwrap();
+            assert_eq!(view.lockout_remaining, 23 * 3_600_000);
+            assert_eq!(
+                contract.prepare_redemption(default_accounts.bob, &account),
+                Err(Error::NothingToRedeem)
+            );
+
+            // the lockout lasts one configured day
+            contract.set_milliseconds_day(7_200_000).unwrap();
+            let view = contract.get_account_v2(default_accounts.bob).unwrap();
+            assert_eq!(view.lockout_remaining, 3_600_000);
+            assert_eq!(contract.get_account_v2(default_accounts.charlie), None);
+        }
+
+        #[ink::test]
+        fn unknown_account_defaults_to_zeroed_account() {
+            let (default_accounts, mut contract) = default_setup();
+            set_block_time(ONE_MONTH_MILLISECONDS);
+            let now = ink::env::block_timestamp::<DefaultEnvironment>();
+
+            let account = contract.get_account_or_default(default_accounts.django);
+            assert_eq!(account, Account::new(now));
+            assert_eq!(account.green_points, 0);
+            assert_eq!(account.created_at, now);
+            assert_eq!(contract.get_account(default_accounts.django), None);
+
+            let mut stored = Account::new(0);
+            stored.green_points = 1_000;
+            contract.accounts.insert(default_accounts.bob, &stored);
+            assert_eq!(
+                contract.get_account_or_default(default_accounts.bob),
+                stored
+            );
+        }
+
+        #[ink::test]
+        fn credit_redemption_updates_matching_field() {
+            let mut account = Account::new(0);
+            D9MerchantMining::credit_redemption(&mut account, Currency::D9, 1_000);
+            D9MerchantMining::credit_redemption(&mut account, Currency::USDT, 25);
+            D9MerchantMining::credit_redemption(&mut account, Currency::USDT, 5);
+            assert_eq!(account.redeemed_d9, 1_000);
+            assert_eq!(account.redeemed_usdt, 30);
+        }
+
+        #[ink::test]
+        fn give_green_points_rejects_self_dealing() {
+            let (default_accounts, mut contract) = default_setup();
+            set_block_time(0);
+            contract
+                .merchant_expiry
+                .insert(default_accounts.bob, &ONE_MONTH_MILLISECONDS);
+
+            set_caller::<DefaultEnvironment>(default_accounts.bob);
+            assert_eq!(
+                contract.give_green_points_usdt(default_accounts.bob, 1_000),
+                Err(Error::SelfDealingNotAllowed)
+            );
+            assert_eq!(
+                contract.give_green_points_d9(default_accounts.bob),
+                Err(Error::SelfDealingNotAllowed)
+            );
+            assert_eq!(
+                contract.send_usdt_payment_to_merchant(default_accounts.bob, 1_000),
+                Err(Error::SelfDealingNotAllowed)
+            );
+            assert_eq!(
+                contract.give_green_points_internal(default_accounts.bob, 1_000),
+                Err(Error::SelfDealingNotAllowed)
+            );
+            assert!(contract.accounts.get(default_accounts.bob).is_none());
+        }
+
+        #[ink::test]
+        fn merchant_can_be_consumer_at_another_merchant() {
+            let (default_accounts, mut contract) = default_setup();
+            set_block_time(0);
+            contract
+                .merchant_expiry
+                .insert(default_accounts.bob, &ONE_MONTH_MILLISECONDS);
+            contract
+                .merchant_expiry
+                .insert(default_accounts.charlie, &ONE_MONTH_MILLISECONDS);
+
+            // charlie is a merchant too, but here is the consumer at bob's shop
+            set_caller::<DefaultEnvironment>(default_accounts.bob);
+            let result = contract
+                .give_green_points_internal(default_accounts.charlie, 1_600)
+                .unwrap();
+            assert_eq!(result.consumer, 1_000_000);
+            assert_eq!(result.merchant, 160_000);
+        }
+
+        #[ink::test]
+        fn pending_admin_tracks_proposal_and_acceptance() {
+            let (default_accounts, mut contract) = default_setup();
+            assert_eq!(contract.get_pending_admin(), None);
+            assert_eq!(contract.accept_admin(), Err(Error::NotPendingAdmin));
+            assert!(contract.propose_admin(default_accounts.bob).is_ok());
+            assert_eq!(contract.get_pending_admin(), Some(default_accounts.bob));
+
+            set_caller::<DefaultEnvironment>(default_accounts.charlie);
+            assert_eq!(contract.accept_admin(), Err(Error::NotPendingAdmin));
+            set_caller::<DefaultEnvironment>(default_accounts.bob);
+            assert!(contract.accept_admin().is_ok());
+            assert_eq!(contract.get_pending_admin(), None);
+            assert_eq!(contract.admin, default_accounts.bob);
+        }
+
+        #[ink::test]
+        fn green_points_curve_tapers_at_breakpoints() {
+            let (_, mut contract) = default_setup();
+            assert_eq!(contract.calculate_green_points(2_000_000), 200_000_000);
+            contract.set_green_points_curve_enabled(true).unwrap();
+
+            assert_eq!(contract.calculate_green_points(100_000), 10_000_000);
+            assert_eq!(contract.calculate_green_points(100_001), 10_000_050);
+            assert_eq!(contract.calculate_green_points(1_000_000), 55_000_000);
+            assert_eq!(contract.calculate_green_points(1_000_001), 55_000_010);
+            assert_eq!(contract.calculate_green_points(2_000_000), 65_000_000);
+        }
+
+        #[ink::test]
+        fn curve_prices_grants_and_payments_alike() {
+            let (accounts, mut contract) = default_setup();
+            set_block_time(0);
+            contract.set_green_points_curve_enabled(true).unwrap();
+            // a 2_000_000 payment funds 320_000 of green points
+            let (_, funding) = contract.split_payment(2_000_000);
+            assert_eq!(funding, 320_000);
+
+            set_caller::<DefaultEnvironment>(accounts.bob);
+            let granted = contract
+                .give_green_points_internal(accounts.django, funding)
+                .unwrap();
+            let paid = contract
+                .mint_payment_points(accounts.eve, accounts.bob, 2_000_000)
+                .unwrap();
+            assert_eq!(granted, paid);
+            assert_eq!(paid.consumer, 65_000_000);
+            assert_eq!(paid.merchant, 10_400_000);
+        }
+
+        #[ink::test]
+        fn green_points_curve_can_be_reconfigured() {
+            let (default_accounts, mut contract) = default_setup();
+            contract.set_green_points_curve_enabled(true).unwrap();
+            assert_eq!(contract.calculate_green_points(200_000), 15_000_000);
+
+            assert_eq!(
+                contract.set_green_points_curve(vec![(10, 100)]),
+                Err(Error::InvalidGreenPointsCurve)
+            );
+            assert_eq!(
+                contract.set_green_points_curve(vec![(0, 100), (50, 20), (50, 10)]),
+                Err(Error::InvalidGreenPointsCurve)
+            );
+            contract
+                .set_green_points_curve(vec![(0, 100), (50_000, 20)])
+                .unwrap();
+            assert_eq!(contract.calculate_green_points(200_000), 8_000_000);
+
+            set_caller::<DefaultEnvironment>(default_accounts.bob);
+            assert_eq!(
+                contract.set_green_points_curve(vec![(0, 1)]),
+                Err(Error::OnlyAdmin)
+            );
+            assert_eq!(
+                contract.set_green_points_curve_enabled(false),
+                Err(Error::OnlyAdmin)
+            );
+        }
+
+        #[ink::test]
+        fn low_amm_liquidity_is_rejected_early() {
+            let (default_accounts, mut contract) = default_setup();
+            assert_eq!(
+                D9MerchantMining::check_amm_liquidity(999, 1_000),
+                Err(Error::AMMLiquidityTooLow)
+            );
+            assert_eq!(D9MerchantMining::check_amm_liquidity(1_000, 1_000), Ok(()));
+            // disabled by default, so no amm call is made
+            assert_eq!(contract.ensure_amm_liquidity(), Ok(()));
+
+            set_caller::<DefaultEnvironment>(default_accounts.bob);
+            assert_eq!(
+                contract.set_min_amm_usdt_liquidity(1_000),
+                Err(Error::OnlyAdmin)
+            );
+            set_caller::<DefaultEnvironment>(default_accounts.alice);
+            assert!(contract.set_min_amm_usdt_liquidity(1_000).is_ok());
+            assert_eq!(contract.get_min_amm_usdt_liquidity(), 1_000);
+        }
+
+        #[ink::test]
+        fn conversion_min_out_follows_the_slippage_setting() {
+            let (default_accounts, mut contract) = default_setup();
+            assert_eq!(contract.get_conversion_slippage_permille(), 10);
+            assert_eq!(D9MerchantMining::calc_min_d9_out(1_000_000, 10), 990_000);
+            assert_eq!(D9MerchantMining::calc_min_d9_out(999, 10), 989);
+            assert_eq!(D9MerchantMining::calc_min_d9_out(1_000_000, 0), 1_000_000);
+            assert_eq!(D9MerchantMining::calc_min_d9_out(1_000_000, 1000), 0);
+
+            set_caller::<DefaultEnvironment>(default_accounts.bob);
+            assert_eq!(
+                contract.set_conversion_slippage_permille(50),
+                Err(Error::OnlyAdmin)
+            );
+            set_caller::<DefaultEnvironment>(default_accounts.alice);
+            assert_eq!(
+                contract.set_conversion_slippage_permille(1001),
+                Err(Error::InvalidSlippage)
+            );
+            assert!(contract.set_conversion_slippage_permille(50).is_ok());
+            assert_eq!(contract.get_conversion_slippage_permille(), 50);
+        }
+
+        #[ink::test]
+        fn amm_fee_change_after_quote_is_rejected() {
+            // the merchant priced the payment while the amm charged 1%
+            let expected_fee_percent = 1;
+            assert_eq!(
+                D9MerchantMining::check_amm_fee(expected_fee_percent, 1),
+                Ok(())
+            );
+            // the admin raised the fee before the payment landed
+            assert_eq!(
+                D9MerchantMining::check_amm_fee(expected_fee_percent, 2),
+                Err(Error::AmmFeeChanged)
+            );
+            assert_eq!(
+                D9MerchantMining::check_amm_fee(expected_fee_percent, 0),
+                Err(Error::AmmFeeChanged)
+            );
+        }
+
+        #[ink::test]
+        fn payment_settles_red_points_without_redeeming() {
+            let (default_accounts, mut contract) = default_setup();
+            set_block_time(0);
+            let mut account = Account::new(0);
+            account.green_points = 200_000_000;
+            contract.accounts.insert(default_accounts.bob, &account);
+            // ten and a half days of accrual on 200_000_000 green points
+            move_time_forward(86_400_000 * 21 / 2);
+            let redeemable_before = contract.get_redeemable_red_points(default_accounts.bob);
+            assert_eq!(redeemable_before, 1_000_000);
+
+            // used to redeem through the mining pool, which can't run off-chain
+            assert!(contract
+                .add_green_points(default_accounts.bob, 100_000_000, true)
+                .is_ok());
+            assert_eq!(ink::env::test::recorded_events().count(), 1);
+            let account = contract.accounts.get(default_accounts.bob).unwrap();
+            assert_eq!(account.green_points, 300_000_000);
+            assert_eq!(account.last_conversion, None);
+            // the new points don't accrue retroactively
+            assert_eq!(
+                contract.get_redeemable_red_points(default_accounts.bob),
+                redeemable_before
+            );
+
+            // the half day carried over completes, the new balance accrues from day ten
+            move_time_forward(86_400_000 / 2);
+            assert_eq!(
+                contract.get_redeemable_red_points(default_accounts.bob),
+                redeemable_before + 150_000
+            );
+        }
+
+        #[ink::test]
+        fn set_milliseconds_day_only_admin_and_nonzero() {
+            let (default_accounts, mut contract) = default_setup();
+            set_caller::<DefaultEnvironment>(default_accounts.bob);
+            assert_eq!(
+                contract.set_milliseconds_day(3_600_000),
+                Err(Error::OnlyAdmin)
+            );
+            set_caller::<DefaultEnvironment>(default_accounts.alice);
+            assert_eq!(
+                contract.set_milliseconds_day(0),
+                Err(Error::InvalidMillisecondsDay)
+            );
+            assert!(contract.set_milliseconds_day(3_600_000).is_ok());
+            assert_eq!(contract.get_milliseconds_day(), 3_600_000);
+        }
+
+        #[ink::test]
+        fn red_point_accrual_scales_with_milliseconds_day() {
+            let (default_accounts, mut contract) = default_setup();
             init_calling_env(default_accounts.alice);
-            let account: Account = Account {
-                green_points: 200000000,
-                relationship_factors: (0, 0),
-                last_conversion: None,
-                redeemed_usdt: 0,
-                redeemed_d9: 0,
-                created_at: 0,
+            set_block_time(0);
+            let green_points: Balance = 200_000_000;
+            move_time_forward(86_400_000);
+            let daily_accrual = contract.calc_red_points_from_time(green_points, 0);
+            assert_eq!(daily_accrual, 100_000);
+
+            // one "day" per hour => 24 accrual periods in the same elapsed time
+            contract.set_milliseconds_day(3_600_000).unwrap();
+            let hourly_accrual = contract.calc_red_points_from_time(green_points, 0);
+            assert_eq!(hourly_accrual, daily_accrual * 24);
+        }
+
+        #[ink::test]
+        fn hourly_accrual_credits_partial_days() {
+            let (default_accounts, mut contract) = default_setup();
+            init_calling_env(default_accounts.alice);
+            set_block_time(0);
+            let start = ink::env::block_timestamp::<DefaultEnvironment>();
+            let green_points: Balance = 200_000_000;
+            move_time_forward(36 * 3_600_000);
+
+            // daily mode drops the half day
+            let daily_accrual = contract.calc_red_points_from_time(green_points, start);
+            assert_eq!(daily_accrual, 100_000);
+
+            assert!(contract.set_accrual_unit_ms(3_600_000).is_ok());
+            let hourly_accrual = contract.calc_red_points_from_time(green_points, start);
+            assert_eq!(hourly_accrual, 150_000);
+            assert!(hourly_accrual > daily_accrual);
+
+            assert_eq!(
+                contract.set_accrual_unit_ms(contract.get_milliseconds_day() + 1),
+                Err(Error::InvalidAccrualUnit)
+            );
+        }
+
+        #[ink::test]
+        fn import_legacy_account_round_trips_export() {
+            let (default_accounts, mut contract) = default_setup();
+            let result =
+                contract.import_legacy_account(default_accounts.django, 500_000, 10, Some(20));
+            assert!(result.is_ok());
+            assert_eq!(
+                contract.export_account(default_accounts.django),
+                Some((default_accounts.django, 500_000, 10, Some(20)))
+            );
+            assert!(contract.is_migrated_from_legacy(default_accounts.django));
+
+            // an account can only be imported once
+            let result = contract.import_legacy_account(default_accounts.django, 1, 1, None);
+            assert_eq!(result, Err(Error::AccountAlreadyExists));
+        }
+
+        #[ink::test]
+        fn import_legacy_account_rejects_existing_accounts() {
+            let (default_accounts, mut contract) = default_setup();
+            contract
+                .accounts
+                .insert(default_accounts.eve, &Account::new(0));
+            let result = contract.import_legacy_account(default_accounts.eve, 1, 1, None);
+            assert_eq!(result, Err(Error::AccountAlreadyExists));
+            assert!(!contract.is_migrated_from_legacy(default_accounts.eve));
+        }
+
+        #[ink::test]
+        fn import_legacy_accounts_is_admin_only_and_capped() {
+            let (default_accounts, mut contract) = default_setup();
+            set_caller::<DefaultEnvironment>(default_accounts.bob);
+            let result = contract.import_legacy_account(default_accounts.django, 500_000, 10, None);
+            assert_eq!(result, Err(Error::OnlyAdmin));
+
+            set_caller::<DefaultEnvironment>(default_accounts.alice);
+            let too_many: Vec<(AccountId, Balance, Timestamp, Option<Timestamp>)> = (0..26u8)
+                .map(|i| (AccountId::from([i; 32]), 1, 0, None))
+                .collect();
+            assert_eq!(
+                contract.import_legacy_accounts(too_many),
+                Err(Error::ImportBatchTooLarge)
+            );
+            let batch: Vec<(AccountId, Balance, Timestamp, Option<Timestamp>)> = (0..25u8)
+                .map(|i| (AccountId::from([i; 32]), 1, 0, None))
+                .collect();
+            assert!(contract.import_legacy_accounts(batch).is_ok());
+            assert!(contract.is_migrated_from_legacy(AccountId::from([24u8; 32])));
+        }
+
+        #[ink::test]
+        fn merchant_share_splits_and_grants_consistently() {
+            let (_, mut contract) = default_setup();
+            assert_eq!(contract.get_merchant_share_permille(), 840);
+            // the legacy 84/16 numbers
+            assert_eq!(contract.split_payment(10_000), (8_400, 1_600));
+            assert_eq!(
+                contract.calc_granted_green_points(1_600),
+                (1_000_000, 160_000)
+            );
+
+            for share in [500u32, 840, 900, 990] {
+                assert!(contract.set_merchant_share_permille(share).is_ok());
+                for usdt_amount in [1u128, 999, 10_000, 123_456_789] {
+                    let (merchant_payment, funding) = contract.split_payment(usdt_amount);
+                    assert_eq!(merchant_payment + funding, usdt_amount);
+
+                    // granting points for the funding slice issues what paying in full would
+                    let (consumer_points, merchant_points) =
+                        contract.calc_granted_green_points(funding);
+                    let full_payment_points = contract.calculate_green_points(usdt_amount);
+                    // within one usdt unit of rounding, scaled back up
+                    let tolerance = 100 * (1000 / (1000 - share) as u128 + 1);
+                    assert!(consumer_points.abs_diff(full_payment_points) <= tolerance);
+                    assert_eq!(
+                        merchant_points,
+                        Perbill::from_rational(1000 - share, 1000).mul_floor(consumer_points)
+                    );
+                }
+            }
+        }
+
+        #[ink::test]
+        fn merchant_share_is_bounded_and_admin_only() {
+            let (default_accounts, mut contract) = default_setup();
+            assert_eq!(
+                contract.set_merchant_share_permille(499),
+                Err(Error::InvalidMerchantShare)
+            );
+            assert_eq!(
+                contract.set_merchant_share_permille(991),
+                Err(Error::InvalidMerchantShare)
+            );
+            assert!(contract.set_merchant_share_permille(900).is_ok());
+            assert_eq!(ink::env::test::recorded_events().count(), 1);
+
+            set_caller::<DefaultEnvironment>(default_accounts.bob);
+            assert_eq!(
+                contract.set_merchant_share_permille(840),
+                Err(Error::OnlyAdmin)
+            );
+            assert_eq!(contract.get_merchant_share_permille(), 900);
+        }
+
+        #[ink::test]
+        fn parent_bonus_skips_ancestors_without_an_account() {
+            let (accounts, mut contract) = default_setup();
+            for ancestor in [accounts.charlie, accounts.django] {
+                contract
+                    .import_legacy_account(ancestor, 1_000, 0, None)
+                    .unwrap();
+            }
+            let events_before = ink::env::test::recorded_events().count();
+
+            // bob, the parent, never had an account
+            contract.update_ancestors_coefficients(
+                accounts.alice,
+                &[accounts.bob, accounts.charlie, accounts.django],
+                1_000,
+            );
+            assert!(contract.accounts.get(accounts.bob).is_none());
+            let charlie = contract.accounts.get(accounts.charlie).unwrap();
+            assert_eq!(charlie.relationship_factors, (100, 0));
+            let django = contract.accounts.get(accounts.django).unwrap();
+            assert_eq!(django.relationship_factors, (0, 10));
+            assert_eq!(ink::env::test::recorded_events().count(), events_before + 1);
+
+            // nobody in the chain has an account, nothing to credit
+            contract.update_ancestors_coefficients(
+                accounts.alice,
+                &[accounts.bob, accounts.eve],
+                1_000,
+            );
+            assert_eq!(ink::env::test::recorded_events().count(), events_before + 1);
+        }
+
+        #[ink::test]
+        fn ancestor_bonus_skips_gaps_up_to_the_depth_bound() {
+            fn factors(
+                contract: &D9MerchantMining,
+                account_id: AccountId,
+            ) -> Option<(Balance, Balance)> {
+                contract
+                    .accounts
+                    .get(account_id)
+                    .map(|account| account.relationship_factors)
+            }
+            let (accounts, mut contract) = default_setup();
+            for ancestor in [accounts.bob, accounts.django, accounts.frank] {
+                contract
+                    .import_legacy_account(ancestor, 1_000, 0, None)
+                    .unwrap();
+            }
+
+            // charlie and eve in the middle have no account
+            contract.update_ancestors_coefficients(
+                accounts.alice,
+                &[
+                    accounts.bob,
+                    accounts.charlie,
+                    accounts.django,
+                    accounts.eve,
+                    accounts.frank,
+                ],
+                1_000,
+            );
+            assert_eq!(factors(&contract, accounts.bob), Some((100, 0)));
+            assert_eq!(factors(&contract, accounts.charlie), None);
+            assert_eq!(factors(&contract, accounts.django), Some((0, 10)));
+            assert_eq!(factors(&contract, accounts.eve), None);
+            assert_eq!(factors(&contract, accounts.frank), Some((0, 10)));
+
+            // frank past the depth bound gets nothing
+            let mut chain = vec![accounts.charlie; MAX_ANCESTOR_DEPTH];
+            chain[0] = accounts.bob;
+            chain.push(accounts.frank);
+            contract.update_ancestors_coefficients(accounts.alice, &chain, 1_000);
+            assert_eq!(factors(&contract, accounts.bob), Some((200, 0)));
+            assert_eq!(factors(&contract, accounts.frank), Some((0, 10)));
+        }
+
+        #[ink::test]
+        fn subscription_tiers_apply_their_own_fee_and_threshold() {
+            let (accounts, mut contract) = default_setup();
+            set_block_time(ONE_MONTH_MILLISECONDS);
+            let basic = SubscriptionTier::Basic as u8;
+            let pro = SubscriptionTier::Pro as u8;
+            contract
+                .import_legacy_account(accounts.django, 200_000_000, 0, None)
+                .unwrap();
+            contract
+                .import_legacy_account(accounts.eve, 600_000_000, 0, None)
+                .unwrap();
+
+            // only the basic tier exists until the admin sets up another
+            assert_eq!(
+                contract.get_tier(basic),
+                Some((
+                    contract.subscription_fee,
+                    DEFAULT_SUBSCRIPTION_POINT_THRESHOLD
+                ))
+            );
+            assert_eq!(
+                contract.check_tier_subscription(accounts.eve, 10_000, pro),
+                Err(Error::UnknownSubscriptionTier)
+            );
+            assert_eq!(
+                contract.set_tier(SubscriptionTier::Pro, 0, 500_000_000),
+                Err(Error::InvalidSubscriptionFee)
+            );
+            assert_eq!(
+                contract.set_tier(SubscriptionTier::Pro, 5_000, 500_000_000),
+                Ok(())
+            );
+            assert_eq!(
+                contract.check_tier_subscription(accounts.eve, 10_000, 2),
+                Err(Error::UnknownSubscriptionTier)
+            );
+
+            // django has the points for basic only
+            assert_eq!(
+                contract.check_tier_subscription(accounts.django, 5_000, pro),
+                Err(Error::PointsInsufficientToCreateMerchantAccount)
+            );
+            let basic_fee = contract
+                .check_tier_subscription(accounts.django, 3_000, basic)
+                .unwrap();
+            assert_eq!(basic_fee, 1_000);
+            let now = ink::env::block_timestamp::<DefaultEnvironment>();
+            assert_eq!(
+                contract.update_subscription(accounts.django, 3_000, basic_fee),
+                Ok(now + 3 * ONE_MONTH_MILLISECONDS)
+            );
+
+            // the same payment buys fewer months on pro
+            assert_eq!(
+                contract.check_tier_subscription(accounts.eve, 4_999, pro),
+                Err(Error::InsufficientPayment)
+            );
+            let pro_fee = contract
+                .check_tier_subscription(accounts.eve, 10_000, pro)
+                .unwrap();
+            assert_eq!(pro_fee, 5_000);
+            assert_eq!(
+                contract.update_subscription(accounts.eve, 10_000, pro_fee),
+                Ok(now + 2 * ONE_MONTH_MILLISECONDS)
+            );
+
+            set_caller::<DefaultEnvironment>(accounts.bob);
+            assert_eq!(
+                contract.set_tier(SubscriptionTier::Basic, 1, 0),
+                Err(Error::OnlyAdmin)
+            );
+        }
+
+        #[ink::test]
+        fn merchant_directory_lists_active_merchants_once() {
+            let (default_accounts, mut contract) = default_setup();
+            set_block_time(ONE_MONTH_MILLISECONDS);
+            let fee = contract.subscription_fee;
+            assert!(contract
+                .update_subscription(default_accounts.bob, fee, fee)
+                .is_ok());
+            assert!(contract
+                .update_subscription(default_accounts.charlie, fee, fee)
+                .is_ok());
+            // renewing doesn't add a second entry
+            assert!(contract
+                .update_subscription(default_accounts.bob, fee, fee)
+                .is_ok());
+            assert_eq!(contract.get_merchant_count(), 2);
+
+            let page = contract.get_active_merchants_paged(0, 10);
+            assert_eq!(page.len(), 2);
+            assert_eq!(page[0].0, default_accounts.bob);
+            assert_eq!(page[1].0, default_accounts.charlie);
+            assert_eq!(contract.get_active_merchants_paged(1, 1).len(), 1);
+            assert!(contract.get_active_merchants_paged(2, 10).is_empty());
+
+            // charlie lapses, bob paid for two months
+            move_time_forward(ONE_MONTH_MILLISECONDS + 1);
+            let page = contract.get_active_merchants_paged(0, 10);
+            assert_eq!(page.len(), 1);
+            assert_eq!(page[0].0, default_accounts.bob);
+        }
+
+        #[ink::test]
+        fn merchant_directory_page_is_capped() {
+            let (_, mut contract) = default_setup();
+            let fee = contract.subscription_fee;
+            for i in 0..(MAX_MERCHANT_PAGE + 5) {
+                let merchant = AccountId::from([i as u8; 32]);
+                assert!(contract.update_subscription(merchant, fee, fee).is_ok());
+            }
+            assert_eq!(contract.get_merchant_count(), MAX_MERCHANT_PAGE + 5);
+            assert_eq!(
+                contract.get_active_merchants_paged(0, u32::MAX).len(),
+                MAX_MERCHANT_PAGE as usize
+            );
+            assert_eq!(
+                contract
+                    .get_active_merchants_paged(MAX_MERCHANT_PAGE, u32::MAX)
+                    .len(),
+                5
+            );
+        }
+
+        #[ink::test]
+        fn subscription_payments_are_recorded_in_order() {
+            let (default_accounts, mut contract) = default_setup();
+            set_block_time(ONE_MONTH_MILLISECONDS);
+            let first_paid_at = ink::env::block_timestamp::<DefaultEnvironment>();
+            let fee = contract.subscription_fee;
+            let first_expiry = contract
+                .update_subscription(default_accounts.bob, fee, fee)
+                .unwrap();
+            move_time_forward(1_000);
+            let second_paid_at = ink::env::block_timestamp::<DefaultEnvironment>();
+            let second_expiry = contract
+                .update_subscription(default_accounts.bob, 2 * fee, fee)
+                .unwrap();
+            assert!(contract
+                .update_subscription(default_accounts.charlie, fee, fee)
+                .is_ok());
+
+            assert_eq!(
+                contract.get_subscription_payment_count(default_accounts.bob),
+                2
+            );
+            assert_eq!(
+                contract.get_subscription_payments(default_accounts.bob, 0, 10),
+                vec![
+                    (fee, first_paid_at, first_expiry),
+                    (2 * fee, second_paid_at, second_expiry),
+                ]
+            );
+            // the second payment extends the first
+            assert_eq!(
+                second_expiry,
+                first_expiry + 2 * 30 * contract.milliseconds_day
+            );
+            assert_eq!(contract.get_total_subscription_revenue(), 4 * fee);
+            assert_eq!(
+                contract.get_subscription_payments(default_accounts.django, 0, 10),
+                vec![]
+            );
+        }
+
+        #[ink::test]
+        fn subscription_payment_pages_are_capped() {
+            let (default_accounts, mut contract) = default_setup();
+            let fee = contract.subscription_fee;
+            for _ in 0..(MAX_SUBSCRIPTION_PAYMENT_PAGE + 5) {
+                assert!(contract
+                    .update_subscription(default_accounts.bob, fee, fee)
+                    .is_ok());
+            }
+            let merchant = default_accounts.bob;
+            assert_eq!(
+                contract
+                    .get_subscription_payments(merchant, 0, u32::MAX)
+                    .len(),
+                MAX_SUBSCRIPTION_PAYMENT_PAGE as usize
+            );
+            let tail =
+                contract.get_subscription_payments(merchant, MAX_SUBSCRIPTION_PAYMENT_PAGE, 10);
+            assert_eq!(tail.len(), 5);
+            assert_eq!(
+                tail.last().map(|payment| payment.2),
+                contract.get_expiry(merchant).ok()
+            );
+            assert_eq!(contract.get_subscription_payments(merchant, 2, 3).len(), 3);
+            assert!(contract
+                .get_subscription_payments(merchant, u32::MAX, 10)
+                .is_empty());
+        }
+
+        #[ink::test]
+        fn refused_pool_forwards_are_queued_and_flushed() {
+            let (default_accounts, mut contract) = default_setup();
+            let first = contract.defer_pool_forward(default_accounts.bob, 1_000);
+            let second = contract.defer_pool_forward(default_accounts.charlie, 2_000);
+            assert_eq!((first, second), (0, 1));
+            assert_eq!(contract.get_pending_pool_forward_range(), (0, 2));
+            assert_eq!(ink::env::test::recorded_events().count(), 2);
+
+            // the pool is still being upgraded, nothing moves
+            let flushed =
+                contract.flush_pending_forwards_with(10, |_, _, _| Err(Error::NotCallable));
+            assert_eq!(flushed, 0);
+            assert_eq!(contract.get_pending_pool_forward_range(), (0, 2));
+            assert_eq!(
+                contract.get_pending_pool_forward(0),
+                Some((default_accounts.bob, 1_000))
+            );
+
+            let mut delivered = Vec::new();
+            let flushed = contract.flush_pending_forwards_with(1, |_, merchant, d9| {
+                delivered.push((merchant, d9));
+                Ok(())
+            });
+            assert_eq!(flushed, 1);
+            assert_eq!(delivered, vec![(default_accounts.bob, 1_000)]);
+            assert_eq!(contract.get_pending_pool_forward(0), None);
+            assert_eq!(contract.get_pending_pool_forward_range(), (1, 2));
+        }
+
+        #[ink::test]
+        fn pool_refusals_count_as_failed_forwards() {
+            assert_eq!(
+                D9MerchantMining::settle_pool_forward(Ok(Ok(Ok(())))),
+                Ok(())
+            );
+            assert_eq!(
+                D9MerchantMining::settle_pool_forward(Ok(Ok(Err(Error::NotCallable)))),
+                Err(Error::SendingD9ToMiningPool)
+            );
+            assert_eq!(
+                D9MerchantMining::settle_pool_forward(Ok(Err(ink::LangError::CouldNotReadInput))),
+                Err(Error::SendingD9ToMiningPool)
+            );
+            assert!(
+                D9MerchantMining::settle_pool_forward(Err(ink::env::Error::CalleeTrapped)).is_err()
+            );
+        }
+
+        #[ink::test]
+        fn flushing_pool_forwards_is_idempotent() {
+            let (default_accounts, mut contract) = default_setup();
+            contract.defer_pool_forward(default_accounts.bob, 1_000);
+            contract.defer_pool_forward(default_accounts.bob, 2_000);
+
+            let mut delivered = Vec::new();
+            let flushed = contract.flush_pending_forwards_with(10, |_, _, d9| {
+                delivered.push(d9);
+                Ok(())
+            });
+            assert_eq!(flushed, 2);
+            let flushed = contract.flush_pending_forwards_with(10, |_, _, d9| {
+                delivered.push(d9);
+                Ok(())
+            });
+            assert_eq!(flushed, 0);
+            assert_eq!(delivered, vec![1_000, 2_000]);
+            assert_eq!(contract.get_pending_pool_forward_range(), (2, 2));
+
+            // a forward deferred later is picked up by the next flush
+            contract.defer_pool_forward(default_accounts.bob, 3_000);
+            let flushed = contract.flush_pending_forwards_with(10, |_, _, d9| {
+                delivered.push(d9);
+                Ok(())
+            });
+            assert_eq!(flushed, 1);
+            assert_eq!(delivered, vec![1_000, 2_000, 3_000]);
+        }
+
+        #[ink::test]
+        fn d9_payment_event_reports_conversion_figures() {
+            let (default_accounts, contract) = default_setup();
+            // 1% fee, 2_000 d9 bought 99 usdt
+            assert_eq!(
+                D9MerchantMining::calc_d9_payment_figures(2_000, 99, 1),
+                (49_500_000_000_000_000, 20)
+            );
+            // 1_000 whole d9 for 100 whole usdt still reports a rate
+            assert_eq!(
+                D9MerchantMining::calc_d9_payment_figures(1_000_000_000_000_000, 10_000, 0),
+                (10_000_000, 0)
+            );
+            assert_eq!(D9MerchantMining::calc_d9_payment_figures(0, 0, 1), (0, 0));
+
+            contract.emit_d9_payment(default_accounts.bob, default_accounts.charlie, 2_000, 99, 1);
+            let events = ink::env::test::recorded_events().collect::<Vec<_>>();
+            assert_eq!(events.len(), 1);
+            let expected = D9MerchantPaymentSent {
+                merchant: default_accounts.bob,
+                consumer: default_accounts.charlie,
+                amount: 2_000,
+                usdt_received: 99,
+                effective_rate: 49_500_000_000_000_000,
+                amm_fee_estimate: 20,
+            };
+            // the event enum prefixes the variant index to the encoded event
+            assert!(events[0].data.ends_with(&expected.encode()));
+        }
+
+        #[ink::test]
+        fn green_points_result_is_versioned() {
+            let result = GreenPointsResult {
+                result_version: GREEN_POINTS_RESULT_VERSION,
+                merchant: 10,
+                consumer: 100,
+                usdt_received: Some(99),
             };
+            let decoded = GreenPointsResult::decode(&mut &result.encode()[..]).unwrap();
+            assert_eq!(decoded, result);
+            assert_eq!(decoded.result_version, 1);
+        }
+
+        #[ink::test]
+        fn failed_conversions_are_held_for_retry() {
+            let (default_accounts, mut contract) = default_setup();
+            let first = contract.record_failed_conversion(default_accounts.bob, 1_000);
+            let second = contract.record_failed_conversion(default_accounts.bob, 1_000);
+            assert_ne!(first, second);
+            assert_eq!(
+                first,
+                D9MerchantMining::conversion_payment_id(default_accounts.bob, 0)
+            );
+            assert_eq!(contract.get_failed_conversion_count(), 2);
+            assert_eq!(
+                contract.get_failed_conversion(first),
+                Some((default_accounts.bob, 1_000))
+            );
+
+            // only the merchant or the admin may retry, and only known payments
+            set_caller::<DefaultEnvironment>(default_accounts.charlie);
+            assert_eq!(
+                contract.retry_conversion(first),
+                Err(Error::OnlyMerchantOrAdmin)
+            );
+            assert_eq!(
+                contract.retry_conversion([0u8; 32]),
+                Err(Error::NoFailedConversion)
+            );
+            assert_eq!(
+                contract.get_failed_conversion(first),
+                Some((default_accounts.bob, 1_000))
+            );
+        }
+
+        #[ink::test]
+        fn redeem_for_requires_approved_delegate() {
+            let (default_accounts, mut contract) = default_setup();
+            let beneficiary = default_accounts.bob;
+            let delegate = default_accounts.charlie;
+            set_block_time(ONE_MONTH_MILLISECONDS);
+            let mut account = Account::new(0);
+            account.green_points = 200_000_000;
+            // redeemed recently, so the redemption stops at the lockout before paying out
+            account.last_conversion = Some(ONE_MONTH_MILLISECONDS);
+            contract.accounts.insert(beneficiary, &account);
+
+            set_caller::<DefaultEnvironment>(delegate);
+            assert_eq!(
+                contract.redeem_d9_for(beneficiary),
+                Err(Error::NotRedeemDelegate)
+            );
+
+            set_caller::<DefaultEnvironment>(beneficiary);
+            contract.approve_redeem_delegate(delegate, true);
+            assert!(contract.is_redeem_delegate(beneficiary, delegate));
+            assert!(!contract.is_redeem_delegate(delegate, beneficiary));
+
+            // the delegate now reaches the beneficiary's account; the delegate has none itself
+            set_caller::<DefaultEnvironment>(delegate);
+            assert_eq!(
+                contract.redeem_d9_for(beneficiary),
+                Err(Error::NothingToRedeem)
+            );
+            assert_eq!(contract.redeem_d9(), Err(Error::NoAccountFound));
+
+            set_caller::<DefaultEnvironment>(beneficiary);
+            contract.approve_redeem_delegate(delegate, false);
+            set_caller::<DefaultEnvironment>(delegate);
+            assert_eq!(
+                contract.redeem_d9_for(beneficiary),
+                Err(Error::NotRedeemDelegate)
+            );
+        }
+
+        #[ink::test]
+        fn expiry_reminders_dedup_per_expiry() {
+            let (default_accounts, mut contract) = default_setup();
+            let merchant = default_accounts.bob;
+            let seven_days = 7 * contract.get_milliseconds_day();
+            contract.merchant_expiry.insert(merchant, &seven_days);
+
+            assert!(contract.emit_expiry_reminders(vec![merchant]).is_ok());
+            assert_eq!(ink::env::test::recorded_events().count(), 1);
+            assert!(contract.emit_expiry_reminders(vec![merchant]).is_ok());
+            assert_eq!(ink::env::test::recorded_events().count(), 1);
+
+            // a renewal is a new expiry value and gets its own reminder
+            contract.merchant_expiry.insert(merchant, &(seven_days - 1));
+            assert!(contract.emit_expiry_reminders(vec![merchant]).is_ok());
+            assert_eq!(ink::env::test::recorded_events().count(), 2);
+
+            let too_many: Vec<AccountId> = (0..51u8).map(|i| AccountId::from([i; 32])).collect();
+            assert_eq!(
+                contract.emit_expiry_reminders(too_many),
+                Err(Error::ReminderBatchTooLarge)
+            );
+        }
+
+        #[ink::test]
+        fn expiry_reminders_respect_seven_day_window() {
+            let (default_accounts, mut contract) = default_setup();
+            let seven_days = 7 * contract.get_milliseconds_day();
+            set_block_time(1_000);
+            let now = ink::env::block_timestamp::<DefaultEnvironment>();
+            // exactly on the horizon, just past it, and already expired
+            contract
+                .merchant_expiry
+                .insert(default_accounts.bob, &(now + seven_days));
+            contract
+                .merchant_expiry
+                .insert(default_accounts.charlie, &(now + seven_days + 1));
+            contract
+                .merchant_expiry
+                .insert(default_accounts.django, &(now - 1));
+
+            assert!(contract
+                .emit_expiry_reminders(vec![
+                    default_accounts.bob,
+                    default_accounts.charlie,
+                    default_accounts.django,
+                    default_accounts.eve,
+                ])
+                .is_ok());
+            assert_eq!(ink::env::test::recorded_events().count(), 1);
+            assert_eq!(
+                contract.last_reminded_expiry.get(default_accounts.bob),
+                Some(now + seven_days)
+            );
+            assert_eq!(
+                contract.last_reminded_expiry.get(default_accounts.charlie),
+                None
+            );
+        }
+
+        #[ink::test]
+        fn settle_received_usdt_uses_actual_delta() {
+            let (default_accounts, contract) = default_setup();
+            // a token taking a 1% fee on transfer only delivers 990 of 1_000
+            let received = contract.settle_received_usdt(default_accounts.bob, 1_000, 5_000, 5_990);
+            assert_eq!(received, 990);
+            assert_eq!(ink::env::test::recorded_events().count(), 1);
+
+            let received = contract.settle_received_usdt(default_accounts.bob, 1_000, 5_990, 6_990);
+            assert_eq!(received, 1_000);
+            assert_eq!(ink::env::test::recorded_events().count(), 1);
+        }
+
+        /// answers `add_voting_interests` with `status` and counts the calls
+        struct MockVotingExtension {
+            status: u32,
+            calls: std::rc::Rc<core::cell::Cell<u32>>,
+        }
+
+        impl ink::env::test::ChainExtension for MockVotingExtension {
+            fn func_id(&self) -> u32 {
+                9
+            }
+
+            fn call(&mut self, _input: &[u8], output: &mut Vec<u8>) -> u32 {
+                self.calls.set(self.calls.get() + 1);
+                Ok::<(), ()>(()).encode_to(output);
+                self.status
+            }
+        }
+
+        #[ink::test]
+        fn redeem_votes_follow_the_toggle() {
+            let (_, mut contract) = default_setup();
+            let calls = std::rc::Rc::new(core::cell::Cell::new(0));
+            ink::env::test::register_chain_extension(MockVotingExtension {
+                status: 0,
+                calls: calls.clone(),
+            });
+            let d9_amount: Balance = 5_000_000_000_000;
+            let redeemer = AccountId::from([7u8; 32]);
+
+            assert!(!contract.get_grant_votes_on_redeem());
+            assert!(!contract.grant_redeem_votes(redeemer, d9_amount));
+            assert_eq!(calls.get(), 0);
+
+            assert!(contract.set_grant_votes_on_redeem(true).is_ok());
+            assert!(contract.grant_redeem_votes(redeemer, d9_amount));
+            assert_eq!(calls.get(), 1);
+            // less than one d9 earns no votes
+            assert!(!contract.grant_redeem_votes(redeemer, 999_999_999_999));
+            assert_eq!(calls.get(), 1);
+
+            // a failing grant is swallowed
+            ink::env::test::register_chain_extension(MockVotingExtension {
+                status: 1,
+                calls: calls.clone(),
+            });
+            assert!(contract.grant_redeem_votes(redeemer, d9_amount));
+            assert_eq!(calls.get(), 2);
+        }
+
+        #[ink::test]
+        fn points_velocity_is_uncapped_by_default() {
+            let (accounts, mut contract) = default_setup();
             set_block_time(0);
-            contract.accounts.insert(default_accounts.alice, &account);
-            move_time_forward(100_000_000);
+            assert_eq!(contract.get_points_velocity_limits(), (0, 0));
+            assert!(contract
+                .record_points_velocity(accounts.django, accounts.eve, Balance::MAX / 2)
+                .is_ok());
+            assert!(contract
+                .record_points_velocity(accounts.django, accounts.eve, 1_000)
+                .is_ok());
+            // nothing is tracked while both limits are off
+            assert_eq!(contract.get_points_issued_today(accounts.django), 0);
+        }
 
-            //redeem d9
-            set_caller::<DefaultEnvironment>(default_accounts.alice);
-            let redemption_result = contract.redeem_d9();
-            println!("green_points_result: {:?}", redemption_result);
-            assert!(redemption_result.is_ok());
+        #[ink::test]
+        fn points_velocity_resets_each_day() {
+            let (accounts, mut contract) = default_setup();
+            set_block_time(0);
+            contract.set_points_velocity_limits(1_000, 0).unwrap();
+            assert!(contract
+                .record_points_velocity(accounts.django, accounts.eve, 600)
+                .is_ok());
+            assert_eq!(
+                contract.record_points_velocity(accounts.django, accounts.frank, 401),
+                Err(Error::PointsVelocityExceeded)
+            );
+            // a rejected issuance is not counted
+            assert_eq!(contract.get_points_issued_today(accounts.django), 600);
+            assert!(contract
+                .record_points_velocity(accounts.django, accounts.frank, 400)
+                .is_ok());
+
+            move_time_forward(contract.get_milliseconds_day());
+            assert_eq!(contract.get_points_issued_today(accounts.django), 0);
+            assert!(contract
+                .record_points_velocity(accounts.django, accounts.eve, 1_000)
+                .is_ok());
+        }
+
+        #[ink::test]
+        fn points_velocity_limits_each_merchant() {
+            let (accounts, mut contract) = default_setup();
+            set_block_time(0);
+            contract.set_points_velocity_limits(1_000, 300).unwrap();
+            assert!(contract
+                .record_points_velocity(accounts.django, accounts.eve, 300)
+                .is_ok());
+            assert_eq!(
+                contract.record_points_velocity(accounts.django, accounts.eve, 1),
+                Err(Error::PointsVelocityExceeded)
+            );
+            assert!(contract
+                .record_points_velocity(accounts.django, accounts.frank, 300)
+                .is_ok());
+            assert_eq!(contract.get_points_issued_today(accounts.django), 600);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(
+                contract.set_points_velocity_limits(0, 0),
+                Err(Error::OnlyAdmin)
+            );
+        }
+
+        #[ink::test]
+        fn refund_claws_back_points_from_both_sides() {
+            let (accounts, mut contract) = default_setup();
+            set_block_time(0);
+            assert!(contract
+                .add_green_points(accounts.django, 150_000, true)
+                .is_ok());
+            assert!(contract
+                .add_green_points(accounts.bob, 20_000, false)
+                .is_ok());
+
+            // 1_000 usdt issued 100_000 consumer and 16_000 merchant points
+            assert_eq!(
+                contract.apply_refund(accounts.bob, accounts.django, 1_000, None),
+                Ok((100_000, 16_000))
+            );
+            assert_eq!(
+                contract.get_account(accounts.django).unwrap().green_points,
+                50_000
+            );
+            assert_eq!(
+                contract.get_account(accounts.bob).unwrap().green_points,
+                4_000
+            );
+            assert_eq!(contract.get_refund_debt(accounts.django), 0);
+            assert_eq!(contract.get_green_points_cap().0, 54_000);
+            assert_eq!(
+                contract.apply_refund(accounts.bob, accounts.django, 0, None),
+                Err(Error::NothingToRefund)
+            );
+        }
+
+        #[ink::test]
+        fn refund_of_redeemed_points_becomes_debt() {
+            let (accounts, mut contract) = default_setup();
+            set_block_time(0);
+            assert!(contract
+                .add_green_points(accounts.django, 30_000, true)
+                .is_ok());
+
+            // the consumer only holds 30_000 of the 100_000 points, the bob merchant account
+            // holds none of its 16_000
+            assert_eq!(
+                contract.apply_refund(accounts.bob, accounts.django, 1_000, None),
+                Ok((100_000, 16_000))
+            );
+            assert_eq!(
+                contract.get_account(accounts.django).unwrap().green_points,
+                0
+            );
+            assert_eq!(contract.get_refund_debt(accounts.django), 70_000);
+            assert_eq!(contract.get_refund_debt(accounts.bob), 16_000);
+            // no account is created for the merchant just to hold the debt
+            assert!(contract.get_account(accounts.bob).is_none());
+            assert_eq!(contract.get_green_points_cap().0, 0);
+
+            // a second refund adds to the debt
+            assert!(contract
+                .apply_refund(accounts.bob, accounts.django, 100, None)
+                .is_ok());
+            assert_eq!(contract.get_refund_debt(accounts.django), 80_000);
+        }
+
+        #[ink::test]
+        fn refund_debt_is_taken_from_future_grants() {
+            let (accounts, mut contract) = default_setup();
+            set_block_time(0);
+            assert!(contract
+                .apply_refund(accounts.bob, accounts.django, 1_000, None)
+                .is_ok());
+            assert_eq!(contract.get_refund_debt(accounts.django), 100_000);
+
+            // grants pay down the debt before anything is credited
+            assert!(contract
+                .add_green_points(accounts.django, 60_000, true)
+                .is_ok());
+            assert_eq!(
+                contract.get_account(accounts.django).unwrap().green_points,
+                0
+            );
+            assert_eq!(contract.get_refund_debt(accounts.django), 40_000);
+            assert!(contract
+                .add_green_points(accounts.django, 50_000, true)
+                .is_ok());
+            assert_eq!(
+                contract.get_account(accounts.django).unwrap().green_points,
+                10_000
+            );
+            assert_eq!(contract.get_refund_debt(accounts.django), 0);
+            assert_eq!(contract.get_green_points_cap().0, 10_000);
+
+            assert!(contract
+                .add_green_points(accounts.django, 5_000, true)
+                .is_ok());
+            assert_eq!(
+                contract.get_account(accounts.django).unwrap().green_points,
+                15_000
+            );
+        }
+
+        #[ink::test]
+        fn refunds_against_a_receipt_stay_within_the_payment() {
+            let (accounts, mut contract) = default_setup();
+            set_block_time(0);
+            contract.record_receipt(accounts.bob, accounts.django, 1_000);
+            contract.record_receipt(accounts.bob, accounts.eve, 500);
+            assert_eq!(
+                contract.get_receipt(accounts.bob, 0),
+                Some((accounts.django, 1_000, 0))
+            );
+            assert_eq!(
+                contract.get_receipt(accounts.bob, 1),
+                Some((accounts.eve, 500, 0))
+            );
+
+            // partial refunds add up to the payment
+            assert!(contract
+                .apply_refund(accounts.bob, accounts.django, 400, Some(0))
+                .is_ok());
+            assert!(contract
+                .apply_refund(accounts.bob, accounts.django, 600, Some(0))
+                .is_ok());
+            assert_eq!(
+                contract.get_receipt(accounts.bob, 0),
+                Some((accounts.django, 1_000, 1_000))
+            );
+            assert_eq!(
+                contract.apply_refund(accounts.bob, accounts.django, 1, Some(0)),
+                Err(Error::RefundExceedsPayment)
+            );
+            assert_eq!(
+                contract.apply_refund(accounts.bob, accounts.eve, 501, Some(1)),
+                Err(Error::RefundExceedsPayment)
+            );
+
+            // receipts belong to one merchant and one consumer
+            assert_eq!(
+                contract.apply_refund(accounts.bob, accounts.django, 100, Some(1)),
+                Err(Error::NoReceiptFound)
+            );
+            assert_eq!(
+                contract.apply_refund(accounts.charlie, accounts.eve, 100, Some(1)),
+                Err(Error::NoReceiptFound)
+            );
+            assert_eq!(
+                contract.apply_refund(accounts.bob, accounts.eve, 100, Some(2)),
+                Err(Error::NoReceiptFound)
+            );
+            // without a receipt the amount isn't checked
+            assert!(contract
+                .apply_refund(accounts.bob, accounts.eve, 10_000, None)
+                .is_ok());
+        }
+
+        #[ink::test]
+        fn payment_points_preview_matches_minted_points() {
+            let (accounts, mut contract) = default_setup();
+            set_block_time(0);
+            let preview = contract.preview_payment_points(1_234);
+            let minted = contract
+                .mint_payment_points(accounts.django, accounts.bob, 1_234)
+                .unwrap();
+            assert_eq!(preview, minted);
+            assert_eq!(
+                contract.get_account(accounts.django).unwrap().green_points,
+                preview.consumer
+            );
+            assert_eq!(
+                contract.get_account(accounts.bob).unwrap().green_points,
+                preview.merchant
+            );
+
+            // the curve changes both sides of the preview
+            contract.set_green_points_curve_enabled(true).unwrap();
+            let curved_preview = contract.preview_payment_points(200_000);
+            assert_eq!(
+                curved_preview,
+                contract
+                    .mint_payment_points(accounts.eve, accounts.bob, 200_000)
+                    .unwrap()
+            );
+            assert_eq!(
+                contract.get_account(accounts.eve).unwrap().green_points,
+                curved_preview.consumer
+            );
+        }
+
+        #[ink::test]
+        fn account_activity_is_touched_by_mutations() {
+            let (accounts, mut contract) = default_setup();
+            let now = || ink::env::block_timestamp::<DefaultEnvironment>();
+            set_block_time(1_000);
+            assert_eq!(contract.get_last_activity(accounts.django), None);
+
+            // payments and grants touch both sides
+            assert!(contract
+                .mint_payment_points(accounts.django, accounts.bob, 1_000)
+                .is_ok());
+            let paid_at = now();
+            assert_eq!(contract.get_last_activity(accounts.django), Some(paid_at));
+            assert_eq!(contract.get_last_activity(accounts.bob), Some(paid_at));
+
+            move_time_forward(1_000);
+            assert!(contract
+                .update_subscription(
+                    accounts.bob,
+                    contract.subscription_fee,
+                    contract.subscription_fee
+                )
+                .is_ok());
+            assert_eq!(contract.get_last_activity(accounts.bob), Some(now()));
+            assert_eq!(contract.get_last_activity(accounts.django), Some(paid_at));
+
+            move_time_forward(1_000);
+            assert!(contract
+                .apply_refund(accounts.bob, accounts.django, 100, None)
+                .is_ok());
+            assert_eq!(contract.get_last_activity(accounts.django), Some(now()));
+            assert_eq!(contract.get_last_activity(accounts.bob), Some(now()));
+
+            move_time_forward(1_000);
+            set_caller::<DefaultEnvironment>(accounts.eve);
+            contract.approve_redeem_delegate(accounts.frank, true);
+            assert_eq!(contract.get_last_activity(accounts.eve), Some(now()));
+            assert_eq!(contract.get_last_activity(accounts.frank), None);
+        }
+
+        #[ink::test]
+        fn untouched_legacy_accounts_report_their_history() {
+            let (accounts, mut contract) = default_setup();
+            set_block_time(10_000);
+            contract
+                .import_legacy_account(accounts.django, 1_000, 500, None)
+                .unwrap();
+            contract
+                .import_legacy_account(accounts.eve, 1_000, 500, Some(2_000))
+                .unwrap();
+            assert_eq!(contract.get_last_activity(accounts.django), Some(500));
+            assert_eq!(contract.get_last_activity(accounts.eve), Some(2_000));
+
+            assert!(contract
+                .add_green_points(accounts.django, 100, true)
+                .is_ok());
+            assert_eq!(
+                contract.get_last_activity(accounts.django),
+                Some(ink::env::block_timestamp::<DefaultEnvironment>())
+            );
+        }
+
+        #[ink::test]
+        fn green_points_cap_bounds_outstanding_points() {
+            let (accounts, mut contract) = default_setup();
+            set_block_time(0);
+            contract.set_max_total_green_points(1_000).unwrap();
+            assert!(contract
+                .add_green_points(accounts.django, 600, true)
+                .is_ok());
+            assert!(contract.add_green_points(accounts.eve, 400, false).is_ok());
+            assert_eq!(contract.get_green_points_cap(), (1_000, 1_000));
+
+            assert_eq!(
+                contract.add_green_points(accounts.django, 1, true),
+                Err(Error::GlobalPointCapReached)
+            );
+            // a rejected grant leaves the account and the total alone
+            assert_eq!(
+                contract.get_account(accounts.django).unwrap().green_points,
+                600
+            );
+            assert_eq!(contract.get_green_points_cap(), (1_000, 1_000));
+
+            contract.set_max_total_green_points(0).unwrap();
+            assert!(contract.add_green_points(accounts.django, 1, true).is_ok());
+            assert_eq!(contract.get_green_points_cap(), (1_001, 0));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(
+                contract.set_max_total_green_points(1_000),
+                Err(Error::OnlyAdmin)
+            );
         }
     }
 
@@ -1095,8 +4194,11 @@ mod d9_merchant_mining {
     mod e2e_tests {
         /// Imports all the definitions from the outer scope so we can use them here.
         use super::*;
+        use d9_usdt_fee_mock::d9_usdt_fee_mock::D9USDTFeeMockRef;
+        use d9_usdt_permit_mock::d9_usdt_permit_mock::D9USDTPermitMockRef;
         /// A helper function used for calling contract messages.
         use ink_e2e::{account_id, build_message, AccountKeyring};
+        use market_maker::market_maker::MarketMakerRef;
         use mining_pool::mining_pool::MiningPool;
         use mining_pool::mining_pool::MiningPoolRef;
         /// The End-to-End test `Result` type.
@@ -1113,5 +4215,1321 @@ mod d9_merchant_mining {
 
             Ok(())
         }
+
+        #[ink_e2e::test]
+        async fn legacy_account_migration_works(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            let legacy_constructor = D9MerchantMiningRef::new(
+                account_id(AccountKeyring::Alice),
+                account_id(AccountKeyring::Bob),
+                account_id(AccountKeyring::Charlie),
+            );
+            let legacy_address = client
+                .instantiate(
+                    "d9_merchant_mining",
+                    &ink_e2e::alice(),
+                    legacy_constructor,
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate legacy merchant mining")
+                .account_id;
+            let new_constructor = D9MerchantMiningRef::new(
+                account_id(AccountKeyring::Alice),
+                account_id(AccountKeyring::Bob),
+                account_id(AccountKeyring::Charlie),
+            );
+            let new_address = client
+                .instantiate(
+                    "d9_merchant_mining",
+                    &ink_e2e::alice(),
+                    new_constructor,
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate merchant mining")
+                .account_id;
+
+            // seed an account on the legacy deployment
+            let user = account_id(AccountKeyring::Dave);
+            let seed_message = build_message::<D9MerchantMiningRef>(legacy_address.clone())
+                .call(|merchant| merchant.import_legacy_account(user, 200_000_000, 0, None));
+            client
+                .call(&ink_e2e::alice(), seed_message, 0, None)
+                .await
+                .expect("failed to seed legacy account");
+
+            // export from legacy, import into new
+            let export_message = build_message::<D9MerchantMiningRef>(legacy_address.clone())
+                .call(|merchant| merchant.export_account(user));
+            let exported = client
+                .call_dry_run(&ink_e2e::alice(), &export_message, 0, None)
+                .await
+                .return_value()
+                .expect("legacy account missing");
+            let (account, green_points, created_at, last_conversion) = exported;
+            let import_message =
+                build_message::<D9MerchantMiningRef>(new_address.clone()).call(|merchant| {
+                    merchant.import_legacy_account(
+                        account,
+                        green_points,
+                        created_at,
+                        last_conversion,
+                    )
+                });
+            client
+                .call(&ink_e2e::alice(), import_message, 0, None)
+                .await
+                .expect("failed to import legacy account");
+
+            let new_export_message = build_message::<D9MerchantMiningRef>(new_address.clone())
+                .call(|merchant| merchant.export_account(user));
+            let imported = client
+                .call_dry_run(&ink_e2e::alice(), &new_export_message, 0, None)
+                .await
+                .return_value();
+            assert_eq!(imported, Some(exported));
+
+            // a second import is rejected
+            let reimport_message =
+                build_message::<D9MerchantMiningRef>(new_address.clone()).call(|merchant| {
+                    merchant.import_legacy_account(
+                        account,
+                        green_points,
+                        created_at,
+                        last_conversion,
+                    )
+                });
+            let reimport_result = client
+                .call_dry_run(&ink_e2e::alice(), &reimport_message, 0, None)
+                .await
+                .return_value();
+            assert_eq!(reimport_result, Err(Error::AccountAlreadyExists));
+
+            // the migrated account is recognized and redeemable on the new contract; without a
+            // wired mining pool the payout leg itself fails
+            let redeem_message = build_message::<D9MerchantMiningRef>(new_address.clone())
+                .call(|merchant| merchant.redeem_d9());
+            let redeem_result = client
+                .call_dry_run(&ink_e2e::dave(), &redeem_message, 0, None)
+                .await
+                .return_value();
+            assert_eq!(redeem_result, Err(Error::RedeemD9TransferFailed));
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn starved_mining_pool_call_fails_its_own_leg(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            let merchant_constructor = D9MerchantMiningRef::new(
+                account_id(AccountKeyring::Alice),
+                account_id(AccountKeyring::Bob),
+                account_id(AccountKeyring::Charlie),
+            );
+            let merchant_address = client
+                .instantiate(
+                    "d9_merchant_mining",
+                    &ink_e2e::alice(),
+                    merchant_constructor,
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate merchant mining")
+                .account_id;
+            let mining_pool_constructor = MiningPoolRef::new(
+                account_id(AccountKeyring::Eve),
+                merchant_address.clone(),
+                account_id(AccountKeyring::Ferdie),
+                account_id(AccountKeyring::Alice),
+            );
+            let mining_pool_address = client
+                .instantiate(
+                    "mining_pool",
+                    &ink_e2e::alice(),
+                    mining_pool_constructor,
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate mining pool")
+                .account_id;
+
+            let change_pool_message =
+                build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                    .call(|merchant| merchant.change_mining_pool(mining_pool_address));
+            client
+                .call(&ink_e2e::alice(), change_pool_message, 0, None)
+                .await
+                .expect("failed to change mining pool");
+            let user = account_id(AccountKeyring::Dave);
+            let seed_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.import_legacy_account(user, 200_000_000, 0, None));
+            client
+                .call(&ink_e2e::alice(), seed_message, 0, None)
+                .await
+                .expect("failed to seed account");
+
+            // a budget far below what the pool needs starves the callee
+            let starve_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.set_call_gas_limit(CallTarget::MiningPool, 1));
+            client
+                .call(&ink_e2e::alice(), starve_message, 0, None)
+                .await
+                .expect("failed to set gas limit");
+
+            // the pool leg runs out of its own budget and surfaces as a contract error, the
+            // merchant contract keeps enough gas to return it
+            let redeem_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.redeem_d9());
+            let redeem_result = client
+                .call_dry_run(&ink_e2e::dave(), &redeem_message, 0, None)
+                .await;
+            assert!(redeem_result.exec_result.result.is_ok());
+            assert_eq!(
+                redeem_result.return_value(),
+                Err(Error::RedeemD9TransferFailed)
+            );
+            Ok(())
+        }
+
+        #[ink_e2e::test(
+            additional_contracts = "../market-maker/Cargo.toml ../mining-pool/Cargo.toml ../tokens/usdt-permit-mock/Cargo.toml"
+        )]
+        async fn failed_conversion_completes_on_retry(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            let alice = account_id(AccountKeyring::Alice);
+            let bob = account_id(AccountKeyring::Bob);
+            let usdt_address = client
+                .instantiate(
+                    "d9_usdt_permit_mock",
+                    &ink_e2e::alice(),
+                    D9USDTPermitMockRef::new(100_000_000_000_000),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate usdt")
+                .account_id;
+            let amm_address = client
+                .instantiate(
+                    "market_maker",
+                    &ink_e2e::alice(),
+                    MarketMakerRef::new(usdt_address, 1, 100),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate market maker")
+                .account_id;
+            let merchant_address = client
+                .instantiate(
+                    "d9_merchant_mining",
+                    &ink_e2e::alice(),
+                    D9MerchantMiningRef::new(amm_address, alice, usdt_address),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate merchant mining")
+                .account_id;
+            let mining_pool_address = client
+                .instantiate(
+                    "mining_pool",
+                    &ink_e2e::alice(),
+                    MiningPoolRef::new(
+                        account_id(AccountKeyring::Eve),
+                        merchant_address,
+                        account_id(AccountKeyring::Ferdie),
+                        amm_address,
+                    ),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate mining pool")
+                .account_id;
+            let change_pool_message =
+                build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                    .call(|merchant| merchant.change_mining_pool(mining_pool_address));
+            client
+                .call(&ink_e2e::alice(), change_pool_message, 0, None)
+                .await
+                .expect("failed to change mining pool");
+
+            // the mock's permit stands in for approve
+            for spender in [amm_address, merchant_address] {
+                let permit_message = build_message::<D9USDTPermitMockRef>(usdt_address.clone())
+                    .call(|usdt| {
+                        usdt.permit(
+                            alice,
+                            spender,
+                            50_000_000_000_000,
+                            u64::MAX,
+                            27,
+                            [0u8; 32],
+                            [0u8; 32],
+                        )
+                    });
+                client
+                    .call(&ink_e2e::alice(), permit_message, 0, None)
+                    .await
+                    .expect("permit failed");
+            }
+            let add_liquidity_message = build_message::<MarketMakerRef>(amm_address.clone())
+                .call(|market_maker| market_maker.add_liquidity(5_000_000_000_000));
+            client
+                .call(
+                    &ink_e2e::alice(),
+                    add_liquidity_message,
+                    500_000_000_000,
+                    None,
+                )
+                .await
+                .expect("add liquidity failed");
+
+            // alice becomes a merchant
+            let seed_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.import_legacy_account(alice, 200_000_000, 0, None));
+            client
+                .call(&ink_e2e::alice(), seed_message, 0, None)
+                .await
+                .expect("failed to seed merchant account");
+            let subscribe_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.subscribe(1_000, SubscriptionTier::Basic as u8));
+            client
+                .call(&ink_e2e::alice(), subscribe_message, 0, None)
+                .await
+                .expect("subscribe failed");
+
+            // a starved amm call fails the conversion, the payment itself goes through
+            let starve_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.set_call_gas_limit(CallTarget::Amm, 1));
+            client
+                .call(&ink_e2e::alice(), starve_message, 0, None)
+                .await
+                .expect("failed to set gas limit");
+            let usdt_payment: Balance = 1_000_000_000;
+            let payment_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.give_green_points_usdt(bob, usdt_payment));
+            client
+                .call(&ink_e2e::alice(), payment_message, 0, None)
+                .await
+                .expect("payment failed");
+
+            let payment_id = D9MerchantMining::conversion_payment_id(alice, 0);
+            let failed_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.get_failed_conversion(payment_id));
+            let failed_conversion = client
+                .call_dry_run(&ink_e2e::alice(), &failed_message, 0, None)
+                .await
+                .return_value();
+            assert_eq!(failed_conversion, Some((alice, usdt_payment)));
+
+            // the subscription already put volume in the pool
+            let volume_message = build_message::<MiningPoolRef>(mining_pool_address.clone())
+                .call(|mining_pool| mining_pool.get_merchant_volume());
+            let volume_before = client
+                .call_dry_run(&ink_e2e::alice(), &volume_message, 0, None)
+                .await
+                .return_value();
+
+            // with its budget back the retry converts and reaches the mining pool
+            let restore_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.set_call_gas_limit(CallTarget::Amm, 0));
+            client
+                .call(&ink_e2e::alice(), restore_message, 0, None)
+                .await
+                .expect("failed to restore gas limit");
+            let retry_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.retry_conversion(payment_id));
+            let d9_amount = client
+                .call(&ink_e2e::alice(), retry_message, 0, None)
+                .await
+                .expect("retry failed")
+                .return_value()
+                .expect("retry returned an error");
+            assert!(d9_amount > 0);
+
+            let failed_conversion = client
+                .call_dry_run(&ink_e2e::alice(), &failed_message, 0, None)
+                .await
+                .return_value();
+            assert_eq!(failed_conversion, None);
+            let merchant_volume = client
+                .call_dry_run(&ink_e2e::alice(), &volume_message, 0, None)
+                .await
+                .return_value();
+            assert_eq!(merchant_volume, volume_before + d9_amount);
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn subscription_is_swapped_for_the_mining_pool(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            let alice = account_id(AccountKeyring::Alice);
+            let usdt_address = client
+                .instantiate(
+                    "d9_usdt_permit_mock",
+                    &ink_e2e::alice(),
+                    D9USDTPermitMockRef::new(100_000_000_000_000),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate usdt")
+                .account_id;
+            let amm_address = client
+                .instantiate(
+                    "market_maker",
+                    &ink_e2e::alice(),
+                    MarketMakerRef::new(usdt_address, 1, 100),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate market maker")
+                .account_id;
+            let merchant_address = client
+                .instantiate(
+                    "d9_merchant_mining",
+                    &ink_e2e::alice(),
+                    D9MerchantMiningRef::new(amm_address, alice, usdt_address),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate merchant mining")
+                .account_id;
+            let mining_pool_address = client
+                .instantiate(
+                    "mining_pool",
+                    &ink_e2e::alice(),
+                    MiningPoolRef::new(
+                        account_id(AccountKeyring::Eve),
+                        merchant_address,
+                        account_id(AccountKeyring::Ferdie),
+                        amm_address,
+                    ),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate mining pool")
+                .account_id;
+            let change_pool_message =
+                build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                    .call(|merchant| merchant.change_mining_pool(mining_pool_address));
+            client
+                .call(&ink_e2e::alice(), change_pool_message, 0, None)
+                .await
+                .expect("failed to change mining pool");
+            for spender in [amm_address, merchant_address] {
+                let permit_message = build_message::<D9USDTPermitMockRef>(usdt_address.clone())
+                    .call(|usdt| {
+                        usdt.permit(
+                            alice,
+                            spender,
+                            50_000_000_000_000,
+                            u64::MAX,
+                            27,
+                            [0u8; 32],
+                            [0u8; 32],
+                        )
+                    });
+                client
+                    .call(&ink_e2e::alice(), permit_message, 0, None)
+                    .await
+                    .expect("permit failed");
+            }
+            let add_liquidity_message = build_message::<MarketMakerRef>(amm_address.clone())
+                .call(|market_maker| market_maker.add_liquidity(5_000_000_000_000));
+            client
+                .call(
+                    &ink_e2e::alice(),
+                    add_liquidity_message,
+                    500_000_000_000,
+                    None,
+                )
+                .await
+                .expect("add liquidity failed");
+            let seed_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.import_legacy_account(alice, 200_000_000, 0, None));
+            client
+                .call(&ink_e2e::alice(), seed_message, 0, None)
+                .await
+                .expect("failed to seed merchant account");
+
+            let reserves_message = build_message::<MarketMakerRef>(amm_address.clone())
+                .call(|market_maker| market_maker.get_currency_reserves());
+            let usdt_balance_message = build_message::<D9USDTPermitMockRef>(usdt_address.clone())
+                .call(|usdt| usdt.balance_of(alice));
+            let alice_usdt_before = client
+                .call_dry_run(&ink_e2e::alice(), &usdt_balance_message, 0, None)
+                .await
+                .return_value();
+
+            // a starved swap fails the subscription and leaves the merchant's usdt alone
+            let starve_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.set_call_gas_limit(CallTarget::Amm, 1));
+            client
+                .call(&ink_e2e::alice(), starve_message, 0, None)
+                .await
+                .expect("failed to set gas limit");
+            let subscription_fee: Balance = 1_000_000_000;
+            let subscribe_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| {
+                    merchant.subscribe(subscription_fee, SubscriptionTier::Basic as u8)
+                });
+            let starved_subscription = client
+                .call(&ink_e2e::alice(), subscribe_message, 0, None)
+                .await;
+            assert!(starved_subscription.is_err());
+            let alice_usdt = client
+                .call_dry_run(&ink_e2e::alice(), &usdt_balance_message, 0, None)
+                .await
+                .return_value();
+            assert_eq!(alice_usdt, alice_usdt_before);
+            let expiry_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.get_expiry(alice));
+            let expiry = client
+                .call_dry_run(&ink_e2e::alice(), &expiry_message, 0, None)
+                .await
+                .return_value();
+            assert!(expiry.is_err());
+
+            let restore_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.set_call_gas_limit(CallTarget::Amm, 0));
+            client
+                .call(&ink_e2e::alice(), restore_message, 0, None)
+                .await
+                .expect("failed to restore gas limit");
+            let (d9_reserve_before, usdt_reserve_before) = client
+                .call_dry_run(&ink_e2e::alice(), &reserves_message, 0, None)
+                .await
+                .return_value();
+            let pool_balance_before = client
+                .balance(mining_pool_address)
+                .await
+                .expect("failed to read pool balance");
+            let subscribe_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| {
+                    merchant.subscribe(subscription_fee, SubscriptionTier::Basic as u8)
+                });
+            client
+                .call(&ink_e2e::alice(), subscribe_message, 0, None)
+                .await
+                .expect("subscribe failed");
+
+            // the amm only moved by the swap: usdt in, the same d9 out to the pool
+            let (d9_reserve, usdt_reserve) = client
+                .call_dry_run(&ink_e2e::alice(), &reserves_message, 0, None)
+                .await
+                .return_value();
+            let pool_balance = client
+                .balance(mining_pool_address)
+                .await
+                .expect("failed to read pool balance");
+            let d9_swapped = d9_reserve_before - d9_reserve;
+            assert!(d9_swapped > 0);
+            assert_eq!(usdt_reserve, usdt_reserve_before + subscription_fee);
+            assert_eq!(pool_balance, pool_balance_before + d9_swapped);
+            let expiry = client
+                .call_dry_run(&ink_e2e::alice(), &expiry_message, 0, None)
+                .await
+                .return_value();
+            assert!(expiry.is_ok());
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn d9_payment_fails_when_the_amm_fee_changes_after_the_quote(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            let alice = account_id(AccountKeyring::Alice);
+            let bob = account_id(AccountKeyring::Bob);
+            let usdt_address = client
+                .instantiate(
+                    "d9_usdt_permit_mock",
+                    &ink_e2e::alice(),
+                    D9USDTPermitMockRef::new(100_000_000_000_000),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate usdt")
+                .account_id;
+            let amm_address = client
+                .instantiate(
+                    "market_maker",
+                    &ink_e2e::alice(),
+                    MarketMakerRef::new(usdt_address, 1, 100),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate market maker")
+                .account_id;
+            let merchant_address = client
+                .instantiate(
+                    "d9_merchant_mining",
+                    &ink_e2e::alice(),
+                    D9MerchantMiningRef::new(amm_address, alice, usdt_address),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate merchant mining")
+                .account_id;
+            let mining_pool_address = client
+                .instantiate(
+                    "mining_pool",
+                    &ink_e2e::alice(),
+                    MiningPoolRef::new(
+                        account_id(AccountKeyring::Eve),
+                        merchant_address,
+                        account_id(AccountKeyring::Ferdie),
+                        amm_address,
+                    ),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate mining pool")
+                .account_id;
+            let change_pool_message =
+                build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                    .call(|merchant| merchant.change_mining_pool(mining_pool_address));
+            client
+                .call(&ink_e2e::alice(), change_pool_message, 0, None)
+                .await
+                .expect("failed to change mining pool");
+            for spender in [amm_address, merchant_address] {
+                let permit_message = build_message::<D9USDTPermitMockRef>(usdt_address.clone())
+                    .call(|usdt| {
+                        usdt.permit(
+                            alice,
+                            spender,
+                            50_000_000_000_000,
+                            u64::MAX,
+                            27,
+                            [0u8; 32],
+                            [0u8; 32],
+                        )
+                    });
+                client
+                    .call(&ink_e2e::alice(), permit_message, 0, None)
+                    .await
+                    .expect("permit failed");
+            }
+            let add_liquidity_message = build_message::<MarketMakerRef>(amm_address.clone())
+                .call(|market_maker| market_maker.add_liquidity(5_000_000_000_000));
+            client
+                .call(
+                    &ink_e2e::alice(),
+                    add_liquidity_message,
+                    500_000_000_000,
+                    None,
+                )
+                .await
+                .expect("add liquidity failed");
+            let seed_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.import_legacy_account(alice, 200_000_000, 0, None));
+            client
+                .call(&ink_e2e::alice(), seed_message, 0, None)
+                .await
+                .expect("failed to seed merchant account");
+            let subscribe_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.subscribe(1_000, SubscriptionTier::Basic as u8));
+            client
+                .call(&ink_e2e::alice(), subscribe_message, 0, None)
+                .await
+                .expect("subscribe failed");
+
+            // the merchant prices the payment with the fee it reads now
+            let fee_message = build_message::<MarketMakerRef>(amm_address.clone())
+                .call(|market_maker| market_maker.get_fee_percent());
+            let quoted_fee = client
+                .call_dry_run(&ink_e2e::alice(), &fee_message, 0, None)
+                .await
+                .return_value();
+            assert_eq!(quoted_fee, 1);
+
+            // the amm admin changes the fee before the payment lands
+            let set_fee_message = build_message::<MarketMakerRef>(amm_address.clone())
+                .call(|market_maker| market_maker.set_fee_percent(2));
+            client
+                .call(&ink_e2e::alice(), set_fee_message, 0, None)
+                .await
+                .expect("failed to change the amm fee");
+
+            let d9_payment: Balance = 100_000_000_000;
+            let volume_message = build_message::<MiningPoolRef>(mining_pool_address.clone())
+                .call(|mining_pool| mining_pool.get_merchant_volume());
+            let volume_before = client
+                .call_dry_run(&ink_e2e::alice(), &volume_message, 0, None)
+                .await
+                .return_value();
+            let stale_payment_message =
+                build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                    .call(|merchant| merchant.give_green_points_d9_with_fee(bob, quoted_fee));
+            let stale_payment = client
+                .call(&ink_e2e::alice(), stale_payment_message, d9_payment, None)
+                .await;
+            assert!(stale_payment.is_err());
+
+            // nothing was granted or forwarded
+            let account_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.get_account(bob));
+            let bob_account = client
+                .call_dry_run(&ink_e2e::alice(), &account_message, 0, None)
+                .await
+                .return_value();
+            assert!(bob_account.is_none());
+            let volume = client
+                .call_dry_run(&ink_e2e::alice(), &volume_message, 0, None)
+                .await
+                .return_value();
+            assert_eq!(volume, volume_before);
+
+            // priced with the new fee the same payment goes through
+            let payment_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.give_green_points_d9_with_fee(bob, 2));
+            client
+                .call(&ink_e2e::alice(), payment_message, d9_payment, None)
+                .await
+                .expect("payment with the current fee failed");
+            let bob_account = client
+                .call_dry_run(&ink_e2e::alice(), &account_message, 0, None)
+                .await
+                .return_value()
+                .expect("bob has no account");
+            assert!(bob_account.green_points > 0);
+            let volume = client
+                .call_dry_run(&ink_e2e::alice(), &volume_message, 0, None)
+                .await
+                .return_value();
+            assert_eq!(volume, volume_before + d9_payment);
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn fee_on_transfer_usdt_credits_what_arrived(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            let alice = account_id(AccountKeyring::Alice);
+            let bob = account_id(AccountKeyring::Bob);
+            // burns 1% of every transfer
+            let usdt_address = client
+                .instantiate(
+                    "d9_usdt_fee_mock",
+                    &ink_e2e::alice(),
+                    D9USDTFeeMockRef::new(100_000_000_000_000, 10),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate fee usdt")
+                .account_id;
+            let amm_address = client
+                .instantiate(
+                    "market_maker",
+                    &ink_e2e::alice(),
+                    MarketMakerRef::new(usdt_address, 1, 100),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate market maker")
+                .account_id;
+            let merchant_address = client
+                .instantiate(
+                    "d9_merchant_mining",
+                    &ink_e2e::alice(),
+                    D9MerchantMiningRef::new(amm_address, alice, usdt_address),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate merchant mining")
+                .account_id;
+            let mining_pool_address = client
+                .instantiate(
+                    "mining_pool",
+                    &ink_e2e::alice(),
+                    MiningPoolRef::new(
+                        account_id(AccountKeyring::Eve),
+                        merchant_address,
+                        account_id(AccountKeyring::Ferdie),
+                        amm_address,
+                    ),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate mining pool")
+                .account_id;
+            let change_pool_message =
+                build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                    .call(|merchant| merchant.change_mining_pool(mining_pool_address));
+            client
+                .call(&ink_e2e::alice(), change_pool_message, 0, None)
+                .await
+                .expect("failed to change mining pool");
+            for spender in [amm_address, merchant_address] {
+                let approve_message = build_message::<D9USDTFeeMockRef>(usdt_address.clone())
+                    .call(|usdt| usdt.approve(spender, 50_000_000_000_000));
+                client
+                    .call(&ink_e2e::alice(), approve_message, 0, None)
+                    .await
+                    .expect("approve failed");
+            }
+            let add_liquidity_message = build_message::<MarketMakerRef>(amm_address.clone())
+                .call(|market_maker| market_maker.add_liquidity(5_000_000_000_000));
+            client
+                .call(
+                    &ink_e2e::alice(),
+                    add_liquidity_message,
+                    500_000_000_000,
+                    None,
+                )
+                .await
+                .expect("add liquidity failed");
+            let seed_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.import_legacy_account(alice, 200_000_000, 0, None));
+            client
+                .call(&ink_e2e::alice(), seed_message, 0, None)
+                .await
+                .expect("failed to seed merchant account");
+            let subscribe_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.subscribe(2_000, SubscriptionTier::Basic as u8));
+            // 1,980 arrives, still a month's fee
+            client
+                .call(&ink_e2e::alice(), subscribe_message, 0, None)
+                .await
+                .expect("subscribe failed");
+
+            // a starved amm keeps the received usdt in the contract as a failed conversion
+            let starve_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.set_call_gas_limit(CallTarget::Amm, 1));
+            client
+                .call(&ink_e2e::alice(), starve_message, 0, None)
+                .await
+                .expect("failed to set gas limit");
+            let usdt_payment: Balance = 1_000_000_000;
+            let payment_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.give_green_points_usdt(bob, usdt_payment));
+            client
+                .call(&ink_e2e::alice(), payment_message, 0, None)
+                .await
+                .expect("payment failed");
+
+            let usdt_received: Balance = 990_000_000;
+            let payment_id = D9MerchantMining::conversion_payment_id(alice, 0);
+            let failed_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.get_failed_conversion(payment_id));
+            let failed_conversion = client
+                .call_dry_run(&ink_e2e::alice(), &failed_message, 0, None)
+                .await
+                .return_value();
+            assert_eq!(failed_conversion, Some((alice, usdt_received)));
+            let contract_usdt_message = build_message::<D9USDTFeeMockRef>(usdt_address.clone())
+                .call(|usdt| usdt.balance_of(merchant_address));
+            let contract_usdt = client
+                .call_dry_run(&ink_e2e::alice(), &contract_usdt_message, 0, None)
+                .await
+                .return_value();
+            assert_eq!(contract_usdt, usdt_received);
+
+            // points are priced on the 990 that arrived, not the 1,000 requested
+            let account_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.get_account(bob));
+            let bob_account = client
+                .call_dry_run(&ink_e2e::alice(), &account_message, 0, None)
+                .await
+                .return_value()
+                .expect("bob has no account");
+            assert_eq!(bob_account.green_points, usdt_received * 1000 / 160 * 100);
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn usdt_redemption_pays_usdt_through_the_amm(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            let alice = account_id(AccountKeyring::Alice);
+            let bob = account_id(AccountKeyring::Bob);
+            let charlie = account_id(AccountKeyring::Charlie);
+            let usdt_address = client
+                .instantiate(
+                    "d9_usdt_permit_mock",
+                    &ink_e2e::alice(),
+                    D9USDTPermitMockRef::new(100_000_000_000_000),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate usdt")
+                .account_id;
+            let amm_address = client
+                .instantiate(
+                    "market_maker",
+                    &ink_e2e::alice(),
+                    MarketMakerRef::new(usdt_address, 1, 100),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate market maker")
+                .account_id;
+            let merchant_address = client
+                .instantiate(
+                    "d9_merchant_mining",
+                    &ink_e2e::alice(),
+                    D9MerchantMiningRef::new(amm_address, alice, usdt_address),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate merchant mining")
+                .account_id;
+            let mining_pool_address = client
+                .instantiate(
+                    "mining_pool",
+                    &ink_e2e::alice(),
+                    MiningPoolRef::new(
+                        account_id(AccountKeyring::Eve),
+                        merchant_address,
+                        account_id(AccountKeyring::Ferdie),
+                        amm_address,
+                    ),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate mining pool")
+                .account_id;
+            let change_pool_message =
+                build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                    .call(|merchant| merchant.change_mining_pool(mining_pool_address));
+            client
+                .call(&ink_e2e::alice(), change_pool_message, 0, None)
+                .await
+                .expect("failed to change mining pool");
+            for spender in [amm_address, merchant_address] {
+                let permit_message = build_message::<D9USDTPermitMockRef>(usdt_address.clone())
+                    .call(|usdt| {
+                        usdt.permit(
+                            alice,
+                            spender,
+                            50_000_000_000_000,
+                            u64::MAX,
+                            27,
+                            [0u8; 32],
+                            [0u8; 32],
+                        )
+                    });
+                client
+                    .call(&ink_e2e::alice(), permit_message, 0, None)
+                    .await
+                    .expect("permit failed");
+            }
+            let add_liquidity_message = build_message::<MarketMakerRef>(amm_address.clone())
+                .call(|market_maker| market_maker.add_liquidity(5_000_000_000_000));
+            client
+                .call(
+                    &ink_e2e::alice(),
+                    add_liquidity_message,
+                    500_000_000_000,
+                    None,
+                )
+                .await
+                .expect("add liquidity failed");
+            let seed_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.import_legacy_account(alice, 200_000_000, 0, None));
+            client
+                .call(&ink_e2e::alice(), seed_message, 0, None)
+                .await
+                .expect("failed to seed merchant account");
+            let subscribe_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.subscribe(1_000, SubscriptionTier::Basic as u8));
+            client
+                .call(&ink_e2e::alice(), subscribe_message, 0, None)
+                .await
+                .expect("subscribe failed");
+
+            // a d9 payment funds the mining pool that backs redemptions
+            let payment_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.give_green_points_d9(charlie));
+            client
+                .call(&ink_e2e::alice(), payment_message, 100_000_000_000, None)
+                .await
+                .expect("d9 payment failed");
+
+            // created at the epoch, bob has red points waiting and no lockout
+            let import_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.import_legacy_account(bob, 100_000_000, 0, None));
+            client
+                .call(&ink_e2e::alice(), import_message, 0, None)
+                .await
+                .expect("failed to import bob");
+            let bob_usdt_message = build_message::<D9USDTPermitMockRef>(usdt_address.clone())
+                .call(|usdt| usdt.balance_of(bob));
+            let bob_usdt_before = client
+                .call_dry_run(&ink_e2e::bob(), &bob_usdt_message, 0, None)
+                .await
+                .return_value();
+
+            let redeem_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.redeem(Currency::USDT));
+            let usdt_out = client
+                .call(&ink_e2e::bob(), redeem_message, 0, None)
+                .await
+                .expect("usdt redemption failed")
+                .return_value()
+                .expect("redeem returned an error");
+            assert!(usdt_out > 0);
+
+            // the usdt the amm paid out is what reaches bob
+            let bob_usdt = client
+                .call_dry_run(&ink_e2e::bob(), &bob_usdt_message, 0, None)
+                .await
+                .return_value();
+            assert_eq!(bob_usdt, bob_usdt_before + usdt_out);
+            let account_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.get_account(bob));
+            let bob_account = client
+                .call_dry_run(&ink_e2e::bob(), &account_message, 0, None)
+                .await
+                .return_value()
+                .expect("bob has no account");
+            assert_eq!(bob_account.redeemed_usdt, usdt_out);
+            assert_eq!(bob_account.redeemed_d9, 0);
+            assert!(bob_account.green_points < 100_000_000);
+            assert!(bob_account.last_conversion.is_some());
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn public_messages_update_last_activity(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            let alice = account_id(AccountKeyring::Alice);
+            let bob = account_id(AccountKeyring::Bob);
+            let charlie = account_id(AccountKeyring::Charlie);
+            let dave = account_id(AccountKeyring::Dave);
+            let eve = account_id(AccountKeyring::Eve);
+            let ferdie = account_id(AccountKeyring::Ferdie);
+            let usdt_address = client
+                .instantiate(
+                    "d9_usdt_permit_mock",
+                    &ink_e2e::alice(),
+                    D9USDTPermitMockRef::new(100_000_000_000_000),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate usdt")
+                .account_id;
+            let amm_address = client
+                .instantiate(
+                    "market_maker",
+                    &ink_e2e::alice(),
+                    MarketMakerRef::new(usdt_address, 1, 100),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate market maker")
+                .account_id;
+            let merchant_address = client
+                .instantiate(
+                    "d9_merchant_mining",
+                    &ink_e2e::alice(),
+                    D9MerchantMiningRef::new(amm_address, alice, usdt_address),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate merchant mining")
+                .account_id;
+            let mining_pool_address = client
+                .instantiate(
+                    "mining_pool",
+                    &ink_e2e::alice(),
+                    MiningPoolRef::new(
+                        account_id(AccountKeyring::Eve),
+                        merchant_address,
+                        account_id(AccountKeyring::Ferdie),
+                        amm_address,
+                    ),
+                    0,
+                    None,
+                )
+                .await
+                .expect("failed to instantiate mining pool")
+                .account_id;
+            let change_pool_message =
+                build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                    .call(|merchant| merchant.change_mining_pool(mining_pool_address));
+            client
+                .call(&ink_e2e::alice(), change_pool_message, 0, None)
+                .await
+                .expect("failed to change mining pool");
+            for spender in [amm_address, merchant_address] {
+                let permit_message = build_message::<D9USDTPermitMockRef>(usdt_address.clone())
+                    .call(|usdt| {
+                        usdt.permit(
+                            alice,
+                            spender,
+                            50_000_000_000_000,
+                            u64::MAX,
+                            27,
+                            [0u8; 32],
+                            [0u8; 32],
+                        )
+                    });
+                client
+                    .call(&ink_e2e::alice(), permit_message, 0, None)
+                    .await
+                    .expect("permit failed");
+            }
+            let add_liquidity_message = build_message::<MarketMakerRef>(amm_address.clone())
+                .call(|market_maker| market_maker.add_liquidity(5_000_000_000_000));
+            client
+                .call(
+                    &ink_e2e::alice(),
+                    add_liquidity_message,
+                    500_000_000_000,
+                    None,
+                )
+                .await
+                .expect("add liquidity failed");
+            // bob pays in usdt too
+            let fund_bob_message = build_message::<D9USDTPermitMockRef>(usdt_address.clone())
+                .call(|usdt| usdt.transfer(bob, 10_000_000, Vec::new()));
+            client
+                .call(&ink_e2e::alice(), fund_bob_message, 0, None)
+                .await
+                .expect("failed to fund bob");
+            let bob_permit_message = build_message::<D9USDTPermitMockRef>(usdt_address.clone())
+                .call(|usdt| {
+                    usdt.permit(
+                        bob,
+                        merchant_address,
+                        10_000_000,
+                        u64::MAX,
+                        27,
+                        [0u8; 32],
+                        [0u8; 32],
+                    )
+                });
+            client
+                .call(&ink_e2e::bob(), bob_permit_message, 0, None)
+                .await
+                .expect("bob's permit failed");
+
+            let alice_activity = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.get_last_activity(alice));
+            let bob_activity = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.get_last_activity(bob));
+            let charlie_activity = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.get_last_activity(charlie));
+            let dave_activity = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.get_last_activity(dave));
+            let eve_activity = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.get_last_activity(eve));
+
+            // an import isn't activity, the account reports its creation
+            for account in [alice, charlie, dave, eve] {
+                let import_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                    .call(|merchant| merchant.import_legacy_account(account, 200_000_000, 0, None));
+                client
+                    .call(&ink_e2e::alice(), import_message, 0, None)
+                    .await
+                    .expect("failed to import account");
+            }
+            let alice_seen = client
+                .call_dry_run(&ink_e2e::alice(), &alice_activity, 0, None)
+                .await
+                .return_value();
+            assert_eq!(alice_seen, Some(0));
+
+            let subscribe_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.subscribe(1_000, SubscriptionTier::Basic as u8));
+            client
+                .call(&ink_e2e::alice(), subscribe_message, 0, None)
+                .await
+                .expect("subscribe failed");
+            let subscribed_at = client
+                .call_dry_run(&ink_e2e::alice(), &alice_activity, 0, None)
+                .await
+                .return_value();
+            assert!(subscribed_at > alice_seen);
+            let alice_seen = subscribed_at;
+
+            // every payment touches the merchant and the consumer
+            let d9_payment_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.give_green_points_d9(bob));
+            client
+                .call(&ink_e2e::alice(), d9_payment_message, 100_000_000_000, None)
+                .await
+                .expect("d9 payment failed");
+            let bob_seen = client
+                .call_dry_run(&ink_e2e::alice(), &bob_activity, 0, None)
+                .await
+                .return_value();
+            assert!(bob_seen.is_some());
+            let paid_at = client
+                .call_dry_run(&ink_e2e::alice(), &alice_activity, 0, None)
+                .await
+                .return_value();
+            assert!(paid_at > alice_seen);
+            assert_eq!(paid_at, bob_seen);
+
+            let fee_payment_message =
+                build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                    .call(|merchant| merchant.give_green_points_d9_with_fee(bob, 1));
+            client
+                .call(
+                    &ink_e2e::alice(),
+                    fee_payment_message,
+                    100_000_000_000,
+                    None,
+                )
+                .await
+                .expect("d9 payment with fee failed");
+            let paid_at = client
+                .call_dry_run(&ink_e2e::alice(), &bob_activity, 0, None)
+                .await
+                .return_value();
+            assert!(paid_at > bob_seen);
+            let bob_seen = paid_at;
+
+            let usdt_payment_message =
+                build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                    .call(|merchant| merchant.give_green_points_usdt(bob, 1_000_000));
+            client
+                .call(&ink_e2e::alice(), usdt_payment_message, 0, None)
+                .await
+                .expect("usdt payment failed");
+            let paid_at = client
+                .call_dry_run(&ink_e2e::alice(), &bob_activity, 0, None)
+                .await
+                .return_value();
+            assert!(paid_at > bob_seen);
+            let bob_seen = paid_at;
+
+            let usdt_to_merchant_message =
+                build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                    .call(|merchant| merchant.send_usdt_payment_to_merchant(alice, 1_000_000));
+            client
+                .call(&ink_e2e::bob(), usdt_to_merchant_message, 0, None)
+                .await
+                .expect("usdt payment to merchant failed");
+            let paid_at = client
+                .call_dry_run(&ink_e2e::alice(), &bob_activity, 0, None)
+                .await
+                .return_value();
+            assert!(paid_at > bob_seen);
+            let bob_seen = paid_at;
+
+            let d9_to_merchant_message =
+                build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                    .call(|merchant| merchant.send_d9_payment_to_merchant(alice));
+            client
+                .call(
+                    &ink_e2e::bob(),
+                    d9_to_merchant_message,
+                    100_000_000_000,
+                    None,
+                )
+                .await
+                .expect("d9 payment to merchant failed");
+            let paid_at = client
+                .call_dry_run(&ink_e2e::alice(), &bob_activity, 0, None)
+                .await
+                .return_value();
+            assert!(paid_at > bob_seen);
+            let bob_seen = paid_at;
+            let alice_seen = client
+                .call_dry_run(&ink_e2e::alice(), &alice_activity, 0, None)
+                .await
+                .return_value();
+            assert_eq!(alice_seen, bob_seen);
+
+            let refund_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.refund_payment(bob, 1_000_000, None));
+            client
+                .call(&ink_e2e::alice(), refund_message, 0, None)
+                .await
+                .expect("refund failed");
+            let refunded_at = client
+                .call_dry_run(&ink_e2e::alice(), &bob_activity, 0, None)
+                .await
+                .return_value();
+            assert!(refunded_at > bob_seen);
+            let alice_refunded_at = client
+                .call_dry_run(&ink_e2e::alice(), &alice_activity, 0, None)
+                .await
+                .return_value();
+            assert_eq!(alice_refunded_at, refunded_at);
+
+            // each redemption path touches the redeeming account
+            let redeem_d9_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.redeem_d9());
+            client
+                .call(&ink_e2e::charlie(), redeem_d9_message, 0, None)
+                .await
+                .expect("d9 redemption failed");
+            let charlie_seen = client
+                .call_dry_run(&ink_e2e::alice(), &charlie_activity, 0, None)
+                .await
+                .return_value();
+            assert!(charlie_seen > Some(0));
+
+            let redeem_usdt_message =
+                build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                    .call(|merchant| merchant.redeem(Currency::USDT));
+            client
+                .call(&ink_e2e::dave(), redeem_usdt_message, 0, None)
+                .await
+                .expect("usdt redemption failed");
+            let dave_seen = client
+                .call_dry_run(&ink_e2e::alice(), &dave_activity, 0, None)
+                .await
+                .return_value();
+            assert!(dave_seen > Some(0));
+
+            let approve_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.approve_redeem_delegate(ferdie, true));
+            client
+                .call(&ink_e2e::eve(), approve_message, 0, None)
+                .await
+                .expect("approving the delegate failed");
+            let eve_seen = client
+                .call_dry_run(&ink_e2e::alice(), &eve_activity, 0, None)
+                .await
+                .return_value();
+            assert!(eve_seen > Some(0));
+            let redeem_for_message = build_message::<D9MerchantMiningRef>(merchant_address.clone())
+                .call(|merchant| merchant.redeem_d9_for(eve));
+            client
+                .call(&ink_e2e::ferdie(), redeem_for_message, 0, None)
+                .await
+                .expect("delegated redemption failed");
+            let redeemed_at = client
+                .call_dry_run(&ink_e2e::alice(), &eve_activity, 0, None)
+                .await
+                .return_value();
+            assert!(redeemed_at > eve_seen);
+            Ok(())
+        }
     }
 }
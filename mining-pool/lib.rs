@@ -3,13 +3,16 @@
 pub use d9_chain_extension::D9Environment;
 
 #[ink::contract(env = D9Environment)]
-mod mining_pool {
+pub mod mining_pool {
     use super::*;
     use ink::env::call::{ build_call, ExecutionInput, Selector };
+    use ink::env::hash::{ Blake2x256, HashOutput };
     use ink::selector_bytes;
+    use ink::prelude::vec::Vec;
     use ink::storage::Mapping;
     use scale::{ Decode, Encode };
-    use sp_arithmetic::Perquintill;
+    use sp_arithmetic::helpers_128bit::multiply_by_rational_with_rounding;
+    use sp_arithmetic::{ Perquintill, Rounding };
     // use substrate_fixed::{ FixedU128, types::extra::U12 };
     // type FixedBalance = FixedU128<U12>;
 
@@ -23,6 +26,34 @@ mod mining_pool {
     #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
     pub struct Direction(Currency, Currency);
 
+    /// redemptions sell usdt for d9 on the amm
+    pub const REDEEM_DIRECTION: Direction = Direction(Currency::USDT, Currency::D9);
+
+    /// scale of d9 per usdt rates
+    pub const PRICE_PRECISION: Balance = 1_000_000;
+
+    /// decimals `new` assumes for each currency
+    pub const D9_DECIMALS: u8 = 12;
+    pub const USDT_DECIMALS: u8 = 2;
+
+    /// most decimals accepted for either currency
+    pub const MAX_DECIMALS: u8 = 18;
+
+    /// most amms quoted besides `amm_contract`
+    pub const MAX_AMM_CONTRACTS: usize = 4;
+
+    /// how far, in bps, another amm's quote may sit from `amm_contract`'s and still be used
+    pub const MAX_AMM_QUOTE_DEVIATION_BPS: Balance = 500;
+
+    /// volume weight that counts a source 1:1
+    pub const BASE_WEIGHT_BPS: u32 = 10_000;
+
+    /// a protection floor of 100% of the highest rate
+    pub const MAX_PROTECTION_FLOOR_BPS: u32 = 10_000;
+
+    /// window of the oracle twap used for redemptions, one hour
+    pub const ORACLE_TWAP_WINDOW_MS: Timestamp = 3_600_000;
+
     #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
     #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
     pub enum Error {
@@ -30,7 +61,190 @@ mod mining_pool {
         FailedToGetExchangeAmount,
         FailedToTransferD9ToUser,
         SessionPoolNotReady,
-        ErrorAddingVotes
+        ErrorAddingVotes,
+        RateComputationFailed,
+        PayoutRoundedToZero,
+        NotPendingAdmin,
+        AlreadyRetired,
+        ForwardingToSuccessorFailed,
+        /// zero address or a contract wired into more than one role
+        InvalidContractAddress,
+        /// the oracle twap is older than `max_oracle_age_ms`
+        OracleStale,
+        /// the amm can't quote d9 <> usdt in both directions
+        IncompatibleAmmPair,
+        /// a split reward adds up to more than `accumulative_reward_pool`
+        RewardExceedsPool,
+        FailedToPayNodeReward,
+        NoPendingRedemption,
+        /// `keeper_tip` is 0, sessions are only updated by the node reward contract
+        KeeperUpdatesDisabled,
+        /// sessions must be updated in strictly increasing order
+        SessionAlreadyUpdated,
+        /// keepers can only update sessions that already ended on chain
+        SessionNotEnded,
+        FailedToGetCurrentSession,
+        NoSessionRewardToCollect,
+        FailedToPayKeeperTip,
+        /// the session no longer computes to the committed figures, compute it again
+        StaleSessionComputation,
+        /// a currency with more than `MAX_DECIMALS` decimals
+        InvalidDecimals,
+        TooManyAmmContracts,
+        /// a node reward is more than the contract holds
+        InsufficientContractBalance,
+        /// a protection floor above `MAX_PROTECTION_FLOOR_BPS`
+        InvalidProtectionFloor,
+    }
+
+    /// result of a session pool update, returned to the node reward contract
+    #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout))]
+    pub struct SessionPoolUpdate {
+        pub session_index: u32,
+        /// amount available to nodes this session
+        pub reward_pool: Balance,
+        /// volume processed since the previous valid session
+        pub session_delta: Balance,
+        /// pool after the session delta was added
+        pub accumulative_reward_pool: Balance,
+    }
+
+    /// figures `commit_session` would apply for a session, from `compute_session_reward`
+    #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct SessionComputation {
+        pub session_index: u32,
+        /// weighted volume the session is recorded at
+        pub total_volume: Balance,
+        pub session_delta: Balance,
+        pub accumulative_reward_pool: Balance,
+        pub reward_pool: Balance,
+    }
+
+    impl SessionComputation {
+        /// blake2 of the scale encoding, what `commit_session` expects
+        pub fn hash(&self) -> Hash {
+            let mut output = <Blake2x256 as HashOutput>::Type::default();
+            ink::env::hash_encoded::<Blake2x256, _>(self, &mut output);
+            Hash::from(output)
+        }
+    }
+
+    /// intermediate figures of a session pool update
+    #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+    struct RewardBreakdown {
+        session_delta: Balance,
+        three_percent_of_delta: Balance,
+        accumulative_after: Balance,
+        /// part of the session contribution above `max_accumulative_pool`
+        discarded: Balance,
+        /// ten percent of `accumulative_after`, before the per session cap
+        requested: Balance,
+        reward_pool: Balance,
+    }
+
+    /// restricted cross-contract calls counted by `get_call_stats`
+    #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+    enum CallKind {
+        ProcessMerchantPayment = 0,
+        RedeemD9 = 1,
+        UpdatePool = 2,
+        PayNodeReward = 3,
+    }
+
+    const CALL_KINDS: [CallKind; 4] = [
+        CallKind::ProcessMerchantPayment,
+        CallKind::RedeemD9,
+        CallKind::UpdatePool,
+        CallKind::PayNodeReward,
+    ];
+
+    /// where the market rate of a redemption came from
+    #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum RateSource {
+        Oracle,
+        /// the amm with the best quote
+        Amm(AccountId),
+    }
+
+    #[ink(event)]
+    pub struct RedemptionPaid {
+        #[ink(topic)]
+        user: AccountId,
+        d9_amount: Balance,
+        rate_source: RateSource,
+    }
+
+    /// the pool couldn't pay a redemption, the d9 is owed to `user` until claimed
+    #[ink(event)]
+    pub struct RedemptionQueued {
+        #[ink(topic)]
+        user: AccountId,
+        d9_amount: Balance,
+        /// everything owed to `user` after this redemption
+        pending: Balance,
+    }
+
+    #[ink(event)]
+    pub struct RedemptionClaimed {
+        #[ink(topic)]
+        user: AccountId,
+        d9_amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct RewardSmoothed {
+        /// reward computed from the accumulative pool
+        requested: Balance,
+        /// reward actually released for the session
+        released: Balance,
+    }
+
+    #[ink(event)]
+    pub struct PoolCapReached {
+        session_index: u32,
+        max_accumulative_pool: Balance,
+        /// contribution dropped instead of added to the pool
+        discarded: Balance,
+    }
+
+    #[ink(event)]
+    pub struct KeeperTipPaid {
+        #[ink(topic)]
+        keeper: AccountId,
+        session_index: u32,
+        tip: Balance,
+    }
+
+    #[ink(event)]
+    pub struct ContractRetired {
+        #[ink(topic)]
+        successor: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct CallForwarded {
+        #[ink(topic)]
+        successor: AccountId,
+        selector: [u8; 4],
+    }
+
+    /// rates involved in paying out a merchant redemption
+    #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct RedeemPreview {
+        /// d9 paid to the user
+        pub d9_amount: Balance,
+        /// market rate from the oracle twap or the amm
+        pub current_rate: Balance,
+        /// anchor after this redemption
+        pub highest_rate: Balance,
+        /// rate actually paid
+        pub effective_rate: Balance,
+        /// the floor rate was used instead of the market rate
+        pub protection_applied: bool,
     }
 
     #[ink(storage)]
@@ -53,6 +267,49 @@ mod mining_pool {
         last_session: u32,
         /// total accumulative reward session pool
         accumulative_reward_pool: Balance,
+        /// highest whole d9 per whole usdt rate seen in redemptions, anchor for price protection
+        highest_rate: Balance,
+        /// redemptions pay at least this share of `highest_rate`, 0 pays the market rate
+        protection_floor_bps: u32,
+        /// proposed admin, must call `accept_admin` to take over
+        pending_admin: Option<AccountId>,
+        /// optional cap on the reward released per session, the rest stays in the pool
+        max_reward_per_session: Option<Balance>,
+        /// weight of burn volume in the total volume
+        burn_weight_bps: u32,
+        /// weight of merchant volume in the total volume
+        merchant_weight_bps: u32,
+        /// retired contracts forward payment and session calls to `successor`
+        retired: bool,
+        successor: Option<AccountId>,
+        /// redemptions paid at the floor rate
+        total_protected_redemptions: u64,
+        /// d9 paid or queued by redemptions at the floor rate
+        total_protected_d9_paid: Balance,
+        /// d9 paid or queued by redemptions at the market rate
+        total_market_d9_paid: Balance,
+        /// volume units per burned d9, in `PRICE_PRECISION` units
+        burn_to_volume_rate: Balance,
+        /// preferred over the amm quote for redemptions when set
+        price_oracle: Option<AccountId>,
+        /// oracle twaps last updated longer ago than this are refused
+        max_oracle_age_ms: Timestamp,
+        /// session contributions stop growing the pool at this size
+        max_accumulative_pool: Balance,
+        /// paid to whoever calls `trigger_session_update`, 0 disables the permissionless path
+        keeper_tip: Balance,
+        /// session updates triggered by a keeper, waiting for the node reward contract
+        keeper_session_updates: Mapping<u32, SessionPoolUpdate>,
+        /// d9 owed for redemptions the pool couldn't pay when they were made
+        pending_redemptions: Mapping<AccountId, Balance>,
+        total_pending_redemptions: Balance,
+        /// calls received per `CallKind`, with the timestamp of the latest one
+        call_counts: Mapping<u8, (u64, Timestamp)>,
+        /// decimals of each currency, amounts are brought to the larger one before pricing
+        d9_decimals: u8,
+        usdt_decimals: u8,
+        /// quoted alongside `amm_contract` for redemptions, the best quote within the band wins
+        amm_contracts: Vec<AccountId>,
     }
 
     impl MiningPool {
@@ -63,8 +320,37 @@ mod mining_pool {
             merchant_contract: AccountId,
             node_reward_contract: AccountId,
             amm_contract: AccountId
-        ) -> Self {
-            Self {
+        ) -> Result<Self, Error> {
+            Self::new_with_decimals(
+                main_contract,
+                merchant_contract,
+                node_reward_contract,
+                amm_contract,
+                D9_DECIMALS,
+                USDT_DECIMALS
+            )
+        }
+
+        /// like `new`, for currencies that don't use the default decimals
+        #[ink(constructor)]
+        pub fn new_with_decimals(
+            main_contract: AccountId,
+            merchant_contract: AccountId,
+            node_reward_contract: AccountId,
+            amm_contract: AccountId,
+            d9_decimals: u8,
+            usdt_decimals: u8
+        ) -> Result<Self, Error> {
+            Self::validate_wiring(
+                main_contract,
+                merchant_contract,
+                node_reward_contract,
+                amm_contract
+            )?;
+            if d9_decimals > MAX_DECIMALS || usdt_decimals > MAX_DECIMALS {
+                return Err(Error::InvalidDecimals);
+            }
+            Ok(Self {
                 admin: Self::env().caller(),
                 main_contract,
                 node_reward_contract,
@@ -74,7 +360,48 @@ mod mining_pool {
                 volume_at_index: Mapping::new(),
                 last_session: 0,
                 accumulative_reward_pool: 0,
-            }
+                highest_rate: 0,
+                protection_floor_bps: 0,
+                pending_admin: None,
+                max_reward_per_session: None,
+                burn_weight_bps: BASE_WEIGHT_BPS,
+                merchant_weight_bps: BASE_WEIGHT_BPS,
+                retired: false,
+                successor: None,
+                total_protected_redemptions: 0,
+                total_protected_d9_paid: 0,
+                total_market_d9_paid: 0,
+                burn_to_volume_rate: PRICE_PRECISION,
+                price_oracle: None,
+                max_oracle_age_ms: ORACLE_TWAP_WINDOW_MS,
+                max_accumulative_pool: Balance::MAX,
+                keeper_tip: 0,
+                keeper_session_updates: Mapping::new(),
+                pending_redemptions: Mapping::new(),
+                total_pending_redemptions: 0,
+                call_counts: Mapping::new(),
+                d9_decimals,
+                usdt_decimals,
+                amm_contracts: Vec::new(),
+            })
+        }
+
+        /// like `new`, but fails unless the amm quotes the d9 <> usdt pair both ways
+        #[ink(constructor)]
+        pub fn new_with_pair_check(
+            main_contract: AccountId,
+            merchant_contract: AccountId,
+            node_reward_contract: AccountId,
+            amm_contract: AccountId
+        ) -> Result<Self, Error> {
+            let mining_pool = Self::new(
+                main_contract,
+                merchant_contract,
+                node_reward_contract,
+                amm_contract
+            )?;
+            mining_pool.check_pair()?;
+            Ok(mining_pool)
         }
  
         #[ink(message)]
@@ -89,11 +416,67 @@ mod mining_pool {
             amount: Balance
         ) -> Result<(), Error> {
             let _ = self.only_callable_by(self.node_reward_contract)?;
-            let _ = self.env().transfer(account_id, amount);
+            self.record_call(CallKind::PayNodeReward);
+            if let Some(successor) = self.forwarding_target() {
+                let selector = selector_bytes!("pay_node_reward");
+                let result = build_call::<D9Environment>()
+                    .call(successor)
+                    .gas_limit(0)
+                    .exec_input(
+                        ExecutionInput::new(Selector::new(selector))
+                            .push_arg(account_id)
+                            .push_arg(amount)
+                    )
+                    .returns::<Result<(), Error>>()
+                    .try_invoke();
+                return self.forwarded_result(successor, selector, result);
+            }
+            if amount > self.env().balance() {
+                return Err(Error::InsufficientContractBalance);
+            }
+            self.env().transfer(account_id, amount).map_err(|_| Error::FailedToPayNodeReward)?;
             self.accumulative_reward_pool = self.accumulative_reward_pool.saturating_sub(amount);
             Ok(())
         }
 
+        /// pay one node reward to several accounts, e.g. operator and treasury. either every
+        /// recipient is paid or none is
+        #[ink(message)]
+        pub fn pay_node_reward_split(
+            &mut self,
+            recipients: Vec<(AccountId, Balance)>
+        ) -> Result<(), Error> {
+            self.only_callable_by(self.node_reward_contract)?;
+            self.record_call(CallKind::PayNodeReward);
+            if let Some(successor) = self.forwarding_target() {
+                let selector = selector_bytes!("pay_node_reward_split");
+                let result = build_call::<D9Environment>()
+                    .call(successor)
+                    .gas_limit(0)
+                    .exec_input(ExecutionInput::new(Selector::new(selector)).push_arg(&recipients))
+                    .returns::<Result<(), Error>>()
+                    .try_invoke();
+                return self.forwarded_result(successor, selector, result);
+            }
+            let total = recipients
+                .iter()
+                .try_fold(0 as Balance, |total, (_, amount)| total.checked_add(*amount))
+                .ok_or(Error::RewardExceedsPool)?;
+            if total > self.accumulative_reward_pool {
+                return Err(Error::RewardExceedsPool);
+            }
+            if total > self.env().balance() {
+                return Err(Error::InsufficientContractBalance);
+            }
+            for (account_id, amount) in recipients {
+                self.env()
+                    .transfer(account_id, amount)
+                    .map_err(|_| Error::FailedToPayNodeReward)?;
+            }
+            self.accumulative_reward_pool = self.accumulative_reward_pool.saturating_sub(total);
+            Ok(())
+        }
+
         #[ink(message)]
         pub fn get_merchant_volume(&self) -> Balance {
             self.merchant_volume
@@ -106,25 +489,383 @@ mod mining_pool {
 
         #[ink(message)]
         pub fn update_pool_and_retrieve(&mut self, session_index: u32) -> Result<Balance, Error> {
+            let update = self.update_pool_and_retrieve_v2(session_index)?;
+            Ok(update.reward_pool)
+        }
+
+        /// same as `update_pool_and_retrieve` but also returns the session delta
+        #[ink(message)]
+        pub fn update_pool_and_retrieve_v2(
+            &mut self,
+            session_index: u32
+        ) -> Result<SessionPoolUpdate, Error> {
             self.only_callable_by(self.node_reward_contract)?;
+            self.record_call(CallKind::UpdatePool);
+            if let Some(successor) = self.forwarding_target() {
+                let selector = selector_bytes!("update_pool_and_retrieve_v2");
+                let result = build_call::<D9Environment>()
+                    .call(successor)
+                    .gas_limit(0)
+                    .exec_input(ExecutionInput::new(Selector::new(selector)).push_arg(session_index))
+                    .returns::<Result<SessionPoolUpdate, Error>>()
+                    .try_invoke();
+                return self.forwarded_result(successor, selector, result);
+            }
+            // a keeper got here first, hand over its update instead of accruing twice
+            if let Some(update) = self.keeper_session_updates.take(session_index) {
+                return Ok(update);
+            }
 
-            self.last_session = session_index;
             let total_volume = self.get_total_volume();
-            self.volume_at_index.insert(session_index, &total_volume);
+            self.apply_session_volume(session_index, total_volume)
+        }
+
+        /// what `commit_session` would apply for `session_index` right now, without changing
+        /// anything. lets the node reward contract see the reward before it splits it
+        #[ink(message)]
+        pub fn compute_session_reward(
+            &self,
+            session_index: u32
+        ) -> Result<SessionComputation, Error> {
+            if let Some(successor) = self.forwarding_target() {
+                let selector = selector_bytes!("compute_session_reward");
+                let result = build_call::<D9Environment>()
+                    .call(successor)
+                    .gas_limit(0)
+                    .exec_input(ExecutionInput::new(Selector::new(selector)).push_arg(session_index))
+                    .returns::<Result<SessionComputation, Error>>()
+                    .try_invoke();
+                return self.forwarded_result(successor, selector, result);
+            }
+            if let Some(update) = self.keeper_session_updates.get(session_index) {
+                return Ok(self.keeper_computation(update));
+            }
+            let total_volume = self.get_total_volume();
+            self.calc_session_computation(session_index, total_volume)
+        }
+
+        /// applies a session computed by `compute_session_reward`. fails with
+        /// `StaleSessionComputation` when the session computes differently by now, e.g. because
+        /// of merchant volume in between
+        #[ink(message)]
+        pub fn commit_session(
+            &mut self,
+            session_index: u32,
+            computation_hash: Hash
+        ) -> Result<(), Error> {
+            self.only_callable_by(self.node_reward_contract)?;
+            self.record_call(CallKind::UpdatePool);
+            if let Some(successor) = self.forwarding_target() {
+                let selector = selector_bytes!("commit_session");
+                let result = build_call::<D9Environment>()
+                    .call(successor)
+                    .gas_limit(0)
+                    .exec_input(
+                        ExecutionInput::new(Selector::new(selector))
+                            .push_arg(session_index)
+                            .push_arg(computation_hash)
+                    )
+                    .returns::<Result<(), Error>>()
+                    .try_invoke();
+                return self.forwarded_result(successor, selector, result);
+            }
+            if let Some(update) = self.keeper_session_updates.get(session_index) {
+                if self.keeper_computation(update).hash() != computation_hash {
+                    return Err(Error::StaleSessionComputation);
+                }
+                self.keeper_session_updates.remove(session_index);
+                return Ok(());
+            }
+            let total_volume = self.get_total_volume();
+            self.apply_session_computation(session_index, total_volume, computation_hash)
+        }
+
+        fn calc_session_computation(
+            &self,
+            session_index: u32,
+            total_volume: Balance
+        ) -> Result<SessionComputation, Error> {
+            let breakdown = self.calc_reward_breakdown(session_index, total_volume)?;
+            Ok(SessionComputation {
+                session_index,
+                total_volume,
+                session_delta: breakdown.session_delta,
+                accumulative_reward_pool: breakdown.accumulative_after,
+                reward_pool: breakdown.reward_pool,
+            })
+        }
+
+        /// a session a keeper already accrued computes to the update it left behind
+        fn keeper_computation(&self, update: SessionPoolUpdate) -> SessionComputation {
+            SessionComputation {
+                session_index: update.session_index,
+                total_volume: self.volume_at_index.get(update.session_index).unwrap_or(0),
+                session_delta: update.session_delta,
+                accumulative_reward_pool: update.accumulative_reward_pool,
+                reward_pool: update.reward_pool,
+            }
+        }
+
+        fn apply_session_computation(
+            &mut self,
+            session_index: u32,
+            total_volume: Balance,
+            computation_hash: Hash
+        ) -> Result<(), Error> {
+            let computation = self.calc_session_computation(session_index, total_volume)?;
+            if computation.hash() != computation_hash {
+                return Err(Error::StaleSessionComputation);
+            }
+            self.apply_session_volume(session_index, total_volume)?;
+            Ok(())
+        }
+
+        /// permissionless session update for when the node reward pipeline stalls. accrues like
+        /// `update_pool_and_retrieve`, pays the caller `keeper_tip` from the pool and keeps the
+        /// update for `collect_session_reward`
+        #[ink(message)]
+        pub fn trigger_session_update(&mut self, session_index: u32) -> Result<Balance, Error> {
+            if self.retired {
+                return Err(Error::AlreadyRetired);
+            }
+            if self.keeper_tip == 0 {
+                return Err(Error::KeeperUpdatesDisabled);
+            }
+            let current_session = self
+                .env()
+                .extension()
+                .get_current_session_index()
+                .map_err(|_| Error::FailedToGetCurrentSession)?;
+            let total_volume = self.get_total_volume();
+            self.apply_keeper_update(
+                self.env().caller(),
+                session_index,
+                current_session,
+                total_volume
+            )
+        }
+
+        /// the update recorded by `trigger_session_update`, each session can be collected once
+        #[ink(message)]
+        pub fn collect_session_reward(
+            &mut self,
+            session_index: u32
+        ) -> Result<SessionPoolUpdate, Error> {
+            self.only_callable_by(self.node_reward_contract)?;
+            self.record_call(CallKind::UpdatePool);
+            self.keeper_session_updates
+                .take(session_index)
+                .ok_or(Error::NoSessionRewardToCollect)
+        }
+
+        /// update recorded by a keeper and not collected yet
+        #[ink(message)]
+        pub fn get_keeper_session_update(&self, session_index: u32) -> Option<SessionPoolUpdate> {
+            self.keeper_session_updates.get(session_index)
+        }
+
+        #[ink(message)]
+        pub fn get_keeper_tip(&self) -> Balance {
+            self.keeper_tip
+        }
+
+        #[ink(message)]
+        pub fn set_keeper_tip(&mut self, keeper_tip: Balance) -> Result<(), Error> {
+            self.only_callable_by(self.admin)?;
+            self.keeper_tip = keeper_tip;
+            Ok(())
+        }
+
+        /// only sessions that ended on chain, i.e. before `current_session`, can be updated, and
+        /// each of them once
+        fn apply_keeper_update(
+            &mut self,
+            keeper: AccountId,
+            session_index: u32,
+            current_session: u32,
+            total_volume: Balance
+        ) -> Result<Balance, Error> {
+            if session_index >= current_session {
+                return Err(Error::SessionNotEnded);
+            }
+            let has_sessions = self.volume_at_index.contains(self.last_session);
+            if has_sessions && session_index <= self.last_session {
+                return Err(Error::SessionAlreadyUpdated);
+            }
+            let update = self.apply_session_volume(session_index, total_volume)?;
+            let tip = Self::calc_keeper_tip(
+                self.keeper_tip,
+                update.accumulative_reward_pool,
+                update.reward_pool
+            );
+            if tip > 0 {
+                self.env()
+                    .transfer(keeper, tip)
+                    .map_err(|_| Error::FailedToPayKeeperTip)?;
+                self.accumulative_reward_pool = self.accumulative_reward_pool.saturating_sub(tip);
+                self.env().emit_event(KeeperTipPaid { keeper, session_index, tip });
+            }
+            self.keeper_session_updates.insert(
+                session_index,
+                &(SessionPoolUpdate {
+                    accumulative_reward_pool: self.accumulative_reward_pool,
+                    ..update
+                })
+            );
+            Ok(tip)
+        }
+
+        /// the tip never eats into the reward released for the session
+        fn calc_keeper_tip(
+            keeper_tip: Balance,
+            accumulative_reward_pool: Balance,
+            reward_pool: Balance
+        ) -> Balance {
+            keeper_tip.min(accumulative_reward_pool.saturating_sub(reward_pool))
+        }
+
+        /// (session_delta, three_percent_of_delta, accumulative_after, ten_percent_release)
+        /// that `update_pool_and_retrieve` would produce for `session_index` right now
+        #[ink(message)]
+        pub fn get_reward_pool_breakdown(
+            &self,
+            session_index: u32
+        ) -> Result<(Balance, Balance, Balance, Balance), Error> {
+            let total_volume = self.get_total_volume();
+            let breakdown = self.calc_reward_breakdown(session_index, total_volume)?;
+            Ok((
+                breakdown.session_delta,
+                breakdown.three_percent_of_delta,
+                breakdown.accumulative_after,
+                breakdown.reward_pool,
+            ))
+        }
 
+        fn calc_reward_breakdown(
+            &self,
+            session_index: u32,
+            total_volume: Balance
+        ) -> Result<RewardBreakdown, Error> {
             let session_delta = self.calculate_session_delta(session_index, total_volume)?;
             let three_percent: Perquintill = Perquintill::from_percent(3);
             let three_percent_of_delta = three_percent.mul_floor(session_delta);
-            self.accumulative_reward_pool =
-                self.accumulative_reward_pool.saturating_add(three_percent_of_delta);
+            let (accumulative_after, discarded) = Self::apply_pool_cap(
+                self.accumulative_reward_pool,
+                three_percent_of_delta,
+                self.max_accumulative_pool
+            );
             let ten_percent = Perquintill::from_percent(10);
-            let reward_pool = ten_percent.mul_floor(self.accumulative_reward_pool);
-            Ok(reward_pool)
+            let requested = ten_percent.mul_floor(accumulative_after);
+            let reward_pool = Self::apply_reward_cap(requested, self.max_reward_per_session);
+            Ok(RewardBreakdown {
+                session_delta,
+                three_percent_of_delta,
+                accumulative_after,
+                discarded,
+                requested,
+                reward_pool,
+            })
+        }
+
+        /// (pool after the contribution, discarded part of the contribution)
+        ///
+        /// a pool already above a lowered cap keeps its funds, it just stops growing
+        fn apply_pool_cap(
+            accumulative_reward_pool: Balance,
+            contribution: Balance,
+            max_accumulative_pool: Balance
+        ) -> (Balance, Balance) {
+            let uncapped = accumulative_reward_pool.saturating_add(contribution);
+            let capped = uncapped.min(max_accumulative_pool).max(accumulative_reward_pool);
+            (capped, contribution.saturating_sub(capped - accumulative_reward_pool))
+        }
+
+        fn apply_session_volume(
+            &mut self,
+            session_index: u32,
+            total_volume: Balance
+        ) -> Result<SessionPoolUpdate, Error> {
+            let breakdown = self.calc_reward_breakdown(session_index, total_volume)?;
+            self.last_session = session_index;
+            self.volume_at_index.insert(session_index, &total_volume);
+            self.accumulative_reward_pool = breakdown.accumulative_after;
+            if breakdown.discarded > 0 {
+                self.env().emit_event(PoolCapReached {
+                    session_index,
+                    max_accumulative_pool: self.max_accumulative_pool,
+                    discarded: breakdown.discarded,
+                });
+            }
+            if breakdown.reward_pool < breakdown.requested {
+                self.env().emit_event(RewardSmoothed {
+                    requested: breakdown.requested,
+                    released: breakdown.reward_pool,
+                });
+            }
+            Ok(SessionPoolUpdate {
+                session_index,
+                reward_pool: breakdown.reward_pool,
+                session_delta: breakdown.session_delta,
+                accumulative_reward_pool: self.accumulative_reward_pool,
+            })
+        }
+
+        #[ink(message)]
+        pub fn get_max_reward_per_session(&self) -> Option<Balance> {
+            self.max_reward_per_session
+        }
+
+        /// `None` removes the cap
+        #[ink(message)]
+        pub fn set_max_reward_per_session(
+            &mut self,
+            max_reward_per_session: Option<Balance>
+        ) -> Result<(), Error> {
+            self.only_callable_by(self.admin)?;
+            self.max_reward_per_session = max_reward_per_session;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_max_accumulative_pool(&self) -> Balance {
+            self.max_accumulative_pool
+        }
+
+        /// `Balance::MAX` removes the cap
+        #[ink(message)]
+        pub fn set_max_accumulative_pool(
+            &mut self,
+            max_accumulative_pool: Balance
+        ) -> Result<(), Error> {
+            self.only_callable_by(self.admin)?;
+            self.max_accumulative_pool = max_accumulative_pool;
+            Ok(())
+        }
+
+        /// only the released amount is deducted later, so the excess carries forward in the pool
+        fn apply_reward_cap(
+            reward_pool: Balance,
+            max_reward_per_session: Option<Balance>
+        ) -> Balance {
+            match max_reward_per_session {
+                Some(max_reward) => reward_pool.min(max_reward),
+                None => reward_pool,
+            }
         }
 
         #[ink(message)]
         pub fn deduct_from_reward_pool(&mut self, amount: Balance) -> Result<(), Error> {
             let _ = self.only_callable_by(self.node_reward_contract)?;
+            if let Some(successor) = self.forwarding_target() {
+                let selector = selector_bytes!("deduct_from_reward_pool");
+                let result = build_call::<D9Environment>()
+                    .call(successor)
+                    .gas_limit(0)
+                    .exec_input(ExecutionInput::new(Selector::new(selector)).push_arg(amount))
+                    .returns::<Result<(), Error>>()
+                    .try_invoke();
+                return self.forwarded_result(successor, selector, result);
+            }
             self.accumulative_reward_pool = self.accumulative_reward_pool.saturating_sub(amount);
             Ok(())
         }
@@ -135,6 +876,10 @@ mod mining_pool {
             current_volume: Balance
         ) -> Result<Balance, Error> {
             let previous_index = self.get_previous_valid_session_index(session_index);
+            // session 0 has no predecessor, its own volume is the baseline
+            if previous_index == session_index {
+                return Ok(0);
+            }
             let previous_volume = self.volume_at_index.get(&previous_index).unwrap_or(0);
             let session_delta = current_volume.saturating_sub(previous_volume);
             Ok(session_delta)
@@ -150,15 +895,144 @@ mod mining_pool {
 
         #[ink(message)]
         pub fn get_total_volume(&self) -> Balance {
-            let total_burned = self.get_total_burned();
+            self.calc_total_volume(self.get_total_burned())
+        }
+
+        /// total volume under the current weights and burn rate
+        fn calc_total_volume(&self, total_burned: Balance) -> Balance {
+            let total_burned = Self::convert_burn_to_volume(total_burned, self.burn_to_volume_rate);
             let total_merchant_mined = self.merchant_volume;
-            total_burned.saturating_add(total_merchant_mined)
+            Self::weigh_volume(
+                total_burned,
+                total_merchant_mined,
+                self.burn_weight_bps,
+                self.merchant_weight_bps
+            )
+        }
+
+        /// (burn_weight_bps, merchant_weight_bps)
+        #[ink(message)]
+        pub fn get_volume_weights(&self) -> (u32, u32) {
+            (self.burn_weight_bps, self.merchant_weight_bps)
+        }
+
+        /// volume from before the change keeps its old weight, see `apply_volume_settings`
+        #[ink(message)]
+        pub fn set_volume_weights(
+            &mut self,
+            burn_weight_bps: u32,
+            merchant_weight_bps: u32
+        ) -> Result<(), Error> {
+            self.only_callable_by(self.admin)?;
+            let total_burned = self.get_total_burned_if_rebasing();
+            self.apply_volume_settings(
+                total_burned,
+                burn_weight_bps,
+                merchant_weight_bps,
+                self.burn_to_volume_rate
+            );
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_burn_to_volume_rate(&self) -> Balance {
+            self.burn_to_volume_rate
+        }
+
+        /// like the volume weights, burns from before the change keep the old rate
+        #[ink(message)]
+        pub fn set_burn_to_volume_rate(&mut self, burn_to_volume_rate: Balance) -> Result<(), Error> {
+            self.only_callable_by(self.admin)?;
+            let total_burned = self.get_total_burned_if_rebasing();
+            self.apply_volume_settings(
+                total_burned,
+                self.burn_weight_bps,
+                self.merchant_weight_bps,
+                burn_to_volume_rate
+            );
+            Ok(())
+        }
+
+        /// the burn total is only needed to rebase a recorded session
+        fn get_total_burned_if_rebasing(&self) -> Balance {
+            if self.volume_at_index.contains(self.last_session) {
+                self.get_total_burned()
+            } else {
+                0
+            }
+        }
+
+        /// changes how the total volume is weighted. the last recorded session volume is moved
+        /// by as much as the current total moves, so the next session delta only counts volume
+        /// after the change at the new settings instead of reweighting everything before it
+        fn apply_volume_settings(
+            &mut self,
+            total_burned: Balance,
+            burn_weight_bps: u32,
+            merchant_weight_bps: u32,
+            burn_to_volume_rate: Balance
+        ) {
+            let old_total = self.calc_total_volume(total_burned);
+            self.burn_weight_bps = burn_weight_bps;
+            self.merchant_weight_bps = merchant_weight_bps;
+            self.burn_to_volume_rate = burn_to_volume_rate;
+            let Some(recorded) = self.volume_at_index.get(self.last_session) else {
+                return;
+            };
+            let new_total = self.calc_total_volume(total_burned);
+            let rebased = if new_total >= old_total {
+                recorded.saturating_add(new_total - old_total)
+            } else {
+                recorded.saturating_sub(old_total - new_total)
+            };
+            self.volume_at_index.insert(self.last_session, &rebased);
+        }
+
+        /// burned d9 expressed in the unit of merchant volume
+        fn convert_burn_to_volume(burned: Balance, burn_to_volume_rate: Balance) -> Balance {
+            multiply_by_rational_with_rounding(
+                burned,
+                burn_to_volume_rate,
+                PRICE_PRECISION,
+                Rounding::Down
+            ).unwrap_or(Balance::MAX)
+        }
+
+        fn weigh_volume(
+            burned: Balance,
+            merchant_volume: Balance,
+            burn_weight_bps: u32,
+            merchant_weight_bps: u32
+        ) -> Balance {
+            let apply_weight = |volume: Balance, weight_bps: u32| {
+                multiply_by_rational_with_rounding(
+                    volume,
+                    weight_bps as Balance,
+                    BASE_WEIGHT_BPS as Balance,
+                    Rounding::Down
+                ).unwrap_or(Balance::MAX)
+            };
+            apply_weight(burned, burn_weight_bps).saturating_add(
+                apply_weight(merchant_volume, merchant_weight_bps)
+            )
         }
 
         #[ink(message, payable)]
         pub fn process_merchant_payment(&mut self, merchant_id:AccountId) -> Result<(), Error> {
             let _ = self.only_callable_by(self.merchant_contract)?;
+            self.record_call(CallKind::ProcessMerchantPayment);
             let received_amount = self.env().transferred_value();
+            if let Some(successor) = self.forwarding_target() {
+                let selector = selector_bytes!("process_merchant_payment");
+                let result = build_call::<D9Environment>()
+                    .call(successor)
+                    .gas_limit(0)
+                    .transferred_value(received_amount)
+                    .exec_input(ExecutionInput::new(Selector::new(selector)).push_arg(merchant_id))
+                    .returns::<Result<(), Error>>()
+                    .try_invoke();
+                return self.forwarded_result(successor, selector, result);
+            }
             self.merchant_volume = self.merchant_volume.saturating_add(received_amount);
             
             // give merchant votes
@@ -176,44 +1050,485 @@ mod mining_pool {
             votes as u64
         }
 
+        #[ink(message)]
+        pub fn get_highest_rate(&self) -> Balance {
+            self.highest_rate
+        }
+
+        #[ink(message)]
+        pub fn get_protection_floor_bps(&self) -> u32 {
+            self.protection_floor_bps
+        }
+
+        /// share of `highest_rate` redemptions are protected at. off (0) by default, the anchor
+        /// only ever rises so a floor keeps paying above a market that fell for good
+        #[ink(message)]
+        pub fn set_protection_floor_bps(&mut self, protection_floor_bps: u32) -> Result<(), Error> {
+            self.only_callable_by(self.admin)?;
+            if protection_floor_bps > MAX_PROTECTION_FLOOR_BPS {
+                return Err(Error::InvalidProtectionFloor);
+            }
+            self.protection_floor_bps = protection_floor_bps;
+            Ok(())
+        }
+
+        /// pay a merchant user d9 for their redeemable usdt, at the market rate unless the
+        /// protection floor is set and higher
         #[ink(message)]
         pub fn merchant_user_redeem_d9(
-            &self,
+            &mut self,
             user_account: AccountId,
             redeemable_usdt: Balance
         ) -> Result<Balance, Error> {
             let _ = self.only_callable_by(self.merchant_contract)?;
+            self.record_call(CallKind::RedeemD9);
+            if let Some(successor) = self.forwarding_target() {
+                let selector = selector_bytes!("merchant_user_redeem_d9");
+                let result = build_call::<D9Environment>()
+                    .call(successor)
+                    .gas_limit(0)
+                    .exec_input(
+                        ExecutionInput::new(Selector::new(selector))
+                            .push_arg(user_account)
+                            .push_arg(redeemable_usdt)
+                    )
+                    .returns::<Result<Balance, Error>>()
+                    .try_invoke();
+                return self.forwarded_result(successor, selector, result);
+            }
+
+            let (redemption, rate_source) = self.quote_redemption(redeemable_usdt)?;
+            self.record_redemption(&redemption);
+            if self.pay_or_queue_redemption(user_account, redemption.d9_amount) {
+                self.env().emit_event(RedemptionPaid {
+                    user: user_account,
+                    d9_amount: redemption.d9_amount,
+                    rate_source,
+                });
+            }
+            Ok(redemption.d9_amount)
+        }
 
-            let amount_request = self.get_exchange_amount(
-                Direction(Currency::USDT, Currency::D9),
-                redeemable_usdt
+        /// an underfunded pool still settles the redemption, so the merchant contract deducts
+        /// the red points now. the d9 is owed until `claim_pending_redemption`. true if paid
+        fn pay_or_queue_redemption(&mut self, user: AccountId, d9_amount: Balance) -> bool {
+            // d9 already owed to queued redemptions is not free to pay out again
+            let available = self.env().balance().saturating_sub(self.total_pending_redemptions);
+            let is_funded = available >= d9_amount;
+            if is_funded && self.env().transfer(user, d9_amount).is_ok() {
+                return true;
+            }
+            let pending = self.get_pending_redemption(user).saturating_add(d9_amount);
+            self.pending_redemptions.insert(user, &pending);
+            self.total_pending_redemptions = self.total_pending_redemptions.saturating_add(
+                d9_amount
             );
-            if amount_request.is_err() {
-                return Err(Error::FailedToGetExchangeAmount);
+            self.env().emit_event(RedemptionQueued { user, d9_amount, pending });
+            false
+        }
+
+        /// pay out everything owed to the caller from queued redemptions
+        #[ink(message)]
+        pub fn claim_pending_redemption(&mut self) -> Result<Balance, Error> {
+            let caller = self.env().caller();
+            let pending = self.get_pending_redemption(caller);
+            if pending == 0 {
+                return Err(Error::NoPendingRedemption);
             }
-            let d9_amount = amount_request.unwrap();
-            let transfer_to_user_result = self.env().transfer(user_account, d9_amount);
-            if transfer_to_user_result.is_err() {
+            if self.env().balance() < pending {
                 return Err(Error::FailedToTransferD9ToUser);
             }
-            Ok(d9_amount)
+            self.env()
+                .transfer(caller, pending)
+                .map_err(|_| Error::FailedToTransferD9ToUser)?;
+            self.pending_redemptions.remove(caller);
+            self.total_pending_redemptions = self.total_pending_redemptions.saturating_sub(pending);
+            self.env().emit_event(RedemptionClaimed { user: caller, d9_amount: pending });
+            Ok(pending)
         }
 
+        #[ink(message)]
+        pub fn get_pending_redemption(&self, user: AccountId) -> Balance {
+            self.pending_redemptions.get(user).unwrap_or(0)
+        }
+
+        /// d9 the pool owes across all queued redemptions
+        #[ink(message)]
+        pub fn get_total_pending_redemptions(&self) -> Balance {
+            self.total_pending_redemptions
+        }
+
+        /// what `merchant_user_redeem_d9` would pay right now, without moving the anchor
+        #[ink(message)]
+        pub fn preview_redeem_d9(&self, redeemable_usdt: Balance) -> Result<RedeemPreview, Error> {
+            self.quote_redemption(redeemable_usdt).map(|(redemption, _)| redemption)
+        }
+
+        /// prefers the oracle twap, the amm quote covers a missing or failing oracle but not a
+        /// stale one
+        fn quote_redemption(
+            &self,
+            redeemable_usdt: Balance
+        ) -> Result<(RedeemPreview, RateSource), Error> {
+            let common_decimals = self.d9_decimals.max(self.usdt_decimals);
+            let usdt_amount = Self::to_common_scale(
+                redeemable_usdt,
+                self.usdt_decimals,
+                common_decimals
+            );
+            if let Some(oracle_rate) = self.get_oracle_rate()? {
+                let redemption = Self::calc_protected_redemption_from_rate(
+                    usdt_amount,
+                    oracle_rate,
+                    self.highest_rate,
+                    self.protection_floor_bps
+                )?;
+                let redemption = Self::redemption_in_d9_units(
+                    redemption,
+                    self.d9_decimals,
+                    common_decimals
+                )?;
+                return Ok((redemption, RateSource::Oracle));
+            }
+            let (amm, market_d9_amount) = self
+                .get_exchange_amount(REDEEM_DIRECTION, redeemable_usdt)
+                .map_err(|_| Error::FailedToGetExchangeAmount)?;
+            let redemption = Self::calc_protected_redemption(
+                usdt_amount,
+                Self::to_common_scale(market_d9_amount, self.d9_decimals, common_decimals),
+                self.highest_rate,
+                self.protection_floor_bps
+            )?;
+            let redemption = Self::redemption_in_d9_units(
+                redemption,
+                self.d9_decimals,
+                common_decimals
+            )?;
+            Ok((redemption, RateSource::Amm(amm)))
+        }
+
+        /// (d9 decimals, usdt decimals)
+        #[ink(message)]
+        pub fn get_decimals(&self) -> (u8, u8) {
+            (self.d9_decimals, self.usdt_decimals)
+        }
+
+        /// `amount` in base units of a currency with `decimals`, rescaled to `common_decimals`
+        fn to_common_scale(amount: Balance, decimals: u8, common_decimals: u8) -> Balance {
+            amount.saturating_mul(
+                (10 as Balance).saturating_pow(common_decimals.saturating_sub(decimals).into())
+            )
+        }
+
+        /// converts the payout of a redemption priced at `common_decimals` back to d9 base units
+        fn redemption_in_d9_units(
+            redemption: RedeemPreview,
+            d9_decimals: u8,
+            common_decimals: u8
+        ) -> Result<RedeemPreview, Error> {
+            let d9_amount = redemption.d9_amount.saturating_div(
+                (10 as Balance).saturating_pow(common_decimals.saturating_sub(d9_decimals).into())
+            );
+            if d9_amount == 0 {
+                return Err(Error::PayoutRoundedToZero);
+            }
+            Ok(RedeemPreview { d9_amount, ..redemption })
+        }
+
+        #[ink(message)]
+        pub fn get_price_oracle(&self) -> Option<AccountId> {
+            self.price_oracle
+        }
+
+        /// `None` switches redemptions back to the amm quote
+        #[ink(message)]
+        pub fn set_price_oracle(&mut self, price_oracle: Option<AccountId>) -> Result<(), Error> {
+            self.only_callable_by(self.admin)?;
+            self.price_oracle = price_oracle;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_max_oracle_age_ms(&self) -> Timestamp {
+            self.max_oracle_age_ms
+        }
+
+        #[ink(message)]
+        pub fn set_max_oracle_age_ms(&mut self, max_oracle_age_ms: Timestamp) -> Result<(), Error> {
+            self.only_callable_by(self.admin)?;
+            self.max_oracle_age_ms = max_oracle_age_ms;
+            Ok(())
+        }
+
+        /// oracle twap in whole d9 per whole usdt, `None` when unset or unusable
+        fn get_oracle_rate(&self) -> Result<Option<Balance>, Error> {
+            let Some(oracle) = self.price_oracle else {
+                return Ok(None);
+            };
+            let result = build_call::<D9Environment>()
+                .call(oracle)
+                .gas_limit(0)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(selector_bytes!("get_twap"))).push_arg(
+                        ORACLE_TWAP_WINDOW_MS
+                    )
+                )
+                .returns::<(Balance, Timestamp)>()
+                .try_invoke();
+            Self::oracle_rate_from(result, self.env().block_timestamp(), self.max_oracle_age_ms)
+        }
+
+        /// a failed call, an undecodable reply or a zero rate all fall back to the amm, a rate
+        /// updated more than `max_age_ms` before `now` is an error
+        fn oracle_rate_from(
+            result: ink::env::Result<ink::MessageResult<(Balance, Timestamp)>>,
+            now: Timestamp,
+            max_age_ms: Timestamp
+        ) -> Result<Option<Balance>, Error> {
+            match result {
+                Ok(Ok((rate, updated_at))) if rate > 0 => {
+                    if now.saturating_sub(updated_at) > max_age_ms {
+                        return Err(Error::OracleStale);
+                    }
+                    Ok(Some(rate))
+                }
+                _ => Ok(None),
+            }
+        }
+
+        /// (protected redemptions, d9 paid at the floor rate, d9 paid at the market rate)
+        #[ink(message)]
+        pub fn get_redemption_stats(&self) -> (u64, Balance, Balance) {
+            (
+                self.total_protected_redemptions,
+                self.total_protected_d9_paid,
+                self.total_market_d9_paid,
+            )
+        }
+
+        fn record_redemption(&mut self, redemption: &RedeemPreview) {
+            self.highest_rate = redemption.highest_rate;
+            if redemption.protection_applied {
+                self.total_protected_redemptions =
+                    self.total_protected_redemptions.saturating_add(1);
+                self.total_protected_d9_paid = self.total_protected_d9_paid.saturating_add(
+                    redemption.d9_amount
+                );
+            } else {
+                self.total_market_d9_paid = self.total_market_d9_paid.saturating_add(
+                    redemption.d9_amount
+                );
+            }
+        }
+
+        /// price a redemption at the rate of a market quote, see
+        /// `calc_protected_redemption_from_rate`
+        fn calc_protected_redemption(
+            redeemable_usdt: Balance,
+            market_d9_amount: Balance,
+            highest_rate: Balance,
+            protection_floor_bps: u32
+        ) -> Result<RedeemPreview, Error> {
+            let current_rate = market_d9_amount
+                .saturating_mul(PRICE_PRECISION)
+                .checked_div(redeemable_usdt)
+                .unwrap_or(0);
+            Self::calc_protected_redemption_from_rate(
+                redeemable_usdt,
+                current_rate,
+                highest_rate,
+                protection_floor_bps
+            )
+        }
+
+        /// price a redemption at a market rate already in d9 per usdt, raised to
+        /// `protection_floor_bps` of the anchor when that is higher. amounts must share a scale
+        fn calc_protected_redemption_from_rate(
+            redeemable_usdt: Balance,
+            current_rate: Balance,
+            highest_rate: Balance,
+            protection_floor_bps: u32
+        ) -> Result<RedeemPreview, Error> {
+            if current_rate == 0 {
+                return Err(Error::RateComputationFailed);
+            }
+            // the anchor starts at the first observed rate and is never zero
+            let highest_rate = highest_rate.max(current_rate);
+            let floor_rate = highest_rate
+                .saturating_mul(protection_floor_bps.into())
+                .saturating_div(MAX_PROTECTION_FLOOR_BPS.into());
+            let protection_applied = current_rate < floor_rate;
+            let effective_rate = current_rate.max(floor_rate);
+            let d9_amount = effective_rate
+                .saturating_mul(redeemable_usdt)
+                .saturating_div(PRICE_PRECISION);
+            if d9_amount == 0 {
+                return Err(Error::PayoutRoundedToZero);
+            }
+            Ok(RedeemPreview {
+                d9_amount,
+                current_rate,
+                highest_rate,
+                effective_rate,
+                protection_applied,
+            })
+        }
+
+        /// (amm, amount out) of the best quote across `amm_contract` and `amm_contracts`.
+        /// `amm_contract` has to answer, its quote bounds which of the others count
         fn get_exchange_amount(
             &self,
             direction: Direction,
             amount: Balance
-        ) -> Result<Balance, Error> {
-            build_call::<D9Environment>()
+        ) -> Result<(AccountId, Balance), Error> {
+            let selector = Selector::new(selector_bytes!("calculate_exchange"));
+            let reference = build_call::<D9Environment>()
+                .call(self.amm_contract)
+                .gas_limit(0)
+                .exec_input(ExecutionInput::new(selector).push_arg(direction).push_arg(amount))
+                .returns::<Result<Balance, Error>>()
+                .invoke()?;
+            if self.amm_contracts.is_empty() {
+                return Ok((self.amm_contract, reference));
+            }
+            let quotes = self.amm_contracts.iter().map(|amm| {
+                let result = build_call::<D9Environment>()
+                    .call(*amm)
+                    .gas_limit(0)
+                    .exec_input(ExecutionInput::new(selector).push_arg(direction).push_arg(amount))
+                    .returns::<Result<Balance, Error>>()
+                    .try_invoke();
+                (*amm, result)
+            });
+            Ok(Self::best_quote((self.amm_contract, reference), quotes))
+        }
+
+        /// the highest output among the quotes within `MAX_AMM_QUOTE_DEVIATION_BPS` of the
+        /// reference quote, so a pool pushed off the market can't set the rate. a failing amm is
+        /// passed over and ties stay with the earlier amm
+        fn best_quote(
+            reference: (AccountId, Balance),
+            quotes: impl Iterator<
+                Item = (AccountId, ink::env::Result<ink::MessageResult<Result<Balance, Error>>>)
+            >
+        ) -> (AccountId, Balance) {
+            let band = reference.1.saturating_mul(MAX_AMM_QUOTE_DEVIATION_BPS) / 10_000;
+            let (low, high) = (reference.1.saturating_sub(band), reference.1.saturating_add(band));
+            quotes
+                .filter_map(|(amm, result)| {
+                    match result {
+                        Ok(Ok(Ok(amount_out))) if low <= amount_out && amount_out <= high => {
+                            Some((amm, amount_out))
+                        }
+                        _ => None,
+                    }
+                })
+                .fold(reference, |best, quote| if quote.1 > best.1 { quote } else { best })
+        }
+
+        #[ink(message)]
+        pub fn get_amm_contracts(&self) -> Vec<AccountId> {
+            self.amm_contracts.clone()
+        }
+
+        /// amms quoted next to `amm_contract`, an empty list quotes `amm_contract` alone
+        #[ink(message)]
+        pub fn set_amm_contracts(&mut self, amm_contracts: Vec<AccountId>) -> Result<(), Error> {
+            self.only_callable_by(self.admin)?;
+            if amm_contracts.len() > MAX_AMM_CONTRACTS {
+                return Err(Error::TooManyAmmContracts);
+            }
+            let zero_address = AccountId::from([0u8; 32]);
+            if amm_contracts.iter().any(|amm| *amm == zero_address || *amm == self.amm_contract) {
+                return Err(Error::InvalidContractAddress);
+            }
+            self.amm_contracts = amm_contracts;
+            Ok(())
+        }
+
+        /// direction redemptions are quoted in on the amm
+        #[ink(message)]
+        pub fn get_configured_direction(&self) -> Direction {
+            REDEEM_DIRECTION
+        }
+
+        /// quote one whole unit through the amm both ways to confirm it trades d9 <> usdt
+        #[ink(message)]
+        pub fn verify_pair(&self) -> Result<(), Error> {
+            self.only_callable_by(self.admin)?;
+            self.check_pair()
+        }
+
+        fn check_pair(&self) -> Result<(), Error> {
+            let directions = [REDEEM_DIRECTION, Direction(Currency::D9, Currency::USDT)];
+            for direction in directions {
+                let result = build_call::<D9Environment>()
+                    .call(self.amm_contract)
+                    .gas_limit(0)
+                    .exec_input(
+                        ExecutionInput::new(Selector::new(selector_bytes!("estimate_exchange")))
+                            .push_arg(direction)
+                            .push_arg(self.calc_pair_probe_amount(direction))
+                    )
+                    .returns::<Result<(Balance, Balance), Error>>()
+                    .try_invoke();
+                if !Self::is_pair_quote(result) {
+                    return Err(Error::IncompatibleAmmPair);
+                }
+            }
+            Ok(())
+        }
+
+        /// one whole unit of the input currency, small amounts of d9 quote no usdt at all
+        fn calc_pair_probe_amount(&self, direction: Direction) -> Balance {
+            let decimals = match direction.0 {
+                Currency::D9 => self.d9_decimals,
+                Currency::USDT => self.usdt_decimals,
+            };
+            (10 as Balance).saturating_pow(decimals.into())
+        }
+
+        /// only a successful quote with a nonzero output counts, anything the call can't
+        /// decode means the contract isn't a compatible amm
+        fn is_pair_quote(
+            result: ink::env::Result<ink::MessageResult<Result<(Balance, Balance), Error>>>
+        ) -> bool {
+            matches!(result, Ok(Ok(Ok((_, amount_out)))) if amount_out > 0)
+        }
+
+        /// (amm_d9_reserve, amm_usdt_reserve, accumulative_reward_pool, total_burned,
+        /// merchant_volume) for dashboards, a failing read reports 0 for its part
+        #[ink(message)]
+        pub fn get_ecosystem_snapshot(&self) -> (Balance, Balance, Balance, Balance, Balance) {
+            let reserves_result = build_call::<D9Environment>()
                 .call(self.amm_contract)
                 .gas_limit(0)
                 .exec_input(
-                    ExecutionInput::new(Selector::new(selector_bytes!("calculate_exchange")))
-                        .push_arg(direction)
-                        .push_arg(amount)
+                    ExecutionInput::new(Selector::new(selector_bytes!("get_currency_reserves")))
                 )
-                .returns::<Result<Balance, Error>>()
-                .invoke()
+                .returns::<(Balance, Balance)>()
+                .try_invoke();
+            let (amm_d9_reserve, amm_usdt_reserve) = Self::read_or_zero(reserves_result);
+            let burned_result = build_call::<D9Environment>()
+                .call(self.main_contract)
+                .gas_limit(0)
+                .exec_input(ExecutionInput::new(Selector::new(selector_bytes!("get_total_burned"))))
+                .returns::<Balance>()
+                .try_invoke();
+            (
+                amm_d9_reserve,
+                amm_usdt_reserve,
+                self.accumulative_reward_pool,
+                Self::read_or_zero(burned_result),
+                self.merchant_volume,
+            )
+        }
+
+        fn read_or_zero<T: Default>(result: ink::env::Result<ink::MessageResult<T>>) -> T {
+            match result {
+                Ok(Ok(value)) => value,
+                _ => T::default(),
+            }
         }
 
         fn get_total_burned(&self) -> Balance {
@@ -230,7 +1545,13 @@ mod mining_pool {
             &mut self,
             merchant_contract: AccountId
         ) -> Result<(), Error> {
-            let _ = self.only_callable_by(self.admin);
+            self.only_callable_by(self.admin)?;
+            Self::validate_wiring(
+                self.main_contract,
+                merchant_contract,
+                self.node_reward_contract,
+                self.amm_contract
+            )?;
             self.merchant_contract = merchant_contract;
             Ok(())
         }
@@ -246,25 +1567,95 @@ mod mining_pool {
             &mut self,
             node_reward_contract: AccountId
         ) -> Result<(), Error> {
-            let _ = self.only_callable_by(self.admin);
+            self.only_callable_by(self.admin)?;
+            Self::validate_wiring(
+                self.main_contract,
+                self.merchant_contract,
+                node_reward_contract,
+                self.amm_contract
+            )?;
             self.node_reward_contract = node_reward_contract;
             Ok(())
         }
 
         #[ink(message)]
         pub fn change_amm_contract(&mut self, amm_contract: AccountId) -> Result<(), Error> {
-            let _ = self.only_callable_by(self.admin);
+            self.only_callable_by(self.admin)?;
+            Self::validate_wiring(
+                self.main_contract,
+                self.merchant_contract,
+                self.node_reward_contract,
+                amm_contract
+            )?;
             self.amm_contract = amm_contract;
             Ok(())
         }
 
         #[ink(message)]
         pub fn change_main_contract(&mut self, main_contract: AccountId) -> Result<(), Error> {
-            let _ = self.only_callable_by(self.admin);
+            self.only_callable_by(self.admin)?;
+            Self::validate_wiring(
+                main_contract,
+                self.merchant_contract,
+                self.node_reward_contract,
+                self.amm_contract
+            )?;
             self.main_contract = main_contract;
             Ok(())
         }
 
+        /// (main, merchant, node reward, amm) contracts this pool talks to
+        #[ink(message)]
+        pub fn get_wiring(&self) -> (AccountId, AccountId, AccountId, AccountId) {
+            (
+                self.main_contract,
+                self.merchant_contract,
+                self.node_reward_contract,
+                self.amm_contract,
+            )
+        }
+
+        /// every contract must be set and fill exactly one role
+        fn validate_wiring(
+            main_contract: AccountId,
+            merchant_contract: AccountId,
+            node_reward_contract: AccountId,
+            amm_contract: AccountId
+        ) -> Result<(), Error> {
+            let wiring = [main_contract, merchant_contract, node_reward_contract, amm_contract];
+            let zero_address = AccountId::from([0u8; 32]);
+            for (i, contract) in wiring.iter().enumerate() {
+                if *contract == zero_address || wiring[i + 1..].contains(contract) {
+                    return Err(Error::InvalidContractAddress);
+                }
+            }
+            Ok(())
+        }
+
+        /// first step of an admin transfer, `new_admin` has to accept
+        #[ink(message)]
+        pub fn propose_admin(&mut self, new_admin: AccountId) -> Result<(), Error> {
+            self.only_callable_by(self.admin)?;
+            self.pending_admin = Some(new_admin);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn accept_admin(&mut self) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if self.pending_admin != Some(caller) {
+                return Err(Error::NotPendingAdmin);
+            }
+            self.admin = caller;
+            self.pending_admin = None;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_pending_admin(&self) -> Option<AccountId> {
+            self.pending_admin
+        }
+
         #[ink(message)]
         pub fn set_code(&mut self, code_hash: [u8; 32]) {
             let caller = self.env().caller();
@@ -277,6 +1668,64 @@ mod mining_pool {
             ink::env::debug_println!("Switched code hash to {:?}.", code_hash);
         }
 
+        /// retire this contract in favour of `successor`. can only be done once.
+        ///
+        /// the successor must list this contract as its merchant and node reward contract,
+        /// because forwarded calls arrive from here.
+        #[ink(message)]
+        pub fn set_successor(&mut self, successor: AccountId) -> Result<(), Error> {
+            self.only_callable_by(self.admin)?;
+            if self.retired {
+                return Err(Error::AlreadyRetired);
+            }
+            self.retired = true;
+            self.successor = Some(successor);
+            self.env().emit_event(ContractRetired { successor });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_successor(&self) -> Option<AccountId> {
+            self.successor
+        }
+
+        fn forwarding_target(&self) -> Option<AccountId> {
+            if self.retired { self.successor } else { None }
+        }
+
+        fn forwarded_result<T>(
+            &self,
+            successor: AccountId,
+            selector: [u8; 4],
+            call_result: ink::env::Result<ink::MessageResult<Result<T, Error>>>
+        ) -> Result<T, Error> {
+            match call_result {
+                Ok(Ok(result)) => {
+                    self.env().emit_event(CallForwarded { successor, selector });
+                    result
+                }
+                _ => Err(Error::ForwardingToSuccessorFailed),
+            }
+        }
+
+        /// `(call kind, calls received, last call timestamp)` for each `CallKind`, kinds never
+        /// called report 0s. calls that end in an error are reverted and not counted
+        #[ink(message)]
+        pub fn get_call_stats(&self) -> Vec<(u8, u64, Timestamp)> {
+            CALL_KINDS.iter()
+                .map(|kind| {
+                    let (count, last_called) = self.call_counts.get(*kind as u8).unwrap_or((0, 0));
+                    (*kind as u8, count, last_called)
+                })
+                .collect()
+        }
+
+        fn record_call(&mut self, kind: CallKind) {
+            let (count, _) = self.call_counts.get(kind as u8).unwrap_or((0, 0));
+            let last_called = self.env().block_timestamp();
+            self.call_counts.insert(kind as u8, &(count.saturating_add(1), last_called));
+        }
+
         fn only_callable_by(&self, account_id: AccountId) -> Result<(), Error> {
             let caller = self.env().caller();
             if caller != account_id {
@@ -294,6 +1743,1028 @@ mod mining_pool {
         /// Imports all the definitions from the outer scope so we can use them here.
         use super::*;
 
+        #[ink::test]
+        fn pending_admin_tracks_proposal_and_acceptance() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            assert_eq!(mining_pool.get_pending_admin(), None);
+            assert_eq!(mining_pool.accept_admin(), Err(Error::NotPendingAdmin));
+            assert!(mining_pool.propose_admin(accounts.frank).is_ok());
+            assert_eq!(mining_pool.get_pending_admin(), Some(accounts.frank));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            assert_eq!(mining_pool.accept_admin(), Err(Error::NotPendingAdmin));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            assert!(mining_pool.accept_admin().is_ok());
+            assert_eq!(mining_pool.get_pending_admin(), None);
+            assert_eq!(mining_pool.admin, accounts.frank);
+        }
+
+        #[ink::test]
+        fn set_successor_is_admin_only_and_one_shot() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            assert_eq!(mining_pool.forwarding_target(), None);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            assert_eq!(
+                mining_pool.set_successor(accounts.frank),
+                Err(Error::OnlyCallableBy(accounts.alice))
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            assert!(mining_pool.set_successor(accounts.frank).is_ok());
+            assert_eq!(mining_pool.get_successor(), Some(accounts.frank));
+            assert_eq!(mining_pool.forwarding_target(), Some(accounts.frank));
+            assert_eq!(mining_pool.set_successor(accounts.bob), Err(Error::AlreadyRetired));
+            assert_eq!(ink::env::test::recorded_events().count(), 1);
+            // admin messages keep working locally
+            assert!(mining_pool.set_max_reward_per_session(Some(1)).is_ok());
+        }
+
+        #[ink::test]
+        fn burn_weight_scales_total_volume() {
+            let (burned, merchant_volume) = (3_000_000, 1_000_000);
+            let even = MiningPool::weigh_volume(
+                burned,
+                merchant_volume,
+                BASE_WEIGHT_BPS,
+                BASE_WEIGHT_BPS
+            );
+            assert_eq!(even, 4_000_000);
+            let double_burns = MiningPool::weigh_volume(
+                burned,
+                merchant_volume,
+                2 * BASE_WEIGHT_BPS,
+                BASE_WEIGHT_BPS
+            );
+            assert_eq!(double_burns, 7_000_000);
+            assert_eq!(double_burns - even, burned);
+        }
+
+        #[ink::test]
+        fn burn_to_volume_rate_scales_burns() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            assert_eq!(mining_pool.get_burn_to_volume_rate(), PRICE_PRECISION);
+            assert_eq!(MiningPool::convert_burn_to_volume(3_000_000, PRICE_PRECISION), 3_000_000);
+            // a quarter of a volume unit per burned d9
+            assert!(mining_pool.set_burn_to_volume_rate(PRICE_PRECISION / 4).is_ok());
+            assert_eq!(
+                MiningPool::convert_burn_to_volume(3_000_000, mining_pool.get_burn_to_volume_rate()),
+                750_000
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            assert_eq!(
+                mining_pool.set_burn_to_volume_rate(PRICE_PRECISION),
+                Err(Error::OnlyCallableBy(accounts.alice))
+            );
+        }
+
+        #[ink::test]
+        fn volume_setting_changes_only_weigh_later_volume() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            let total_burned = 3_000_000;
+            mining_pool.merchant_volume = 1_000_000;
+            // session 1 recorded everything so far
+            mining_pool.volume_at_index.insert(0, &0);
+            mining_pool.volume_at_index.insert(1, &4_000_000);
+            mining_pool.last_session = 1;
+
+            // doubling the burn weight doesn't make session 2 look like it burned 3_000_000
+            mining_pool.apply_volume_settings(
+                total_burned,
+                2 * BASE_WEIGHT_BPS,
+                BASE_WEIGHT_BPS,
+                PRICE_PRECISION
+            );
+            assert_eq!(mining_pool.get_volume_weights(), (2 * BASE_WEIGHT_BPS, BASE_WEIGHT_BPS));
+            let total = mining_pool.calc_total_volume(total_burned);
+            assert_eq!(total, 7_000_000);
+            assert_eq!(mining_pool.calculate_session_delta(2, total), Ok(0));
+            // 100_000 burned afterwards counts at the new weight
+            let total = mining_pool.calc_total_volume(total_burned + 100_000);
+            assert_eq!(mining_pool.calculate_session_delta(2, total), Ok(200_000));
+
+            // lowering the merchant weight doesn't wipe out the next delta either
+            mining_pool.apply_volume_settings(
+                total_burned + 100_000,
+                2 * BASE_WEIGHT_BPS,
+                BASE_WEIGHT_BPS / 2,
+                PRICE_PRECISION
+            );
+            mining_pool.merchant_volume += 500_000;
+            let total = mining_pool.calc_total_volume(total_burned + 100_000);
+            assert_eq!(mining_pool.calculate_session_delta(2, total), Ok(450_000));
+
+            // a lower burn rate leaves earlier burns where they were as well
+            mining_pool.apply_volume_settings(
+                total_burned + 100_000,
+                2 * BASE_WEIGHT_BPS,
+                BASE_WEIGHT_BPS / 2,
+                PRICE_PRECISION / 4
+            );
+            assert_eq!(mining_pool.get_burn_to_volume_rate(), PRICE_PRECISION / 4);
+            let total = mining_pool.calc_total_volume(total_burned + 100_000);
+            assert_eq!(mining_pool.calculate_session_delta(2, total), Ok(450_000));
+            // 400_000 burned afterwards is 100_000 volume units, weighed double
+            let total = mining_pool.calc_total_volume(total_burned + 500_000);
+            assert_eq!(mining_pool.calculate_session_delta(2, total), Ok(650_000));
+        }
+
+        #[ink::test]
+        fn reward_cap_clamps_only_above_max() {
+            assert_eq!(MiningPool::apply_reward_cap(1_000, None), 1_000);
+            assert_eq!(MiningPool::apply_reward_cap(1_000, Some(400)), 400);
+            assert_eq!(MiningPool::apply_reward_cap(300, Some(400)), 300);
+        }
+
+        #[ink::test]
+        fn set_max_reward_per_session_is_admin_only() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            assert_eq!(mining_pool.get_max_reward_per_session(), None);
+            assert!(mining_pool.set_max_reward_per_session(Some(400)).is_ok());
+            assert_eq!(mining_pool.get_max_reward_per_session(), Some(400));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            assert_eq!(
+                mining_pool.set_max_reward_per_session(None),
+                Err(Error::OnlyCallableBy(accounts.alice))
+            );
+        }
+
+        #[ink::test]
+        fn protected_redemption_rejects_zero_rate() {
+            // less than 1 / PRICE_PRECISION d9 per usdt truncates the rate to zero
+            let result = MiningPool::calc_protected_redemption(10_000_000, 9, 0, 7_000);
+            assert_eq!(result, Err(Error::RateComputationFailed));
+            let result = MiningPool::calc_protected_redemption(0, 1_000, 0, 7_000);
+            assert_eq!(result, Err(Error::RateComputationFailed));
+            // a zero rate fails before it can reach the anchor
+            let result = MiningPool::calc_protected_redemption(10_000_000, 9, 5, 7_000);
+            assert_eq!(result, Err(Error::RateComputationFailed));
+        }
+
+        #[ink::test]
+        fn protected_redemption_rejects_zero_payout() {
+            // a rate of 1 survives truncation but the payout rounds down to nothing
+            let result = MiningPool::calc_protected_redemption(PRICE_PRECISION - 1, 1, 0, 0);
+            assert_eq!(result, Err(Error::PayoutRoundedToZero));
+            // the same quote pays out once the usdt amount covers a whole unit at that rate
+            let result = MiningPool::calc_protected_redemption(PRICE_PRECISION, 1, 0, 0);
+            assert_eq!(result.map(|r| r.d9_amount), Ok(1));
+        }
+
+        #[ink::test]
+        fn redemption_follows_the_market_rate_without_a_floor() {
+            let first = MiningPool::calc_protected_redemption(1_000, 2_000, 0, 0).unwrap();
+            assert_eq!(first.current_rate, 2 * PRICE_PRECISION);
+            assert_eq!(first.highest_rate, first.current_rate);
+            assert_eq!(first.d9_amount, 2_000);
+
+            // a lower quote later pays the lower rate, the anchor only records the high
+            let second = MiningPool::calc_protected_redemption(
+                1_000,
+                1_000,
+                first.highest_rate,
+                0
+            ).unwrap();
+            assert!(!second.protection_applied);
+            assert_eq!(second.effective_rate, PRICE_PRECISION);
+            assert_eq!(second.highest_rate, 2 * PRICE_PRECISION);
+            assert_eq!(second.d9_amount, 1_000);
+        }
+
+        #[ink::test]
+        fn protection_floor_is_opt_in() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            assert_eq!(mining_pool.get_protection_floor_bps(), 0);
+            assert_eq!(
+                mining_pool.set_protection_floor_bps(MAX_PROTECTION_FLOOR_BPS + 1),
+                Err(Error::InvalidProtectionFloor)
+            );
+            assert!(mining_pool.set_protection_floor_bps(7_000).is_ok());
+            assert_eq!(mining_pool.get_protection_floor_bps(), 7_000);
+
+            // market rate halves, the floor pays 70% of the anchor instead
+            let protected = MiningPool::calc_protected_redemption(
+                1_000,
+                1_000,
+                2 * PRICE_PRECISION,
+                mining_pool.get_protection_floor_bps()
+            ).unwrap();
+            assert!(protected.protection_applied);
+            assert_eq!(protected.effective_rate, (14 * PRICE_PRECISION) / 10);
+            assert_eq!(protected.d9_amount, 1_400);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(
+                mining_pool.set_protection_floor_bps(0),
+                Err(Error::OnlyCallableBy(accounts.alice))
+            );
+        }
+
+        #[ink::test]
+        fn oracle_rate_moves_the_anchor_like_an_amm_quote() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            assert!(mining_pool.set_protection_floor_bps(7_000).is_ok());
+
+            // an oracle redemption at 2 d9 per usdt anchors the rate
+            let oracle = MiningPool::calc_protected_redemption_from_rate(
+                1_000,
+                2 * PRICE_PRECISION,
+                mining_pool.get_highest_rate(),
+                mining_pool.get_protection_floor_bps()
+            ).unwrap();
+            mining_pool.record_redemption(&oracle);
+            assert_eq!(mining_pool.get_highest_rate(), 2 * PRICE_PRECISION);
+
+            // a later amm quote at half the rate is floored against the oracle's anchor
+            let amm = MiningPool::calc_protected_redemption(
+                1_000,
+                1_000,
+                mining_pool.get_highest_rate(),
+                mining_pool.get_protection_floor_bps()
+            ).unwrap();
+            assert!(amm.protection_applied);
+            assert_eq!(amm.d9_amount, 1_400);
+
+            // and the other way round, an amm anchor floors a later oracle rate
+            mining_pool.record_redemption(
+                &MiningPool::calc_protected_redemption(1_000, 3_000, 0, 7_000).unwrap()
+            );
+            assert_eq!(mining_pool.get_highest_rate(), 3 * PRICE_PRECISION);
+            let oracle = MiningPool::calc_protected_redemption_from_rate(
+                1_000,
+                PRICE_PRECISION,
+                mining_pool.get_highest_rate(),
+                mining_pool.get_protection_floor_bps()
+            ).unwrap();
+            assert!(oracle.protection_applied);
+            assert_eq!(oracle.d9_amount, 2_100);
+        }
+
+        #[ink::test]
+        fn redemption_rate_is_normalized_across_decimals() {
+            // 1 usdt buying 2 d9 prices at 2 whole d9 per whole usdt, whatever the decimals
+            for (usdt_decimals, one_usdt) in [(2, 100), (6, 1_000_000)] {
+                let common_decimals = D9_DECIMALS.max(usdt_decimals);
+                let redemption = MiningPool::calc_protected_redemption(
+                    MiningPool::to_common_scale(one_usdt, usdt_decimals, common_decimals),
+                    MiningPool::to_common_scale(2_000_000_000_000, D9_DECIMALS, common_decimals),
+                    0,
+                    0
+                ).unwrap();
+                assert_eq!(redemption.current_rate, 2 * PRICE_PRECISION);
+                let redemption = MiningPool::redemption_in_d9_units(
+                    redemption,
+                    D9_DECIMALS,
+                    common_decimals
+                ).unwrap();
+                assert_eq!(redemption.d9_amount, 2_000_000_000_000);
+            }
+
+            // a d9 with fewer decimals than usdt is scaled back down for the payout
+            let redemption = MiningPool::calc_protected_redemption(
+                MiningPool::to_common_scale(1_000_000, 6, 6),
+                MiningPool::to_common_scale(200, 2, 6),
+                0,
+                0
+            ).unwrap();
+            assert_eq!(redemption.current_rate, 2 * PRICE_PRECISION);
+            assert_eq!(MiningPool::redemption_in_d9_units(redemption, 2, 6).unwrap().d9_amount, 200);
+        }
+
+        #[ink::test]
+        fn constructor_rejects_unsupported_decimals() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let (a, b, c, d) = (accounts.bob, accounts.charlie, accounts.django, accounts.eve);
+            assert_eq!(
+                MiningPool::new_with_decimals(a, b, c, d, MAX_DECIMALS + 1, USDT_DECIMALS).err(),
+                Some(Error::InvalidDecimals)
+            );
+            let mining_pool = MiningPool::new_with_decimals(a, b, c, d, 12, 6).unwrap();
+            assert_eq!(mining_pool.get_decimals(), (12, 6));
+            assert_eq!(MiningPool::new(a, b, c, d).unwrap().get_decimals(), (D9_DECIMALS, USDT_DECIMALS));
+        }
+
+        #[ink::test]
+        fn redemption_stats_count_by_branch() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            mining_pool.set_protection_floor_bps(7_000).unwrap();
+            assert_eq!(mining_pool.get_redemption_stats(), (0, 0, 0));
+
+            // market rate redemption
+            let market = MiningPool::calc_protected_redemption(
+                1_000,
+                2_000,
+                mining_pool.highest_rate,
+                mining_pool.protection_floor_bps
+            ).unwrap();
+            mining_pool.record_redemption(&market);
+            assert_eq!(mining_pool.get_redemption_stats(), (0, 0, 2_000));
+
+            // market rate halves and the floor kicks in
+            let protected = MiningPool::calc_protected_redemption(
+                1_000,
+                1_000,
+                mining_pool.highest_rate,
+                mining_pool.protection_floor_bps
+            ).unwrap();
+            mining_pool.record_redemption(&protected);
+            assert_eq!(mining_pool.get_redemption_stats(), (1, 1_400, 2_000));
+            assert_eq!(mining_pool.get_highest_rate(), 2 * PRICE_PRECISION);
+        }
+
+        #[ink::test]
+        fn constructor_rejects_shared_contract_roles() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let (a, b, c, d) = (accounts.bob, accounts.charlie, accounts.django, accounts.eve);
+            // every pair of roles pointing at the same contract
+            let duplicates = [
+                (a, a, c, d),
+                (a, b, a, d),
+                (a, b, c, a),
+                (a, b, b, d),
+                (a, b, c, b),
+                (a, b, c, c),
+            ];
+            for (main, merchant, node_reward, amm) in duplicates {
+                assert_eq!(
+                    MiningPool::new(main, merchant, node_reward, amm).err(),
+                    Some(Error::InvalidContractAddress)
+                );
+            }
+            let zero_address = AccountId::from([0u8; 32]);
+            assert_eq!(
+                MiningPool::new(a, b, zero_address, d).err(),
+                Some(Error::InvalidContractAddress)
+            );
+            let mining_pool = MiningPool::new(a, b, c, d).unwrap();
+            assert_eq!(mining_pool.get_wiring(), (a, b, c, d));
+        }
+
+        #[ink::test]
+        fn contract_setters_keep_wiring_valid() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            assert_eq!(
+                mining_pool.change_merchant_contract(accounts.bob),
+                Err(Error::InvalidContractAddress)
+            );
+            assert_eq!(
+                mining_pool.change_amm_contract(AccountId::from([0u8; 32])),
+                Err(Error::InvalidContractAddress)
+            );
+            assert!(mining_pool.change_main_contract(accounts.frank).is_ok());
+            assert_eq!(
+                mining_pool.get_wiring(),
+                (accounts.frank, accounts.charlie, accounts.django, accounts.eve)
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            assert_eq!(
+                mining_pool.change_node_reward_contract(accounts.alice),
+                Err(Error::OnlyCallableBy(accounts.alice))
+            );
+        }
+
+        #[ink::test]
+        fn reward_breakdown_matches_pool_update() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            mining_pool.accumulative_reward_pool = 50_000;
+            mining_pool.max_reward_per_session = Some(6_000);
+            mining_pool.volume_at_index.insert(4, &1_000_000);
+
+            let breakdown = mining_pool.calc_reward_breakdown(5, 1_500_000).unwrap();
+            assert_eq!(breakdown.session_delta, 500_000);
+            assert_eq!(breakdown.three_percent_of_delta, 15_000);
+            assert_eq!(breakdown.accumulative_after, 65_000);
+            assert_eq!(breakdown.requested, 6_500);
+            // the view left state untouched
+            assert_eq!(mining_pool.accumulative_reward_pool, 50_000);
+            assert_eq!(mining_pool.get_session_volume(5), 0);
+
+            let update = mining_pool.apply_session_volume(5, 1_500_000).unwrap();
+            assert_eq!(update.reward_pool, breakdown.reward_pool);
+            assert_eq!(update.reward_pool, 6_000);
+            assert_eq!(update.session_delta, breakdown.session_delta);
+            assert_eq!(update.accumulative_reward_pool, breakdown.accumulative_after);
+        }
+
+        #[ink::test]
+        fn pool_cap_discards_contributions_beyond_limit() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            assert_eq!(mining_pool.get_max_accumulative_pool(), Balance::MAX);
+            assert!(mining_pool.set_max_accumulative_pool(60_000).is_ok());
+            mining_pool.accumulative_reward_pool = 50_000;
+            mining_pool.volume_at_index.insert(4, &1_000_000);
+
+            // 15_000 contributed, only 10_000 fits under the cap
+            let update = mining_pool.apply_session_volume(5, 1_500_000).unwrap();
+            assert_eq!(update.accumulative_reward_pool, 60_000);
+            assert_eq!(update.reward_pool, 6_000);
+
+            // a full pool takes nothing more
+            let update = mining_pool.apply_session_volume(6, 2_000_000).unwrap();
+            assert_eq!(update.accumulative_reward_pool, 60_000);
+
+            // lowering the cap below the pool keeps what is already there
+            assert_eq!(MiningPool::apply_pool_cap(60_000, 15_000, 40_000), (60_000, 15_000));
+            assert_eq!(MiningPool::apply_pool_cap(50_000, 15_000, Balance::MAX), (65_000, 0));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            assert_eq!(
+                mining_pool.set_max_accumulative_pool(Balance::MAX),
+                Err(Error::OnlyCallableBy(accounts.alice))
+            );
+        }
+
+        #[ink::test]
+        fn keeper_is_tipped_from_the_pool() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            let contract_id = ink::env::account_id::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(
+                contract_id,
+                1_000_000
+            );
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(accounts.frank, 0);
+            assert_eq!(mining_pool.get_keeper_tip(), 0);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            assert_eq!(mining_pool.trigger_session_update(1), Err(Error::KeeperUpdatesDisabled));
+            assert_eq!(mining_pool.set_keeper_tip(100), Err(Error::OnlyCallableBy(accounts.alice)));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            assert!(mining_pool.set_keeper_tip(100).is_ok());
+            mining_pool.volume_at_index.insert(0, &0);
+            assert_eq!(mining_pool.apply_keeper_update(accounts.frank, 1, 2, 1_000_000), Ok(100));
+            // 30_000 accrued, 3_000 reserved for nodes, 100 tipped
+            let update = mining_pool.get_keeper_session_update(1).unwrap();
+            assert_eq!(update.reward_pool, 3_000);
+            assert_eq!(update.accumulative_reward_pool, 29_900);
+            assert_eq!(mining_pool.get_accumulative_reward_pool(), 29_900);
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(
+                    accounts.frank
+                ),
+                Ok(100)
+            );
+
+            // the tip never dips into the released reward
+            assert_eq!(MiningPool::calc_keeper_tip(100, 3_050, 3_000), 50);
+            assert_eq!(MiningPool::calc_keeper_tip(100, 3_000, 3_000), 0);
+        }
+
+        #[ink::test]
+        fn session_commit_requires_a_fresh_computation() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            mining_pool.volume_at_index.insert(0, &0);
+
+            // computing changes nothing
+            let computation = mining_pool.calc_session_computation(1, 1_000_000).unwrap();
+            assert_eq!(computation.session_delta, 1_000_000);
+            assert_eq!(computation.accumulative_reward_pool, 30_000);
+            assert_eq!(computation.reward_pool, 3_000);
+            assert_eq!(mining_pool.get_accumulative_reward_pool(), 0);
+            assert_eq!(mining_pool.get_session_volume(1), 0);
+
+            // merchant volume after the computation makes it stale
+            assert_eq!(
+                mining_pool.apply_session_computation(1, 1_500_000, computation.hash()),
+                Err(Error::StaleSessionComputation)
+            );
+            assert_eq!(mining_pool.get_accumulative_reward_pool(), 0);
+
+            assert_eq!(
+                mining_pool.apply_session_computation(1, 1_000_000, computation.hash()),
+                Ok(())
+            );
+            assert_eq!(mining_pool.get_accumulative_reward_pool(), 30_000);
+            assert_eq!(mining_pool.get_session_volume(1), 1_000_000);
+
+            assert_eq!(
+                mining_pool.commit_session(2, computation.hash()),
+                Err(Error::OnlyCallableBy(accounts.django))
+            );
+        }
+
+        #[ink::test]
+        fn keeper_sessions_commit_to_the_recorded_update() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            let contract_id = ink::env::account_id::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(
+                contract_id,
+                1_000_000
+            );
+            mining_pool.set_keeper_tip(100).unwrap();
+            mining_pool.volume_at_index.insert(0, &0);
+            mining_pool.apply_keeper_update(accounts.frank, 1, 2, 1_000_000).unwrap();
+
+            // the keeper already accrued, nothing is computed twice
+            let computation = mining_pool.compute_session_reward(1).unwrap();
+            assert_eq!(computation.total_volume, 1_000_000);
+            assert_eq!(computation.reward_pool, 3_000);
+            assert_eq!(computation.accumulative_reward_pool, 29_900);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            assert_eq!(
+                mining_pool.commit_session(1, Hash::from([0; 32])),
+                Err(Error::StaleSessionComputation)
+            );
+            assert_eq!(mining_pool.commit_session(1, computation.hash()), Ok(()));
+            assert_eq!(mining_pool.get_keeper_session_update(1), None);
+            assert_eq!(mining_pool.get_accumulative_reward_pool(), 29_900);
+        }
+
+        #[ink::test]
+        fn redemption_quote_takes_the_best_amm_within_the_band() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let quote = |amount_out: Balance| Ok(Ok(Ok(amount_out)));
+            let reference = (accounts.bob, 1_000);
+            // the better quote wins
+            let quotes = [(accounts.eve, quote(1_040)), (accounts.frank, quote(1_020))];
+            assert_eq!(
+                MiningPool::best_quote(reference, quotes.into_iter()),
+                (accounts.eve, 1_040)
+            );
+            // a pool pushed far off the reference doesn't set the rate
+            let quotes = [(accounts.eve, quote(50_000)), (accounts.frank, quote(1_050))];
+            assert_eq!(
+                MiningPool::best_quote(reference, quotes.into_iter()),
+                (accounts.frank, 1_050)
+            );
+            // worse quotes and ties keep the reference
+            let quotes = [(accounts.eve, quote(960)), (accounts.frank, quote(1_000))];
+            assert_eq!(MiningPool::best_quote(reference, quotes.into_iter()), reference);
+
+            // failing, empty and out of band quotes are passed over
+            let quotes = [
+                (accounts.eve, Err(ink::env::Error::CalleeTrapped)),
+                (accounts.frank, Ok(Ok(Err(Error::FailedToGetExchangeAmount)))),
+                (accounts.django, quote(0)),
+                (accounts.charlie, quote(1_051)),
+            ];
+            assert_eq!(MiningPool::best_quote(reference, quotes.into_iter()), reference);
+        }
+
+        #[ink::test]
+        fn amm_contracts_are_admin_managed() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            assert_eq!(mining_pool.get_amm_contracts(), Vec::new());
+            assert_eq!(
+                mining_pool.set_amm_contracts(vec![accounts.eve]),
+                Err(Error::InvalidContractAddress)
+            );
+            assert_eq!(
+                mining_pool.set_amm_contracts(vec![accounts.frank; MAX_AMM_CONTRACTS + 1]),
+                Err(Error::TooManyAmmContracts)
+            );
+            assert!(mining_pool.set_amm_contracts(vec![accounts.frank]).is_ok());
+            assert_eq!(mining_pool.get_amm_contracts(), vec![accounts.frank]);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            assert_eq!(
+                mining_pool.set_amm_contracts(Vec::new()),
+                Err(Error::OnlyCallableBy(accounts.alice))
+            );
+        }
+
+        #[ink::test]
+        fn keeper_updates_are_collected_once() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            let contract_id = ink::env::account_id::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(
+                contract_id,
+                1_000_000
+            );
+            mining_pool.set_keeper_tip(100).unwrap();
+            mining_pool.volume_at_index.insert(0, &0);
+            mining_pool.apply_keeper_update(accounts.frank, 1, 2, 1_000_000).unwrap();
+            let recorded = mining_pool.get_keeper_session_update(1).unwrap();
+
+            // sessions that haven't ended can't be updated ahead of time
+            assert_eq!(
+                mining_pool.apply_keeper_update(accounts.frank, 2, 2, 2_000_000),
+                Err(Error::SessionNotEnded)
+            );
+            assert_eq!(
+                mining_pool.apply_keeper_update(accounts.frank, 3, 2, 2_000_000),
+                Err(Error::SessionNotEnded)
+            );
+            // replaying the session or an older one accrues nothing
+            assert_eq!(
+                mining_pool.apply_keeper_update(accounts.frank, 1, 2, 2_000_000),
+                Err(Error::SessionAlreadyUpdated)
+            );
+            assert_eq!(
+                mining_pool.apply_keeper_update(accounts.frank, 0, 2, 2_000_000),
+                Err(Error::SessionAlreadyUpdated)
+            );
+            assert_eq!(mining_pool.get_accumulative_reward_pool(), 29_900);
+
+            assert_eq!(
+                mining_pool.collect_session_reward(1),
+                Err(Error::OnlyCallableBy(accounts.django))
+            );
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            assert_eq!(mining_pool.collect_session_reward(1), Ok(recorded));
+            assert_eq!(mining_pool.collect_session_reward(1), Err(Error::NoSessionRewardToCollect));
+            assert_eq!(mining_pool.get_keeper_session_update(1), None);
+        }
+
+        #[ink::test]
+        fn failed_snapshot_reads_report_zero() {
+            let reserves: ink::env::Result<ink::MessageResult<(Balance, Balance)>> = Ok(
+                Ok((5_000, 500))
+            );
+            assert_eq!(MiningPool::read_or_zero(reserves), (5_000, 500));
+            let trapped: ink::env::Result<ink::MessageResult<(Balance, Balance)>> = Err(
+                ink::env::Error::CalleeTrapped
+            );
+            assert_eq!(MiningPool::read_or_zero(trapped), (0, 0));
+            let missing: ink::env::Result<ink::MessageResult<Balance>> = Ok(
+                Err(ink::LangError::CouldNotReadInput)
+            );
+            assert_eq!(MiningPool::read_or_zero(missing), 0);
+            let undecodable: ink::env::Result<ink::MessageResult<Balance>> = Err(
+                ink::env::Error::Decode(scale::Error::from("bad reply"))
+            );
+            assert_eq!(MiningPool::read_or_zero(undecodable), 0);
+        }
+
+        #[ink::test]
+        fn pair_probe_quotes_a_whole_unit() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            assert_eq!(mining_pool.calc_pair_probe_amount(REDEEM_DIRECTION), 100);
+            assert_eq!(
+                mining_pool.calc_pair_probe_amount(Direction(Currency::D9, Currency::USDT)),
+                1_000_000_000_000
+            );
+        }
+
+        #[ink::test]
+        fn pair_check_needs_a_quote_both_ways() {
+            assert!(MiningPool::is_pair_quote(Ok(Ok(Ok((100, 10_000))))));
+            assert!(!MiningPool::is_pair_quote(Ok(Ok(Ok((100, 0))))));
+            assert!(!MiningPool::is_pair_quote(Ok(Ok(Err(Error::FailedToGetExchangeAmount)))));
+            assert!(!MiningPool::is_pair_quote(Ok(Err(ink::LangError::CouldNotReadInput))));
+            assert!(!MiningPool::is_pair_quote(Err(ink::env::Error::CalleeTrapped)));
+
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            assert_eq!(mining_pool.get_configured_direction(), REDEEM_DIRECTION);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            assert_eq!(mining_pool.verify_pair(), Err(Error::OnlyCallableBy(accounts.alice)));
+        }
+
+        #[ink::test]
+        fn split_node_reward_deducts_the_total_once() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            let contract_id = ink::env::account_id::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(
+                contract_id,
+                1_000_000
+            );
+            let balance_of = |account_id| {
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(account_id)
+                    .unwrap_or(0)
+            };
+            let recipients = vec![
+                (accounts.frank, 6_000),
+                (accounts.bob, 3_000),
+                (accounts.charlie, 1_000)
+            ];
+            let before: Vec<Balance> = recipients
+                .iter()
+                .map(|(account_id, _)| balance_of(*account_id))
+                .collect();
+            mining_pool.accumulative_reward_pool = 50_000;
+
+            assert_eq!(
+                mining_pool.pay_node_reward_split(recipients.clone()),
+                Err(Error::OnlyCallableBy(accounts.django))
+            );
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            assert!(mining_pool.pay_node_reward_split(recipients.clone()).is_ok());
+            assert_eq!(mining_pool.get_accumulative_reward_pool(), 40_000);
+            for ((account_id, amount), before) in recipients.iter().zip(before) {
+                assert_eq!(balance_of(*account_id), before + amount);
+            }
+
+            // more than the pool holds pays nobody
+            let too_much = vec![(accounts.frank, 30_000), (accounts.bob, 10_001)];
+            assert_eq!(mining_pool.pay_node_reward_split(too_much), Err(Error::RewardExceedsPool));
+            assert_eq!(mining_pool.get_accumulative_reward_pool(), 40_000);
+        }
+
+        #[ink::test]
+        fn node_reward_beyond_contract_balance_is_rejected() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            let contract_id = ink::env::account_id::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000);
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(accounts.frank, 0);
+            mining_pool.accumulative_reward_pool = 50_000;
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            assert_eq!(
+                mining_pool.pay_node_reward(accounts.frank, 1_001),
+                Err(Error::InsufficientContractBalance)
+            );
+            assert_eq!(mining_pool.get_accumulative_reward_pool(), 50_000);
+
+            assert!(mining_pool.pay_node_reward(accounts.frank, 1_000).is_ok());
+            assert_eq!(mining_pool.get_accumulative_reward_pool(), 49_000);
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.frank),
+                Ok(1_000)
+            );
+
+            // a split is checked as a whole, so nobody is paid
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000);
+            assert_eq!(
+                mining_pool.pay_node_reward_split(vec![(accounts.frank, 600), (accounts.bob, 401)]),
+                Err(Error::InsufficientContractBalance)
+            );
+            assert_eq!(mining_pool.get_accumulative_reward_pool(), 49_000);
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.frank),
+                Ok(1_000)
+            );
+        }
+
+        #[ink::test]
+        fn short_pool_queues_redemptions_until_topped_up() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            let contract_id = ink::env::account_id::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000);
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(accounts.frank, 0);
+
+            assert!(mining_pool.pay_or_queue_redemption(accounts.frank, 600));
+            assert!(!mining_pool.pay_or_queue_redemption(accounts.frank, 700));
+            assert!(!mining_pool.pay_or_queue_redemption(accounts.frank, 500));
+            // the 400 left is already owed to the queue, so later redemptions wait behind it
+            assert!(!mining_pool.pay_or_queue_redemption(accounts.frank, 300));
+            assert_eq!(mining_pool.get_pending_redemption(accounts.frank), 1_500);
+            assert_eq!(mining_pool.get_total_pending_redemptions(), 1_500);
+
+            // still short
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            assert_eq!(
+                mining_pool.claim_pending_redemption(),
+                Err(Error::FailedToTransferD9ToUser)
+            );
+            assert_eq!(mining_pool.get_pending_redemption(accounts.frank), 1_500);
+
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(
+                contract_id,
+                5_000
+            );
+            assert_eq!(mining_pool.claim_pending_redemption(), Ok(1_500));
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(
+                    accounts.frank
+                ),
+                Ok(2_100)
+            );
+            assert_eq!(mining_pool.get_total_pending_redemptions(), 0);
+            assert_eq!(mining_pool.claim_pending_redemption(), Err(Error::NoPendingRedemption));
+        }
+
+        #[ink::test]
+        fn call_stats_count_restricted_calls() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            let contract_id = ink::env::account_id::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000);
+            assert_eq!(
+                mining_pool.get_call_stats(),
+                vec![(0, 0, 0), (1, 0, 0), (2, 0, 0), (3, 0, 0)]
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1_000);
+            assert_eq!(mining_pool.pay_node_reward(accounts.frank, 10), Ok(()));
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(2_000);
+            assert_eq!(
+                mining_pool.pay_node_reward_split(vec![(accounts.frank, 0)]),
+                Ok(())
+            );
+            mining_pool.record_call(CallKind::RedeemD9);
+
+            // rejected callers aren't counted
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            assert!(mining_pool.pay_node_reward(accounts.frank, 10).is_err());
+
+            assert_eq!(
+                mining_pool.get_call_stats(),
+                vec![(0, 0, 0), (1, 1, 2_000), (2, 0, 0), (3, 2, 2_000)]
+            );
+        }
+
+        #[ink::test]
+        fn oracle_rate_falls_back_on_bad_replies() {
+            let now = 10 * ORACLE_TWAP_WINDOW_MS;
+            let max_age = ORACLE_TWAP_WINDOW_MS;
+            let undecodable = Err(ink::env::Error::Decode(scale::Error::from("bad twap")));
+            assert_eq!(MiningPool::oracle_rate_from(undecodable, now, max_age), Ok(None));
+            assert_eq!(
+                MiningPool::oracle_rate_from(Err(ink::env::Error::CalleeTrapped), now, max_age),
+                Ok(None)
+            );
+            assert_eq!(
+                MiningPool::oracle_rate_from(
+                    Ok(Err(ink::LangError::CouldNotReadInput)),
+                    now,
+                    max_age
+                ),
+                Ok(None)
+            );
+            assert_eq!(MiningPool::oracle_rate_from(Ok(Ok((0, now))), now, max_age), Ok(None));
+            assert_eq!(
+                MiningPool::oracle_rate_from(Ok(Ok((2 * PRICE_PRECISION, now))), now, max_age),
+                Ok(Some(2 * PRICE_PRECISION))
+            );
+        }
+
+        #[ink::test]
+        fn stale_oracle_rate_is_refused() {
+            let now = 10 * ORACLE_TWAP_WINDOW_MS;
+            let max_age = ORACLE_TWAP_WINDOW_MS;
+            let rate = 2 * PRICE_PRECISION;
+            assert_eq!(
+                MiningPool::oracle_rate_from(Ok(Ok((rate, now - max_age))), now, max_age),
+                Ok(Some(rate))
+            );
+            assert_eq!(
+                MiningPool::oracle_rate_from(Ok(Ok((rate, now - max_age - 1))), now, max_age),
+                Err(Error::OracleStale)
+            );
+            // a timestamp ahead of the block is not stale
+            assert_eq!(
+                MiningPool::oracle_rate_from(Ok(Ok((rate, now + 1))), now, max_age),
+                Ok(Some(rate))
+            );
+
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut mining_pool = MiningPool::new(
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve
+            ).unwrap();
+            assert_eq!(mining_pool.get_max_oracle_age_ms(), ORACLE_TWAP_WINDOW_MS);
+            assert!(mining_pool.set_max_oracle_age_ms(5 * ORACLE_TWAP_WINDOW_MS).is_ok());
+            assert_eq!(mining_pool.get_max_oracle_age_ms(), 5 * ORACLE_TWAP_WINDOW_MS);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            assert_eq!(
+                mining_pool.set_max_oracle_age_ms(0),
+                Err(Error::OnlyCallableBy(accounts.alice))
+            );
+        }
+
+        #[ink::test]
+        fn oracle_rate_prices_like_an_amm_quote() {
+            let from_amm = MiningPool::calc_protected_redemption(1_000, 2_000, 0, 0).unwrap();
+            let from_oracle = MiningPool::calc_protected_redemption_from_rate(
+                1_000,
+                2 * PRICE_PRECISION,
+                0,
+                0
+            );
+            assert_eq!(from_oracle, Ok(from_amm));
+            assert_eq!(
+                MiningPool::calc_protected_redemption_from_rate(1_000, 0, 0, 0),
+                Err(Error::RateComputationFailed)
+            );
+        }
+
         //   #[ink::test]
         //   fn it_works() {
         //       let mut mining_pool = MiningPool::new(false);
@@ -313,12 +2784,602 @@ mod mining_pool {
         /// Imports all the definitions from the outer scope so we can use them here.
         use super::*;
 
+        use d9_usdt_permit_mock::d9_usdt_permit_mock::D9USDTPermitMockRef;
         /// A helper function used for calling contract messages.
         use ink_e2e::build_message;
+        use amm_mock::amm_mock::AmmMockRef;
+        use market_maker::market_maker::MarketMakerRef;
+        use price_oracle_mock::price_oracle_mock::PriceOracleMockRef;
 
         /// The End-to-End test `Result` type.
         type E2EResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
+        #[ink_e2e::test]
+        async fn retired_pool_forwards_merchant_payment(
+            mut client: ink_e2e::Client<C, E>
+        ) -> E2EResult<()> {
+            let alice = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let charlie = ink_e2e::account_id(ink_e2e::AccountKeyring::Charlie);
+            let dave = ink_e2e::account_id(ink_e2e::AccountKeyring::Dave);
+            // alice stands in for the merchant contract on the old pool
+            let old_pool_address = client
+                .instantiate(
+                    "mining_pool",
+                    &ink_e2e::alice(),
+                    MiningPoolRef::new(bob, alice, charlie, dave),
+                    0,
+                    None
+                ).await
+                .expect("instantiate old pool failed").account_id;
+            // forwarded calls reach the new pool from the old one
+            let new_pool_address = client
+                .instantiate(
+                    "mining_pool",
+                    &ink_e2e::alice(),
+                    MiningPoolRef::new(bob, old_pool_address, charlie, dave),
+                    0,
+                    None
+                ).await
+                .expect("instantiate new pool failed").account_id;
+
+            let set_successor = build_message::<MiningPoolRef>(old_pool_address.clone()).call(
+                |mining_pool| mining_pool.set_successor(new_pool_address)
+            );
+            client
+                .call(&ink_e2e::alice(), set_successor, 0, None).await
+                .expect("set successor failed");
+
+            let process_payment = build_message::<MiningPoolRef>(old_pool_address.clone()).call(
+                |mining_pool| mining_pool.process_merchant_payment(alice)
+            );
+            client
+                .call(&ink_e2e::alice(), process_payment, 2_000_000_000_000, None).await
+                .expect("forwarded payment failed");
+
+            let old_volume = build_message::<MiningPoolRef>(old_pool_address.clone()).call(
+                |mining_pool| mining_pool.get_merchant_volume()
+            );
+            let old_volume = client
+                .call_dry_run(&ink_e2e::alice(), &old_volume, 0, None).await
+                .return_value();
+            assert_eq!(old_volume, 0);
+            let new_volume = build_message::<MiningPoolRef>(new_pool_address.clone()).call(
+                |mining_pool| mining_pool.get_merchant_volume()
+            );
+            let new_volume = client
+                .call_dry_run(&ink_e2e::alice(), &new_volume, 0, None).await
+                .return_value();
+            assert_eq!(new_volume, 2_000_000_000_000);
+
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn redemption_prefers_oracle_twap(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            let alice = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let charlie = ink_e2e::account_id(ink_e2e::AccountKeyring::Charlie);
+            let dave = ink_e2e::account_id(ink_e2e::AccountKeyring::Dave);
+            let eve = ink_e2e::account_id(ink_e2e::AccountKeyring::Eve);
+            let oracle_address = client
+                .instantiate(
+                    "price_oracle_mock",
+                    &ink_e2e::alice(),
+                    PriceOracleMockRef::new(2 * PRICE_PRECISION),
+                    0,
+                    None
+                ).await
+                .expect("instantiate oracle failed").account_id;
+            // alice stands in for the merchant contract, dave is no amm so only the oracle can quote
+            let pool_address = client
+                .instantiate(
+                    "mining_pool",
+                    &ink_e2e::alice(),
+                    MiningPoolRef::new(bob, alice, charlie, dave),
+                    0,
+                    None
+                ).await
+                .expect("instantiate pool failed").account_id;
+            let set_oracle = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.set_price_oracle(Some(oracle_address))
+            );
+            client
+                .call(&ink_e2e::alice(), set_oracle, 0, None).await
+                .expect("set price oracle failed");
+            let process_payment = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.process_merchant_payment(alice)
+            );
+            client
+                .call(&ink_e2e::alice(), process_payment, 2_000_000_000_000, None).await
+                .expect("funding payment failed");
+
+            let redeem = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.merchant_user_redeem_d9(eve, 50)
+            );
+            let redeemed = client
+                .call(&ink_e2e::alice(), redeem, 0, None).await
+                .expect("redeem failed")
+                .return_value();
+            // half a usdt at 2 d9 per usdt
+            assert_eq!(redeemed, Ok(1_000_000_000_000));
+            let stats = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.get_redemption_stats()
+            );
+            let stats = client
+                .call_dry_run(&ink_e2e::alice(), &stats, 0, None).await
+                .return_value();
+            assert_eq!(stats, (0, 0, 1_000_000_000_000));
+            // the oracle rate anchors future redemptions just like an amm quote
+            let highest_rate = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.get_highest_rate()
+            );
+            let highest_rate = client
+                .call_dry_run(&ink_e2e::alice(), &highest_rate, 0, None).await
+                .return_value();
+            assert_eq!(highest_rate, 2 * PRICE_PRECISION);
+
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn redeem_preview_matches_execution(
+            mut client: ink_e2e::Client<C, E>
+        ) -> E2EResult<()> {
+            let alice = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let charlie = ink_e2e::account_id(ink_e2e::AccountKeyring::Charlie);
+            let dave = ink_e2e::account_id(ink_e2e::AccountKeyring::Dave);
+            let eve = ink_e2e::account_id(ink_e2e::AccountKeyring::Eve);
+            let oracle_address = client
+                .instantiate(
+                    "price_oracle_mock",
+                    &ink_e2e::alice(),
+                    PriceOracleMockRef::new(2 * PRICE_PRECISION),
+                    0,
+                    None
+                ).await
+                .expect("instantiate oracle failed").account_id;
+            let pool_address = client
+                .instantiate(
+                    "mining_pool",
+                    &ink_e2e::alice(),
+                    MiningPoolRef::new(bob, alice, charlie, dave),
+                    0,
+                    None
+                ).await
+                .expect("instantiate pool failed").account_id;
+            let set_oracle = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.set_price_oracle(Some(oracle_address))
+            );
+            client
+                .call(&ink_e2e::alice(), set_oracle, 0, None).await
+                .expect("set price oracle failed");
+            let set_floor = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.set_protection_floor_bps(7_000)
+            );
+            client
+                .call(&ink_e2e::alice(), set_floor, 0, None).await
+                .expect("set protection floor failed");
+            let process_payment = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.process_merchant_payment(alice)
+            );
+            client
+                .call(&ink_e2e::alice(), process_payment, 2_000_000_000_000, None).await
+                .expect("funding payment failed");
+            // the first redemption anchors the rate at 2 d9 per usdt
+            let redeem = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.merchant_user_redeem_d9(eve, 50)
+            );
+            client
+                .call(&ink_e2e::alice(), redeem, 0, None).await
+                .expect("anchoring redeem failed");
+            let set_twap = build_message::<PriceOracleMockRef>(oracle_address.clone()).call(
+                |oracle| oracle.set_twap(PRICE_PRECISION)
+            );
+            client
+                .call(&ink_e2e::alice(), set_twap, 0, None).await
+                .expect("set twap failed");
+
+            let preview = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.preview_redeem_d9(50)
+            );
+            let preview = client
+                .call_dry_run(&ink_e2e::alice(), &preview, 0, None).await
+                .return_value()
+                .expect("preview failed");
+            assert!(preview.protection_applied);
+            let eve_before = client.balance(eve).await.expect("eve balance failed");
+            let redeem = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.merchant_user_redeem_d9(eve, 50)
+            );
+            let redeemed = client
+                .call(&ink_e2e::alice(), redeem, 0, None).await
+                .expect("redeem failed")
+                .return_value();
+            // the market halved, the floor holds the payout at 70% of the anchored rate
+            assert_eq!(redeemed, Ok(preview.d9_amount));
+            assert_eq!(preview.d9_amount, 700_000_000_000);
+            let eve_after = client.balance(eve).await.expect("eve balance failed");
+            assert_eq!(eve_after - eve_before, preview.d9_amount);
+
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn stale_oracle_blocks_redemption(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            let alice = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let charlie = ink_e2e::account_id(ink_e2e::AccountKeyring::Charlie);
+            let dave = ink_e2e::account_id(ink_e2e::AccountKeyring::Dave);
+            let eve = ink_e2e::account_id(ink_e2e::AccountKeyring::Eve);
+            let oracle_address = client
+                .instantiate(
+                    "price_oracle_mock",
+                    &ink_e2e::alice(),
+                    PriceOracleMockRef::new(2 * PRICE_PRECISION),
+                    0,
+                    None
+                ).await
+                .expect("instantiate oracle failed").account_id;
+            let pool_address = client
+                .instantiate(
+                    "mining_pool",
+                    &ink_e2e::alice(),
+                    MiningPoolRef::new(bob, alice, charlie, dave),
+                    0,
+                    None
+                ).await
+                .expect("instantiate pool failed").account_id;
+            let set_oracle = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.set_price_oracle(Some(oracle_address))
+            );
+            client
+                .call(&ink_e2e::alice(), set_oracle, 0, None).await
+                .expect("set price oracle failed");
+            let process_payment = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.process_merchant_payment(alice)
+            );
+            client
+                .call(&ink_e2e::alice(), process_payment, 2_000_000_000_000, None).await
+                .expect("funding payment failed");
+            // the oracle stopped updating at genesis
+            let backdate = build_message::<PriceOracleMockRef>(oracle_address.clone()).call(
+                |oracle| oracle.set_updated_at(0)
+            );
+            client
+                .call(&ink_e2e::alice(), backdate, 0, None).await
+                .expect("backdate oracle failed");
+
+            let redeem = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.merchant_user_redeem_d9(eve, 1_000_000)
+            );
+            let redeemed = client
+                .call_dry_run(&ink_e2e::alice(), &redeem, 0, None).await
+                .return_value();
+            assert_eq!(redeemed, Err(Error::OracleStale));
+
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn ecosystem_snapshot_tolerates_failing_reads(
+            mut client: ink_e2e::Client<C, E>
+        ) -> E2EResult<()> {
+            let alice = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let charlie = ink_e2e::account_id(ink_e2e::AccountKeyring::Charlie);
+            let usdt_address = client
+                .instantiate(
+                    "d9_usdt_permit_mock",
+                    &ink_e2e::alice(),
+                    D9USDTPermitMockRef::new(100_000_000_000_000),
+                    0,
+                    None
+                ).await
+                .expect("instantiate usdt failed").account_id;
+            let amm_address = client
+                .instantiate(
+                    "market_maker",
+                    &ink_e2e::alice(),
+                    MarketMakerRef::new(usdt_address, 1, 100),
+                    0,
+                    None
+                ).await
+                .expect("instantiate market maker failed").account_id;
+            let permit = build_message::<D9USDTPermitMockRef>(usdt_address.clone()).call(|usdt|
+                usdt.permit(alice, amm_address, 50_000_000_000_000, u64::MAX, 27, [0u8; 32], [0u8; 32])
+            );
+            client
+                .call(&ink_e2e::alice(), permit, 0, None).await
+                .expect("permit failed");
+            let add_liquidity = build_message::<MarketMakerRef>(amm_address.clone()).call(
+                |market_maker| market_maker.add_liquidity(5_000_000_000_000)
+            );
+            client
+                .call(&ink_e2e::alice(), add_liquidity, 500_000_000_000, None).await
+                .expect("add liquidity failed");
+            // bob is no main contract, so the total burned read fails
+            let pool_address = client
+                .instantiate(
+                    "mining_pool",
+                    &ink_e2e::alice(),
+                    MiningPoolRef::new(bob, alice, charlie, amm_address),
+                    0,
+                    None
+                ).await
+                .expect("instantiate pool failed").account_id;
+            let process_payment = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.process_merchant_payment(alice)
+            );
+            client
+                .call(&ink_e2e::alice(), process_payment, 2_000_000_000_000, None).await
+                .expect("payment failed");
+
+            let snapshot = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.get_ecosystem_snapshot()
+            );
+            let (d9_reserve, usdt_reserve, _, total_burned, merchant_volume) = client
+                .call_dry_run(&ink_e2e::alice(), &snapshot, 0, None).await
+                .return_value();
+            assert!(d9_reserve >= 500_000_000_000);
+            assert_eq!(usdt_reserve, 5_000_000_000_000);
+            assert_eq!(total_burned, 0);
+            assert_eq!(merchant_volume, 2_000_000_000_000);
+
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn pair_check_accepts_only_a_d9_usdt_amm(
+            mut client: ink_e2e::Client<C, E>
+        ) -> E2EResult<()> {
+            let alice = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let charlie = ink_e2e::account_id(ink_e2e::AccountKeyring::Charlie);
+            let usdt_address = client
+                .instantiate(
+                    "d9_usdt_permit_mock",
+                    &ink_e2e::alice(),
+                    D9USDTPermitMockRef::new(100_000_000_000_000),
+                    0,
+                    None
+                ).await
+                .expect("instantiate usdt failed").account_id;
+            let amm_address = client
+                .instantiate(
+                    "market_maker",
+                    &ink_e2e::alice(),
+                    MarketMakerRef::new(usdt_address, 1, 100),
+                    0,
+                    None
+                ).await
+                .expect("instantiate market maker failed").account_id;
+            let permit = build_message::<D9USDTPermitMockRef>(usdt_address.clone()).call(|usdt|
+                usdt.permit(alice, amm_address, 50_000_000_000_000, u64::MAX, 27, [0u8; 32], [0u8; 32])
+            );
+            client
+                .call(&ink_e2e::alice(), permit, 0, None).await
+                .expect("permit failed");
+            let add_liquidity = build_message::<MarketMakerRef>(amm_address.clone()).call(
+                |market_maker| market_maker.add_liquidity(5_000_000_000_000)
+            );
+            client
+                .call(&ink_e2e::alice(), add_liquidity, 500_000_000_000, None).await
+                .expect("add liquidity failed");
+
+            let pool_address = client
+                .instantiate(
+                    "mining_pool",
+                    &ink_e2e::alice(),
+                    MiningPoolRef::new_with_pair_check(bob, alice, charlie, amm_address),
+                    0,
+                    None
+                ).await
+                .expect("instantiate pool against the amm failed").account_id;
+            let verify_pair = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.verify_pair()
+            );
+            let verified = client
+                .call_dry_run(&ink_e2e::alice(), &verify_pair, 0, None).await
+                .return_value();
+            assert_eq!(verified, Ok(()));
+
+            // the usdt token has no `estimate_exchange`
+            let incompatible = client.instantiate(
+                "mining_pool",
+                &ink_e2e::alice(),
+                MiningPoolRef::new_with_pair_check(bob, alice, charlie, usdt_address),
+                0,
+                None
+            ).await;
+            assert!(incompatible.is_err());
+            let unchecked_address = client
+                .instantiate(
+                    "mining_pool",
+                    &ink_e2e::alice(),
+                    MiningPoolRef::new(bob, alice, charlie, usdt_address),
+                    0,
+                    None
+                ).await
+                .expect("instantiate unchecked pool failed").account_id;
+            let verify_pair = build_message::<MiningPoolRef>(unchecked_address.clone()).call(
+                |mining_pool| mining_pool.verify_pair()
+            );
+            let verified = client
+                .call_dry_run(&ink_e2e::alice(), &verify_pair, 0, None).await
+                .return_value();
+            assert_eq!(verified, Err(Error::IncompatibleAmmPair));
+
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn redemption_takes_the_better_amm(
+            mut client: ink_e2e::Client<C, E>
+        ) -> E2EResult<()> {
+            let alice = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let charlie = ink_e2e::account_id(ink_e2e::AccountKeyring::Charlie);
+            let eve = ink_e2e::account_id(ink_e2e::AccountKeyring::Eve);
+            // 2 d9 per usdt, 4% better, and one pool pushed far off the market
+            let mut amms = Vec::new();
+            for rate in [20_000_000_000, 20_800_000_000, 40_000_000_000] {
+                let amm_address = client
+                    .instantiate(
+                        "amm_mock",
+                        &ink_e2e::alice(),
+                        AmmMockRef::new(rate),
+                        0,
+                        None
+                    ).await
+                    .expect("instantiate amm failed").account_id;
+                amms.push(amm_address);
+            }
+            let pool_address = client
+                .instantiate(
+                    "mining_pool",
+                    &ink_e2e::alice(),
+                    MiningPoolRef::new(bob, alice, charlie, amms[0]),
+                    0,
+                    None
+                ).await
+                .expect("instantiate pool failed").account_id;
+            let set_amms = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.set_amm_contracts(vec![amms[1], amms[2]])
+            );
+            client
+                .call(&ink_e2e::alice(), set_amms, 0, None).await
+                .expect("set amm contracts failed");
+            let process_payment = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.process_merchant_payment(alice)
+            );
+            client
+                .call(&ink_e2e::alice(), process_payment, 2_000_000_000_000, None).await
+                .expect("funding payment failed");
+
+            let redeem = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.merchant_user_redeem_d9(eve, 50)
+            );
+            let redeemed = client
+                .call(&ink_e2e::alice(), redeem, 0, None).await
+                .expect("redeem failed")
+                .return_value();
+            // half a usdt at the better 2.08 d9 per usdt, the outlier is ignored
+            assert_eq!(redeemed, Ok(1_040_000_000_000));
+
+            // without the reference amm there is nothing to bound the others by
+            let set_rate = build_message::<AmmMockRef>(amms[0].clone()).call(|amm| amm.set_rate(0));
+            client
+                .call(&ink_e2e::alice(), set_rate, 0, None).await
+                .expect("set rate failed");
+            let preview = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.preview_redeem_d9(50)
+            );
+            let preview = client
+                .call_dry_run(&ink_e2e::alice(), &preview, 0, None).await
+                .return_value();
+            assert_eq!(preview, Err(Error::FailedToGetExchangeAmount));
+
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn redemption_falls_back_to_amm_without_oracle(
+            mut client: ink_e2e::Client<C, E>
+        ) -> E2EResult<()> {
+            let alice = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let charlie = ink_e2e::account_id(ink_e2e::AccountKeyring::Charlie);
+            let eve = ink_e2e::account_id(ink_e2e::AccountKeyring::Eve);
+            let ferdie = ink_e2e::account_id(ink_e2e::AccountKeyring::Ferdie);
+            let usdt_address = client
+                .instantiate(
+                    "d9_usdt_permit_mock",
+                    &ink_e2e::alice(),
+                    D9USDTPermitMockRef::new(100_000_000_000_000),
+                    0,
+                    None
+                ).await
+                .expect("instantiate usdt failed").account_id;
+            let amm_address = client
+                .instantiate(
+                    "market_maker",
+                    &ink_e2e::alice(),
+                    MarketMakerRef::new(usdt_address, 1, 100),
+                    0,
+                    None
+                ).await
+                .expect("instantiate market maker failed").account_id;
+            // the mock's permit stands in for approve
+            let permit = build_message::<D9USDTPermitMockRef>(usdt_address.clone()).call(|usdt|
+                usdt.permit(alice, amm_address, 50_000_000_000_000, u64::MAX, 27, [0u8; 32], [0u8; 32])
+            );
+            client
+                .call(&ink_e2e::alice(), permit, 0, None).await
+                .expect("permit failed");
+            let add_liquidity = build_message::<MarketMakerRef>(amm_address.clone()).call(
+                |market_maker| market_maker.add_liquidity(5_000_000_000_000)
+            );
+            client
+                .call(&ink_e2e::alice(), add_liquidity, 500_000_000_000, None).await
+                .expect("add liquidity failed");
+            let pool_address = client
+                .instantiate(
+                    "mining_pool",
+                    &ink_e2e::alice(),
+                    MiningPoolRef::new(bob, alice, charlie, amm_address),
+                    0,
+                    None
+                ).await
+                .expect("instantiate pool failed").account_id;
+            let process_payment = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.process_merchant_payment(alice)
+            );
+            client
+                .call(&ink_e2e::alice(), process_payment, 2_000_000_000_000, None).await
+                .expect("funding payment failed");
+
+            let preview = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.preview_redeem_d9(1_000_000)
+            );
+            let amm_preview = client
+                .call_dry_run(&ink_e2e::alice(), &preview, 0, None).await
+                .return_value()
+                .expect("amm preview failed");
+
+            // ferdie is no contract, the oracle call fails and the amm quote is used
+            let set_oracle = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.set_price_oracle(Some(ferdie))
+            );
+            client
+                .call(&ink_e2e::alice(), set_oracle, 0, None).await
+                .expect("set price oracle failed");
+            let fallback_preview = client
+                .call_dry_run(&ink_e2e::alice(), &preview, 0, None).await
+                .return_value()
+                .expect("fallback preview failed");
+            assert_eq!(fallback_preview, amm_preview);
+
+            let redeem = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.merchant_user_redeem_d9(eve, 1_000_000)
+            );
+            let redeemed = client
+                .call(&ink_e2e::alice(), redeem, 0, None).await
+                .expect("redeem failed")
+                .return_value();
+            assert_eq!(redeemed, Ok(amm_preview.d9_amount));
+            let stats = build_message::<MiningPoolRef>(pool_address.clone()).call(
+                |mining_pool| mining_pool.get_redemption_stats()
+            );
+            let stats = client
+                .call_dry_run(&ink_e2e::alice(), &stats, 0, None).await
+                .return_value();
+            assert_eq!(stats, (0, 0, amm_preview.d9_amount));
+
+            Ok(())
+        }
+
         /// We test that we can upload and instantiate the contract using its default constructor.
         #[ink_e2e::test]
         async fn default_works(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
@@ -7,6 +7,7 @@ pub use d9_chain_extension::D9Environment;
 mod node_reward {
     use super::*;
     use ink::env::call::{ build_call, ExecutionInput, Selector };
+    use ink::env::hash::{ Blake2x256, HashOutput };
     use ink::prelude::vec::Vec;
     use ink::selector_bytes;
     use ink::storage::Mapping;
@@ -25,6 +26,42 @@ mod node_reward {
         authorized_reward_receiver: Mapping<AccountId, AccountId>,
         /// minimum number of votes a node must have to receive a reward
         vote_limit: u64,
+        /// era points, relative to the session's best node, needed for a full reward. 0 disables
+        min_era_points_permille: u32,
+        /// set while distributions are frozen, sessions and withdrawals are refused until then
+        frozen_since: Option<Timestamp>,
+        /// latest session credited
+        last_processed_session: Option<u32>,
+        /// nodes and votes the rewards pallet sent for sessions that couldn't be credited yet
+        deferred_sessions: Mapping<u32, Vec<(AccountId, u64)>>,
+        /// (first, last) session still waiting in `deferred_sessions`
+        deferred_range: Option<(u32, u32)>,
+    }
+
+    /// scaling factor of a node that keeps its full reward
+    pub const FULL_REWARD_PERMILLE: u32 = 1_000;
+
+    /// most deferred sessions `update_rewards` credits before the session it was sent
+    pub const MAX_DEFERRED_PER_UPDATE: u32 = 4;
+
+    /// mirrors `SessionComputation` returned by the mining pool
+    #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct SessionComputation {
+        session_index: u32,
+        total_volume: Balance,
+        session_delta: Balance,
+        accumulative_reward_pool: Balance,
+        reward_pool: Balance,
+    }
+
+    impl SessionComputation {
+        /// must match the mining pool's hash of the same computation
+        fn hash(&self) -> Hash {
+            let mut output = <Blake2x256 as HashOutput>::Type::default();
+            ink::env::hash_encoded::<Blake2x256, _>(self, &mut output);
+            Hash::from(output)
+        }
     }
 
     #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
@@ -53,6 +90,15 @@ mod node_reward {
         NotAuthorizedToWithdraw,
         NothingToWithdraw,
         ErrorGettingCurrentValidators,
+        /// permille above 1_000
+        InvalidPermille,
+        DistributionFrozen,
+        DistributionNotFrozen,
+        /// the session, or a later one, has already been credited
+        SessionAlreadyProcessed,
+        /// the mining pool refused the session computed earlier in the same call
+        ErrorCommittingSessionToMiningPool,
+        ErrorGettingCurrentSession,
     }
     #[ink(event)]
     pub struct NodeRewardPaid {
@@ -71,6 +117,38 @@ mod node_reward {
         total_paid_out: Balance, 
     }
 
+    #[ink(event)]
+    pub struct RewardScaled {
+        #[ink(topic)]
+        node: AccountId,
+        factor_permille: u32,
+    }
+
+    #[ink(event)]
+    pub struct DistributionFreezeStarted {
+        frozen_at: Timestamp,
+    }
+
+    #[ink(event)]
+    pub struct DistributionFreezeEnded {
+        frozen_since: Timestamp,
+        unfrozen_at: Timestamp,
+    }
+
+    /// the session was stored to be credited by `process_deferred_sessions`
+    #[ink(event)]
+    pub struct SessionDeferred {
+        #[ink(topic)]
+        session_index: u32,
+    }
+
+    /// the deferred session was dropped by the admin without being credited
+    #[ink(event)]
+    pub struct DeferredSessionVoided {
+        #[ink(topic)]
+        session_index: u32,
+    }
+
     impl NodeReward {
         /// Constructor that initializes the `bool` value to the given `init_value`.
         #[ink(constructor)]
@@ -84,6 +162,11 @@ mod node_reward {
                 node_reward: Mapping::new(),
                 authorized_reward_receiver: Mapping::new(),
                 vote_limit: 680_000,
+                min_era_points_permille: 0,
+                frozen_since: None,
+                last_processed_session: None,
+                deferred_sessions: Mapping::new(),
+                deferred_range: None,
             }
         }
 
@@ -142,8 +225,77 @@ mod node_reward {
             Ok(())
         }
 
+        #[ink(message)]
+        pub fn get_min_era_points_permille(&self) -> u32 {
+            self.min_era_points_permille
+        }
+
+        #[ink(message)]
+        pub fn set_min_era_points_permille(
+            &mut self,
+            min_era_points_permille: u32
+        ) -> Result<(), Error> {
+            self.only_callable_by(self.admin)?;
+            if min_era_points_permille > FULL_REWARD_PERMILLE {
+                return Err(Error::InvalidPermille);
+            }
+            self.min_era_points_permille = min_era_points_permille;
+            Ok(())
+        }
+
+        /// stop crediting sessions and paying out rewards, e.g. when the chain's weighting
+        /// data can't be trusted. the mining pool is left alone and sessions are deferred
+        #[ink(message)]
+        pub fn freeze_distribution(&mut self) -> Result<(), Error> {
+            self.only_callable_by(self.admin)?;
+            if self.frozen_since.is_some() {
+                return Err(Error::DistributionFrozen);
+            }
+            let frozen_at = self.env().block_timestamp();
+            self.frozen_since = Some(frozen_at);
+            self.env().emit_event(DistributionFreezeStarted { frozen_at });
+            Ok(())
+        }
+
+        /// sessions deferred while frozen are credited by the next `update_rewards`, or by
+        /// anyone through `process_deferred_sessions`
+        #[ink(message)]
+        pub fn unfreeze_distribution(&mut self) -> Result<(), Error> {
+            self.only_callable_by(self.admin)?;
+            let frozen_since = self.frozen_since.take().ok_or(Error::DistributionNotFrozen)?;
+            self.env().emit_event(DistributionFreezeEnded {
+                frozen_since,
+                unfrozen_at: self.env().block_timestamp(),
+            });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_frozen_since(&self) -> Option<Timestamp> {
+            self.frozen_since
+        }
+
+        #[ink(message)]
+        pub fn get_last_processed_session(&self) -> Option<u32> {
+            self.last_processed_session
+        }
+
+        /// (first, last) session waiting for `process_deferred_sessions`
+        #[ink(message)]
+        pub fn get_deferred_range(&self) -> Option<(u32, u32)> {
+            self.deferred_range
+        }
+
+        fn ensure_not_frozen(&self) -> Result<(), Error> {
+            if self.frozen_since.is_some() {
+                return Err(Error::DistributionFrozen);
+            }
+            Ok(())
+        }
+
         #[ink(message)]
         pub fn withdraw_reward(&mut self, node_id: AccountId) -> Result<(), Error> {
+            self.ensure_not_frozen()?;
             let caller = self.env().caller();
             let _ = self.validate_withdraw(node_id, caller)?;
             let reward_balance = self.node_reward.get(&node_id).unwrap_or(0);
@@ -199,6 +351,10 @@ mod node_reward {
             Ok(())
         }
 
+        /// while frozen the session is stored as sent instead. once unfrozen, earlier deferred
+        /// sessions go first: the session queues behind them and up to
+        /// `MAX_DEFERRED_PER_UPDATE` of the queue is credited, so sessions always reach the
+        /// mining pool in order
         #[ink(message)]
         pub fn update_rewards(
             &mut self,
@@ -206,15 +362,124 @@ mod node_reward {
             sorted_nodes_and_votes: Vec<(AccountId, u64)>
         ) -> Result<(), Error> {
             self.only_callable_by(self.rewards_pallet)?;
+            if self.frozen_since.is_some() {
+                return self.defer_session(last_session, sorted_nodes_and_votes);
+            }
+            if self.deferred_range.is_some() {
+                self.defer_session(last_session, sorted_nodes_and_votes)?;
+                // the pallet only sends sessions that ended, so everything queued up to this one
+                // has ended too
+                self.credit_deferred_sessions(
+                    MAX_DEFERRED_PER_UPDATE,
+                    last_session.saturating_add(1)
+                )?;
+                return Ok(());
+            }
+            self.process_session(last_session, sorted_nodes_and_votes)
+        }
+
+        /// credit up to `limit` deferred sessions oldest first, with the nodes and votes the
+        /// rewards pallet sent for them. sessions that haven't ended on chain are left waiting.
+        /// anyone may call. returns how many sessions were credited
+        #[ink(message)]
+        pub fn process_deferred_sessions(&mut self, limit: u32) -> Result<u32, Error> {
+            self.ensure_not_frozen()?;
+            let current_session = self
+                .env()
+                .extension()
+                .get_current_session_index()
+                .map_err(|_| Error::ErrorGettingCurrentSession)?;
+            self.credit_deferred_sessions(limit, current_session)
+        }
+
+        /// drop up to `limit` deferred sessions oldest first without crediting them, e.g. when
+        /// the data sent for them was corrupted. they count as processed, so the pallet can't
+        /// send them again. returns how many sessions were voided
+        #[ink(message)]
+        pub fn void_deferred_sessions(&mut self, limit: u32) -> Result<u32, Error> {
+            self.only_callable_by(self.admin)?;
+            let mut voided = 0;
+            for _ in 0..limit {
+                let Some(session_index) = self.pop_deferred_session(None) else {
+                    break;
+                };
+                self.deferred_sessions.remove(session_index);
+                self.last_processed_session = Some(
+                    self.last_processed_session.map_or(session_index, |last|
+                        last.max(session_index)
+                    )
+                );
+                self.env().emit_event(DeferredSessionVoided { session_index });
+                voided += 1;
+            }
+            Ok(voided)
+        }
+
+        /// credits deferred sessions before `end_session`, at most `limit` of them
+        fn credit_deferred_sessions(&mut self, limit: u32, end_session: u32) -> Result<u32, Error> {
+            let mut processed = 0;
+            for _ in 0..limit {
+                let Some(session_index) = self.pop_deferred_session(Some(end_session)) else {
+                    break;
+                };
+                if let Some(sorted_nodes_and_votes) = self.deferred_sessions.take(session_index) {
+                    self.process_session(session_index, sorted_nodes_and_votes)?;
+                    processed += 1;
+                }
+            }
+            Ok(processed)
+        }
+
+        /// takes the oldest deferred session off `deferred_range` if it is before `end_session`
+        fn pop_deferred_session(&mut self, end_session: Option<u32>) -> Option<u32> {
+            let (first, last) = self.deferred_range?;
+            if matches!(end_session, Some(end_session) if first >= end_session) {
+                return None;
+            }
+            self.deferred_range = if first < last { Some((first + 1, last)) } else { None };
+            Some(first)
+        }
+
+        fn defer_session(
+            &mut self,
+            session_index: u32,
+            sorted_nodes_and_votes: Vec<(AccountId, u64)>
+        ) -> Result<(), Error> {
+            let latest = self.deferred_range
+                .map(|(_, last)| last)
+                .or(self.last_processed_session);
+            if matches!(latest, Some(latest) if session_index <= latest) {
+                return Err(Error::SessionAlreadyProcessed);
+            }
+            self.deferred_sessions.insert(session_index, &sorted_nodes_and_votes);
+            let first = self.deferred_range.map_or(session_index, |(first, _)| first);
+            self.deferred_range = Some((first, session_index));
+            self.env().emit_event(SessionDeferred { session_index });
+            Ok(())
+        }
+
+        fn process_session(
+            &mut self,
+            last_session: u32,
+            sorted_nodes_and_votes: Vec<(AccountId, u64)>
+        ) -> Result<(), Error> {
             let mut nodes_and_votes_vec: Vec<(AccountId, u64)> = sorted_nodes_and_votes.clone();
             // let current_active_validators = self.get_active_validators()?;
             let mut total_paid_out: Balance = 0;
-            let reward_pool = self.get_reward_pool(last_session)?;
+            // the pool only accrues once the split is known, see `commit_session_to_pool`
+            let computation = self.compute_session_from_pool(last_session)?;
+            // a mining pool without `compute_session_reward` accrues in the legacy update
+            let reward_pool = match computation {
+                Some(computation) => computation.reward_pool,
+                None => self.get_reward_pool(last_session)?,
+            };
             // from pallet it is truncated to limit of MaxCandidates
             // here we truncate to max payable of 288
             if nodes_and_votes_vec.len() > 288 {
                 nodes_and_votes_vec.truncate(288);
             }
+            let mut paid_nodes: Vec<AccountId> = Vec::new();
+            let mut node_shares: Vec<Balance> = Vec::new();
             for (index, node_and_votes) in nodes_and_votes_vec.iter().enumerate() {
                 let get_node_tier_result = self.node_tier_by_vec_position(index);
                 if get_node_tier_result.is_err() {
@@ -224,13 +489,46 @@ mod node_reward {
                 let node_share = self.calc_single_node_share(reward_pool, node_tier);
 
                 if node_and_votes.1 >= self.vote_limit {
-                    let node_id: AccountId = node_and_votes.0;
-                    let _ = self.credit_node_reward(node_id, node_share)?;
-                    total_paid_out = total_paid_out.saturating_add(node_share);
-                    let _ = self.deduct_from_reward_pool(node_share);
+                    paid_nodes.push(node_and_votes.0);
+                    node_shares.push(node_share);
+                }
+            }
+            let scaled_shares = match self.get_era_points(last_session, &paid_nodes) {
+                Some(era_points) =>
+                    Self::calc_uptime_scaled_shares(
+                        &node_shares,
+                        &era_points,
+                        self.min_era_points_permille
+                    ),
+                None =>
+                    node_shares
+                        .iter()
+                        .map(|node_share| (*node_share, FULL_REWARD_PERMILLE))
+                        .collect(),
+            };
+            if let Some(computation) = computation {
+                self.commit_session_to_pool(last_session, computation.hash())?;
+            }
+            for (node_id, (node_share, factor_permille)) in paid_nodes
+                .into_iter()
+                .zip(scaled_shares) {
+                if factor_permille < FULL_REWARD_PERMILLE {
+                    self.env().emit_event(RewardScaled {
+                        node: node_id,
+                        factor_permille,
+                    });
+                }
+                if node_share == 0 {
+                    continue;
                 }
+                let _ = self.credit_node_reward(node_id, node_share)?;
+                total_paid_out = total_paid_out.saturating_add(node_share);
+                let _ = self.deduct_from_reward_pool(node_share);
             }
             self.session_rewards.insert(last_session, &(reward_pool, total_paid_out));
+            self.last_processed_session = Some(
+                self.last_processed_session.map_or(last_session, |last| last.max(last_session))
+            );
             self.env().emit_event(SessionRewardsIssued {
                 session_index: last_session,
                 reward_pool,
@@ -239,6 +537,89 @@ mod node_reward {
             Ok(())
         }
 
+        /// era points of `nodes` in the same order, `None` when the threshold is off or the
+        /// chain can't answer, in which case nobody is scaled
+        fn get_era_points(&self, session_index: u32, nodes: &[AccountId]) -> Option<Vec<u32>> {
+            if self.min_era_points_permille == 0 || nodes.is_empty() {
+                return None;
+            }
+            let session_era_points = self
+                .env()
+                .extension()
+                .get_session_era_points(session_index)
+                .ok()?;
+            let era_points = nodes
+                .iter()
+                .map(|node_id| {
+                    session_era_points
+                        .iter()
+                        .find(|(account_id, _)| account_id == node_id)
+                        .map_or(0, |(_, points)| *points)
+                })
+                .collect();
+            Some(era_points)
+        }
+
+        /// (share, factor_permille) for each node, `era_points` lines up with `node_shares`.
+        ///
+        /// uptime is a node's era points relative to the best node. below
+        /// `min_era_points_permille` the share shrinks linearly down to nothing at zero points,
+        /// and what is withheld goes to the nodes at or above the threshold pro rata
+        fn calc_uptime_scaled_shares(
+            node_shares: &[Balance],
+            era_points: &[u32],
+            min_era_points_permille: u32
+        ) -> Vec<(Balance, u32)> {
+            let max_points = era_points.iter().copied().max().unwrap_or(0);
+            if min_era_points_permille == 0 || max_points == 0 {
+                return node_shares
+                    .iter()
+                    .map(|node_share| (*node_share, FULL_REWARD_PERMILLE))
+                    .collect();
+            }
+            let factors: Vec<u32> = era_points
+                .iter()
+                .map(|points| {
+                    let uptime_permille =
+                        (u64::from(*points) * u64::from(FULL_REWARD_PERMILLE)) /
+                        u64::from(max_points);
+                    if uptime_permille >= u64::from(min_era_points_permille) {
+                        FULL_REWARD_PERMILLE
+                    } else {
+                        ((uptime_permille * u64::from(FULL_REWARD_PERMILLE)) /
+                            u64::from(min_era_points_permille)) as u32
+                    }
+                })
+                .collect();
+            let mut withheld: Balance = 0;
+            let mut full_shares_total: Balance = 0;
+            let mut scaled: Vec<(Balance, u32)> = Vec::new();
+            for (node_share, factor_permille) in node_shares.iter().zip(factors) {
+                let scaled_share = Perquintill::from_rational(
+                    u64::from(factor_permille),
+                    u64::from(FULL_REWARD_PERMILLE)
+                ).mul_floor(*node_share);
+                if factor_permille == FULL_REWARD_PERMILLE {
+                    full_shares_total = full_shares_total.saturating_add(*node_share);
+                } else {
+                    withheld = withheld.saturating_add(node_share.saturating_sub(scaled_share));
+                }
+                scaled.push((scaled_share, factor_permille));
+            }
+            if withheld == 0 || full_shares_total == 0 {
+                return scaled;
+            }
+            for (node_share, factor_permille) in scaled.iter_mut() {
+                if *factor_permille == FULL_REWARD_PERMILLE {
+                    let bonus = Perquintill::from_rational(*node_share, full_shares_total).mul_floor(
+                        withheld
+                    );
+                    *node_share = node_share.saturating_add(bonus);
+                }
+            }
+            scaled
+        }
+
         fn validate_withdraw(&self, node_id: AccountId, requester: AccountId) -> Result<(), Error> {
             let authorized_receiver = self.authorized_reward_receiver.get(&node_id);
             match authorized_receiver {
@@ -310,6 +691,58 @@ mod node_reward {
                 .invoke()
         }
 
+        /// `None` when the mining pool predates the two phase session update
+        fn compute_session_from_pool(
+            &self,
+            session_index: u32
+        ) -> Result<Option<SessionComputation>, Error> {
+            let result = build_call::<D9Environment>()
+                .call(self.mining_pool)
+                .gas_limit(0)
+                .exec_input(
+                    ExecutionInput::new(
+                        Selector::new(selector_bytes!("compute_session_reward"))
+                    ).push_arg(session_index)
+                )
+                .returns::<Result<SessionComputation, Error>>()
+                .try_invoke();
+            if Self::is_missing_message(&result) {
+                return Ok(None);
+            }
+            match result {
+                Ok(Ok(Ok(computation))) => Ok(Some(computation)),
+                _ => Err(Error::ErrorGettingSessionPoolFromMiningPoolContract),
+            }
+        }
+
+        /// accrues the session in the mining pool, only if it still computes to what the split
+        /// was based on
+        fn commit_session_to_pool(
+            &self,
+            session_index: u32,
+            computation_hash: Hash
+        ) -> Result<(), Error> {
+            let result = build_call::<D9Environment>()
+                .call(self.mining_pool)
+                .gas_limit(0)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(selector_bytes!("commit_session")))
+                        .push_arg(session_index)
+                        .push_arg(computation_hash)
+                )
+                .returns::<Result<(), Error>>()
+                .try_invoke();
+            match result {
+                Ok(Ok(Ok(()))) => Ok(()),
+                _ => Err(Error::ErrorCommittingSessionToMiningPool),
+            }
+        }
+
+        /// an unknown selector is rejected by the callee before any contract logic runs
+        fn is_missing_message<T>(result: &ink::env::Result<ink::MessageResult<T>>) -> bool {
+            matches!(result, Ok(Err(ink::LangError::CouldNotReadInput)))
+        }
+
         fn get_reward_pool(&self, session_index: u32) -> Result<Balance, Error> {
             let result = build_call::<D9Environment>()
                 .call(self.mining_pool)
@@ -388,6 +821,153 @@ mod node_reward {
             let node_reward = NodeReward::default();
             assert_eq!(node_reward.get(), false);
         }
+
+        #[ink::test]
+        fn missing_compute_message_is_detected() {
+            let missing: ink::env::Result<ink::MessageResult<Result<SessionComputation, Error>>> =
+                Ok(Err(ink::LangError::CouldNotReadInput));
+            assert!(NodeReward::is_missing_message(&missing));
+
+            let rejected: ink::env::Result<ink::MessageResult<Result<SessionComputation, Error>>> =
+                Ok(Ok(Err(Error::ErrorGettingSessionPoolFromMiningPoolContract)));
+            assert!(!NodeReward::is_missing_message(&rejected));
+
+            let trapped: ink::env::Result<ink::MessageResult<Result<SessionComputation, Error>>> =
+                Err(ink::env::Error::CalleeTrapped);
+            assert!(!NodeReward::is_missing_message(&trapped));
+        }
+
+        #[ink::test]
+        fn uptime_above_threshold_keeps_shares() {
+            let shares = [3_000, 2_000, 1_000];
+            let scaled = NodeReward::calc_uptime_scaled_shares(&shares, &[100, 95, 90], 800);
+            assert_eq!(scaled, vec![(3_000, 1_000), (2_000, 1_000), (1_000, 1_000)]);
+            // no era points at all means no uptime data, not a dead network
+            let scaled = NodeReward::calc_uptime_scaled_shares(&shares, &[0, 0, 0], 800);
+            assert_eq!(scaled, vec![(3_000, 1_000), (2_000, 1_000), (1_000, 1_000)]);
+        }
+
+        #[ink::test]
+        fn offline_node_is_skipped_and_its_share_redistributed() {
+            let scaled = NodeReward::calc_uptime_scaled_shares(
+                &[3_000, 1_000, 2_000],
+                &[100, 100, 0],
+                500
+            );
+            assert_eq!(scaled, vec![(4_500, 1_000), (1_500, 1_000), (0, 0)]);
+        }
+
+        #[ink::test]
+        fn low_uptime_node_is_scaled_down() {
+            // 40% uptime against an 80% threshold halves the share
+            let scaled = NodeReward::calc_uptime_scaled_shares(
+                &[2_000, 2_000, 1_000],
+                &[100, 90, 40],
+                800
+            );
+            assert_eq!(scaled, vec![(2_250, 1_000), (2_250, 1_000), (500, 500)]);
+            let paid: Balance = scaled.iter().map(|(share, _)| share).sum();
+            assert_eq!(paid, 5_000);
+        }
+        #[ink::test]
+        fn frozen_distribution_refuses_sessions_and_withdrawals() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut node_reward = NodeReward::new(accounts.bob, accounts.charlie);
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1_000);
+            assert_eq!(node_reward.get_frozen_since(), None);
+            assert_eq!(node_reward.unfreeze_distribution(), Err(Error::DistributionNotFrozen));
+            assert_eq!(node_reward.freeze_distribution(), Ok(()));
+            assert_eq!(node_reward.get_frozen_since(), Some(1_000));
+            assert_eq!(node_reward.freeze_distribution(), Err(Error::DistributionFrozen));
+
+            // nothing is asked of the mining pool while frozen, the session waits
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            assert_eq!(node_reward.update_rewards(7, vec![(accounts.django, 1_000_000)]), Ok(()));
+            assert_eq!(node_reward.get_deferred_range(), Some((7, 7)));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            assert_eq!(
+                node_reward.withdraw_reward(accounts.django),
+                Err(Error::DistributionFrozen)
+            );
+            assert_eq!(
+                node_reward.freeze_distribution(),
+                Err(Error::OnlyCallableBy(accounts.alice))
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(node_reward.process_deferred_sessions(1), Err(Error::DistributionFrozen));
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(5_000);
+            assert_eq!(node_reward.unfreeze_distribution(), Ok(()));
+            assert_eq!(node_reward.get_frozen_since(), None);
+            // withdrawals are back to the usual checks
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            assert_eq!(node_reward.withdraw_reward(accounts.django), Err(Error::NothingToWithdraw));
+            assert_eq!(ink::env::test::recorded_events().count(), 3);
+        }
+
+        #[ink::test]
+        fn deferred_sessions_keep_their_order() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut node_reward = NodeReward::new(accounts.bob, accounts.charlie);
+            // session 5 was credited before the freeze
+            node_reward.last_processed_session = Some(5);
+            assert_eq!(node_reward.freeze_distribution(), Ok(()));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            let votes = vec![(accounts.django, 1_000_000), (accounts.eve, 900_000)];
+            assert_eq!(
+                node_reward.update_rewards(5, votes.clone()),
+                Err(Error::SessionAlreadyProcessed)
+            );
+            assert_eq!(node_reward.update_rewards(6, votes.clone()), Ok(()));
+            assert_eq!(node_reward.update_rewards(7, Vec::new()), Ok(()));
+            assert_eq!(
+                node_reward.update_rewards(7, votes.clone()),
+                Err(Error::SessionAlreadyProcessed)
+            );
+            assert_eq!(node_reward.get_deferred_range(), Some((6, 7)));
+            assert_eq!(node_reward.deferred_sessions.get(6), Some(votes.clone()));
+
+            // nobody credits them while frozen
+            assert_eq!(node_reward.process_deferred_sessions(3), Err(Error::DistributionFrozen));
+            assert_eq!(node_reward.get_last_processed_session(), Some(5));
+        }
+
+        #[ink::test]
+        fn admin_voids_deferred_sessions_in_order() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut node_reward = NodeReward::new(accounts.bob, accounts.charlie);
+            node_reward.last_processed_session = Some(5);
+            assert_eq!(node_reward.freeze_distribution(), Ok(()));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            let votes = vec![(accounts.django, 1_000_000)];
+            for session_index in 6..=8 {
+                assert_eq!(node_reward.update_rewards(session_index, votes.clone()), Ok(()));
+            }
+            assert_eq!(
+                node_reward.void_deferred_sessions(1),
+                Err(Error::OnlyCallableBy(accounts.alice))
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(node_reward.void_deferred_sessions(2), Ok(2));
+            assert_eq!(node_reward.get_deferred_range(), Some((8, 8)));
+            assert_eq!(node_reward.deferred_sessions.get(6), None);
+            assert_eq!(node_reward.get_last_processed_session(), Some(7));
+            // a voided session counts as processed and can't be sent again
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            assert_eq!(
+                node_reward.update_rewards(7, votes.clone()),
+                Err(Error::SessionAlreadyProcessed)
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(node_reward.void_deferred_sessions(5), Ok(1));
+            assert_eq!(node_reward.get_deferred_range(), None);
+            assert_eq!(node_reward.get_last_processed_session(), Some(8));
+            assert_eq!(node_reward.void_deferred_sessions(5), Ok(0));
+        }
+
         //   #[ink::test]
         //   fn it_works() {
         //       let mut node_reward = NodeReward::new(false);
@@ -409,6 +989,9 @@ mod node_reward {
 
         /// A helper function used for calling contract messages.
         use ink_e2e::build_message;
+        use legacy_mining_pool_mock::legacy_mining_pool_mock::LegacyMiningPoolMockRef;
+        use main_pool::d9_main_pool::D9MainPoolRef;
+        use mining_pool::mining_pool::{ Error as MiningPoolError, MiningPoolRef };
 
         /// The End-to-End test `Result` type.
         type E2EResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
@@ -466,5 +1049,315 @@ mod node_reward {
 
             Ok(())
         }
+
+        /// alice stands in for the rewards pallet and the merchant contract, bob for the amm
+        #[ink_e2e::test(additional_contracts = "../mining-pool/Cargo.toml ../main-pool/Cargo.toml")]
+        async fn session_rewards_come_from_the_pool_computation(
+            mut client: ink_e2e::Client<C, E>
+        ) -> E2EResult<()> {
+            let alice = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let node_reward_address = client
+                .instantiate(
+                    "node_reward",
+                    &ink_e2e::alice(),
+                    NodeRewardRef::new(alice, alice),
+                    0,
+                    None
+                ).await
+                .expect("instantiate node reward failed").account_id;
+            let main_pool_address = client
+                .instantiate(
+                    "main-pool",
+                    &ink_e2e::alice(),
+                    D9MainPoolRef::new(alice, Vec::new(), node_reward_address, alice),
+                    0,
+                    None
+                ).await
+                .expect("instantiate main pool failed").account_id;
+            let mining_pool_address = client
+                .instantiate(
+                    "mining_pool",
+                    &ink_e2e::alice(),
+                    MiningPoolRef::new(main_pool_address, alice, node_reward_address, bob),
+                    0,
+                    None
+                ).await
+                .expect("instantiate mining pool failed").account_id;
+
+            let set_mining_pool = build_message::<NodeRewardRef>(node_reward_address.clone()).call(
+                |node_reward| node_reward.set_mining_pool(mining_pool_address)
+            );
+            client
+                .call(&ink_e2e::alice(), set_mining_pool, 0, None).await
+                .expect("set mining pool failed");
+
+            // merchant volume becomes the session delta
+            let process_payment = build_message::<MiningPoolRef>(mining_pool_address.clone()).call(
+                |mining_pool| mining_pool.process_merchant_payment(alice)
+            );
+            client
+                .call(&ink_e2e::alice(), process_payment, 1_000_000_000, None).await
+                .expect("process merchant payment failed");
+
+            let update_rewards = build_message::<NodeRewardRef>(node_reward_address.clone()).call(
+                |node_reward| node_reward.update_rewards(1, Vec::new())
+            );
+            client
+                .call(&ink_e2e::alice(), update_rewards, 0, None).await
+                .expect("update rewards failed");
+
+            let get_session_rewards = build_message::<NodeRewardRef>(
+                node_reward_address.clone()
+            ).call(|node_reward| node_reward.get_session_rewards_data(1));
+            let session_rewards = client
+                .call_dry_run(&ink_e2e::alice(), &get_session_rewards, 0, None).await
+                .return_value();
+            // 10% of the 3% of the delta added to the pool, nobody to pay
+            assert_eq!(session_rewards, Some((3_000_000, 0)));
+
+            Ok(())
+        }
+
+        /// alice stands in for the rewards pallet
+        #[ink_e2e::test(additional_contracts = "../legacy-mining-pool-mock/Cargo.toml")]
+        async fn session_rewards_fall_back_to_the_legacy_pool_update(
+            mut client: ink_e2e::Client<C, E>
+        ) -> E2EResult<()> {
+            let alice = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let node_reward_address = client
+                .instantiate(
+                    "node_reward",
+                    &ink_e2e::alice(),
+                    NodeRewardRef::new(alice, alice),
+                    0,
+                    None
+                ).await
+                .expect("instantiate node reward failed").account_id;
+            // a pool without `compute_session_reward`
+            let mining_pool_address = client
+                .instantiate(
+                    "legacy_mining_pool_mock",
+                    &ink_e2e::alice(),
+                    LegacyMiningPoolMockRef::new(5_000_000),
+                    0,
+                    None
+                ).await
+                .expect("instantiate legacy mining pool failed").account_id;
+            let set_mining_pool = build_message::<NodeRewardRef>(node_reward_address.clone()).call(
+                |node_reward| node_reward.set_mining_pool(mining_pool_address)
+            );
+            client
+                .call(&ink_e2e::alice(), set_mining_pool, 0, None).await
+                .expect("set mining pool failed");
+
+            let update_rewards = build_message::<NodeRewardRef>(node_reward_address.clone()).call(
+                |node_reward| node_reward.update_rewards(1, Vec::new())
+            );
+            client
+                .call(&ink_e2e::alice(), update_rewards, 0, None).await
+                .expect("update rewards failed");
+
+            let get_session_rewards = build_message::<NodeRewardRef>(
+                node_reward_address.clone()
+            ).call(|node_reward| node_reward.get_session_rewards_data(1));
+            let session_rewards = client
+                .call_dry_run(&ink_e2e::alice(), &get_session_rewards, 0, None).await
+                .return_value();
+            assert_eq!(session_rewards, Some((5_000_000, 0)));
+
+            Ok(())
+        }
+
+        /// alice stands in for the rewards pallet and the merchant contract, bob for the amm
+        #[ink_e2e::test(additional_contracts = "../mining-pool/Cargo.toml ../main-pool/Cargo.toml")]
+        async fn deferred_session_is_credited_after_unfreeze(
+            mut client: ink_e2e::Client<C, E>
+        ) -> E2EResult<()> {
+            let alice = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let charlie = ink_e2e::account_id(ink_e2e::AccountKeyring::Charlie);
+            let node_reward_address = client
+                .instantiate(
+                    "node_reward",
+                    &ink_e2e::alice(),
+                    NodeRewardRef::new(alice, alice),
+                    0,
+                    None
+                ).await
+                .expect("instantiate node reward failed").account_id;
+            let main_pool_address = client
+                .instantiate(
+                    "main-pool",
+                    &ink_e2e::alice(),
+                    D9MainPoolRef::new(alice, Vec::new(), node_reward_address, alice),
+                    0,
+                    None
+                ).await
+                .expect("instantiate main pool failed").account_id;
+            let mining_pool_address = client
+                .instantiate(
+                    "mining_pool",
+                    &ink_e2e::alice(),
+                    MiningPoolRef::new(main_pool_address, alice, node_reward_address, bob),
+                    0,
+                    None
+                ).await
+                .expect("instantiate mining pool failed").account_id;
+            let set_mining_pool = build_message::<NodeRewardRef>(node_reward_address.clone()).call(
+                |node_reward| node_reward.set_mining_pool(mining_pool_address)
+            );
+            client
+                .call(&ink_e2e::alice(), set_mining_pool, 0, None).await
+                .expect("set mining pool failed");
+            let process_payment = build_message::<MiningPoolRef>(mining_pool_address.clone()).call(
+                |mining_pool| mining_pool.process_merchant_payment(alice)
+            );
+            client
+                .call(&ink_e2e::alice(), process_payment, 1_000_000_000, None).await
+                .expect("process merchant payment failed");
+
+            // session 1 ends while frozen and is stored as sent
+            let freeze = build_message::<NodeRewardRef>(node_reward_address.clone()).call(
+                |node_reward| node_reward.freeze_distribution()
+            );
+            client
+                .call(&ink_e2e::alice(), freeze, 0, None).await
+                .expect("freeze failed");
+            let update_rewards = build_message::<NodeRewardRef>(node_reward_address.clone()).call(
+                |node_reward| node_reward.update_rewards(1, vec![(charlie, 1_000_000)])
+            );
+            client
+                .call(&ink_e2e::alice(), update_rewards, 0, None).await
+                .expect("deferred update rewards failed");
+            let get_range = build_message::<NodeRewardRef>(node_reward_address.clone()).call(
+                |node_reward| node_reward.get_deferred_range()
+            );
+            let range = client
+                .call_dry_run(&ink_e2e::alice(), &get_range, 0, None).await
+                .return_value();
+            assert_eq!(range, Some((1, 1)));
+
+            // the next session after the freeze credits session 1 first, then itself
+            let unfreeze = build_message::<NodeRewardRef>(node_reward_address.clone()).call(
+                |node_reward| node_reward.unfreeze_distribution()
+            );
+            client
+                .call(&ink_e2e::alice(), unfreeze, 0, None).await
+                .expect("unfreeze failed");
+            let update_rewards = build_message::<NodeRewardRef>(node_reward_address.clone()).call(
+                |node_reward| node_reward.update_rewards(2, Vec::new())
+            );
+            client
+                .call(&ink_e2e::alice(), update_rewards, 0, None).await
+                .expect("update rewards failed");
+            let range = client
+                .call_dry_run(&ink_e2e::alice(), &get_range, 0, None).await
+                .return_value();
+            assert_eq!(range, None);
+            let get_last = build_message::<NodeRewardRef>(node_reward_address.clone()).call(
+                |node_reward| node_reward.get_last_processed_session()
+            );
+            let last = client
+                .call_dry_run(&ink_e2e::alice(), &get_last, 0, None).await
+                .return_value();
+            assert_eq!(last, Some(2));
+
+            let get_rewards = build_message::<NodeRewardRef>(node_reward_address.clone()).call(
+                |node_reward| node_reward.get_session_rewards_data(1)
+            );
+            let (reward_pool, total_paid_out) = client
+                .call_dry_run(&ink_e2e::alice(), &get_rewards, 0, None).await
+                .return_value()
+                .expect("session 1 was not credited");
+            assert_eq!(reward_pool, 3_000_000);
+            assert!(total_paid_out > 0);
+            let get_node_reward = build_message::<NodeRewardRef>(node_reward_address.clone()).call(
+                |node_reward| node_reward.get_node_reward_data(charlie)
+            );
+            let node_reward = client
+                .call_dry_run(&ink_e2e::alice(), &get_node_reward, 0, None).await
+                .return_value();
+            assert_eq!(node_reward, Some(total_paid_out));
+
+            Ok(())
+        }
+
+        /// bob stands in for the merchant contract, alice for the node reward contract
+        #[ink_e2e::test(additional_contracts = "../mining-pool/Cargo.toml ../main-pool/Cargo.toml")]
+        async fn stale_session_computation_is_not_committed(
+            mut client: ink_e2e::Client<C, E>
+        ) -> E2EResult<()> {
+            let alice = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let charlie = ink_e2e::account_id(ink_e2e::AccountKeyring::Charlie);
+            let main_pool_address = client
+                .instantiate(
+                    "main-pool",
+                    &ink_e2e::alice(),
+                    D9MainPoolRef::new(alice, Vec::new(), charlie, alice),
+                    0,
+                    None
+                ).await
+                .expect("instantiate main pool failed").account_id;
+            let mining_pool_address = client
+                .instantiate(
+                    "mining_pool",
+                    &ink_e2e::alice(),
+                    MiningPoolRef::new(main_pool_address, bob, alice, charlie),
+                    0,
+                    None
+                ).await
+                .expect("instantiate mining pool failed").account_id;
+            let process_payment = build_message::<MiningPoolRef>(mining_pool_address.clone()).call(
+                |mining_pool| mining_pool.process_merchant_payment(bob)
+            );
+            client
+                .call(&ink_e2e::bob(), process_payment, 1_000_000_000, None).await
+                .expect("process merchant payment failed");
+
+            let compute = build_message::<MiningPoolRef>(mining_pool_address.clone()).call(
+                |mining_pool| mining_pool.compute_session_reward(1)
+            );
+            let computation = client
+                .call_dry_run(&ink_e2e::alice(), &compute, 0, None).await
+                .return_value()
+                .expect("compute session failed");
+            assert_eq!(computation.session_delta, 1_000_000_000);
+
+            // volume arriving between the computation and the commit
+            let process_payment = build_message::<MiningPoolRef>(mining_pool_address.clone()).call(
+                |mining_pool| mining_pool.process_merchant_payment(bob)
+            );
+            client
+                .call(&ink_e2e::bob(), process_payment, 1_000_000_000, None).await
+                .expect("process merchant payment failed");
+            let commit = build_message::<MiningPoolRef>(mining_pool_address.clone()).call(
+                |mining_pool| mining_pool.commit_session(1, computation.hash())
+            );
+            let committed = client.call_dry_run(&ink_e2e::alice(), &commit, 0, None).await;
+            assert_eq!(committed.return_value(), Err(MiningPoolError::StaleSessionComputation));
+
+            let computation = client
+                .call_dry_run(&ink_e2e::alice(), &compute, 0, None).await
+                .return_value()
+                .expect("compute session failed");
+            assert_eq!(computation.session_delta, 2_000_000_000);
+            let commit = build_message::<MiningPoolRef>(mining_pool_address.clone()).call(
+                |mining_pool| mining_pool.commit_session(1, computation.hash())
+            );
+            client
+                .call(&ink_e2e::alice(), commit, 0, None).await
+                .expect("commit session failed");
+            let get_pool = build_message::<MiningPoolRef>(mining_pool_address.clone()).call(
+                |mining_pool| mining_pool.get_accumulative_reward_pool()
+            );
+            let pool = client
+                .call_dry_run(&ink_e2e::alice(), &get_pool, 0, None).await
+                .return_value();
+            assert_eq!(pool, computation.accumulative_reward_pool);
+
+            Ok(())
+        }
     }
 }
@@ -0,0 +1,38 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+/// stands in for the price oracle in e2e tests, `get_twap` answers with a settable rate
+#[ink::contract]
+pub mod price_oracle_mock {
+    #[ink(storage)]
+    pub struct PriceOracleMock {
+        twap: Balance,
+        updated_at: Timestamp,
+    }
+
+    impl PriceOracleMock {
+        #[ink(constructor)]
+        pub fn new(twap: Balance) -> Self {
+            Self { twap, updated_at: Self::env().block_timestamp() }
+        }
+
+        /// (whole d9 per whole usdt in the mining pool's `PRICE_PRECISION` units, time of the last
+        /// update),
+        /// the window is ignored
+        #[ink(message)]
+        pub fn get_twap(&self, _window_ms: Timestamp) -> (Balance, Timestamp) {
+            (self.twap, self.updated_at)
+        }
+
+        #[ink(message)]
+        pub fn set_twap(&mut self, twap: Balance) {
+            self.twap = twap;
+            self.updated_at = self.env().block_timestamp();
+        }
+
+        /// backdates the last update to simulate an oracle that stopped updating
+        #[ink(message)]
+        pub fn set_updated_at(&mut self, updated_at: Timestamp) {
+            self.updated_at = updated_at;
+        }
+    }
+}
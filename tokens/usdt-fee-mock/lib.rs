@@ -0,0 +1,114 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+/// psp22 token that burns a fee on every transfer, so the recipient gets less than was sent.
+/// `approve` stands in for a permit. e2e tests only
+#[ink::contract]
+pub mod d9_usdt_fee_mock {
+    use ink::prelude::vec::Vec;
+    use ink::storage::Mapping;
+
+    #[derive(scale::Decode, scale::Encode, Debug, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Error {
+        InsufficientBalance,
+        InsufficientAllowance,
+    }
+
+    #[ink(storage)]
+    #[derive(Default)]
+    pub struct D9USDTFeeMock {
+        total_supply: Balance,
+        balances: Mapping<AccountId, Balance>,
+        allowances: Mapping<(AccountId, AccountId), Balance>,
+        /// part of each transfer burned on the way, in permille
+        fee_permille: Balance,
+    }
+
+    impl D9USDTFeeMock {
+        #[ink(constructor)]
+        pub fn new(initial_supply: Balance, fee_permille: Balance) -> Self {
+            let mut instance = Self::default();
+            instance.total_supply = initial_supply;
+            instance.fee_permille = fee_permille.min(1000);
+            instance
+                .balances
+                .insert(Self::env().caller(), &initial_supply);
+            instance
+        }
+
+        /// `PSP22::total_supply`
+        #[ink(message, selector = 0x162df8c2)]
+        pub fn total_supply(&self) -> Balance {
+            self.total_supply
+        }
+
+        /// `PSP22::balance_of`
+        #[ink(message, selector = 0x6568382f)]
+        pub fn balance_of(&self, owner: AccountId) -> Balance {
+            self.balances.get(owner).unwrap_or(0)
+        }
+
+        /// `PSP22::allowance`
+        #[ink(message, selector = 0x4d47d921)]
+        pub fn allowance(&self, owner: AccountId, spender: AccountId) -> Balance {
+            self.allowances.get((owner, spender)).unwrap_or(0)
+        }
+
+        /// `PSP22::approve`
+        #[ink(message, selector = 0xb20f1bbd)]
+        pub fn approve(&mut self, spender: AccountId, value: Balance) -> Result<(), Error> {
+            self.allowances
+                .insert((self.env().caller(), spender), &value);
+            Ok(())
+        }
+
+        /// `PSP22::transfer`
+        #[ink(message, selector = 0xdb20f9f5)]
+        pub fn transfer(
+            &mut self,
+            to: AccountId,
+            value: Balance,
+            _data: Vec<u8>,
+        ) -> Result<(), Error> {
+            self.move_balance(self.env().caller(), to, value)
+        }
+
+        /// `PSP22::transfer_from`
+        #[ink(message, selector = 0x54b3c76e)]
+        pub fn transfer_from(
+            &mut self,
+            from: AccountId,
+            to: AccountId,
+            value: Balance,
+            _data: Vec<u8>,
+        ) -> Result<(), Error> {
+            let spender = self.env().caller();
+            let allowance = self.allowance(from, spender);
+            if allowance < value {
+                return Err(Error::InsufficientAllowance);
+            }
+            self.allowances
+                .insert((from, spender), &(allowance - value));
+            self.move_balance(from, to, value)
+        }
+
+        /// `from` pays `value`, `to` receives it less the fee
+        fn move_balance(
+            &mut self,
+            from: AccountId,
+            to: AccountId,
+            value: Balance,
+        ) -> Result<(), Error> {
+            let from_balance = self.balance_of(from);
+            if from_balance < value {
+                return Err(Error::InsufficientBalance);
+            }
+            let fee = value * self.fee_permille / 1000;
+            self.balances.insert(from, &(from_balance - value));
+            let to_balance = self.balance_of(to);
+            self.balances.insert(to, &(to_balance + value - fee));
+            self.total_supply -= fee;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,47 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+/// psp22 usdt with an EIP-2612 style `permit`, for testing permit based swaps.
+/// signatures are not verified: any permit before its deadline sets the allowance
+#[openbrush::implementation(PSP22)]
+#[openbrush::contract]
+pub mod d9_usdt_permit_mock {
+    use openbrush::{
+        contracts::psp22::PSP22Error,
+        traits::{Storage, String},
+    };
+
+    #[ink(storage)]
+    #[derive(Default, Storage)]
+    pub struct D9USDTPermitMock {
+        #[storage_field]
+        psp22: psp22::Data,
+    }
+
+    impl D9USDTPermitMock {
+        #[ink(constructor)]
+        pub fn new(initial_supply: Balance) -> Self {
+            let mut _instance = Self::default();
+            psp22::Internal::_mint_to(&mut _instance, Self::env().caller(), initial_supply)
+                .expect("Should mint");
+            _instance
+        }
+
+        /// set `owner`'s allowance for `spender` to `value`
+        #[ink(message)]
+        pub fn permit(
+            &mut self,
+            owner: AccountId,
+            spender: AccountId,
+            value: Balance,
+            deadline: Timestamp,
+            _v: u8,
+            _r: [u8; 32],
+            _s: [u8; 32],
+        ) -> Result<(), PSP22Error> {
+            if self.env().block_timestamp() > deadline {
+                return Err(PSP22Error::Custom(String::from("PermitExpired")));
+            }
+            psp22::Internal::_approve_from_to(self, owner, spender, value)
+        }
+    }
+}
@@ -0,0 +1,85 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+/// psp22 token in the style of older openbrush versions: `transfer`, `transfer_from` and
+/// `approve` return `()` and signal failure only by reverting. e2e tests only
+#[ink::contract]
+pub mod d9_usdt_unit_mock {
+    use ink::prelude::vec::Vec;
+    use ink::storage::Mapping;
+
+    #[ink(storage)]
+    #[derive(Default)]
+    pub struct D9USDTUnitMock {
+        total_supply: Balance,
+        balances: Mapping<AccountId, Balance>,
+        allowances: Mapping<(AccountId, AccountId), Balance>,
+    }
+
+    impl D9USDTUnitMock {
+        #[ink(constructor)]
+        pub fn new(initial_supply: Balance) -> Self {
+            let mut instance = Self::default();
+            instance.total_supply = initial_supply;
+            instance
+                .balances
+                .insert(Self::env().caller(), &initial_supply);
+            instance
+        }
+
+        /// `PSP22::total_supply`
+        #[ink(message, selector = 0x162df8c2)]
+        pub fn total_supply(&self) -> Balance {
+            self.total_supply
+        }
+
+        /// `PSP22::balance_of`
+        #[ink(message, selector = 0x6568382f)]
+        pub fn balance_of(&self, owner: AccountId) -> Balance {
+            self.balances.get(owner).unwrap_or(0)
+        }
+
+        /// `PSP22::allowance`
+        #[ink(message, selector = 0x4d47d921)]
+        pub fn allowance(&self, owner: AccountId, spender: AccountId) -> Balance {
+            self.allowances.get((owner, spender)).unwrap_or(0)
+        }
+
+        /// `PSP22::approve`
+        #[ink(message, selector = 0xb20f1bbd)]
+        pub fn approve(&mut self, spender: AccountId, value: Balance) {
+            self.allowances
+                .insert((self.env().caller(), spender), &value);
+        }
+
+        /// `PSP22::transfer`
+        #[ink(message, selector = 0xdb20f9f5)]
+        pub fn transfer(&mut self, to: AccountId, value: Balance, _data: Vec<u8>) {
+            self.move_balance(self.env().caller(), to, value);
+        }
+
+        /// `PSP22::transfer_from`
+        #[ink(message, selector = 0x54b3c76e)]
+        pub fn transfer_from(
+            &mut self,
+            from: AccountId,
+            to: AccountId,
+            value: Balance,
+            _data: Vec<u8>,
+        ) {
+            let spender = self.env().caller();
+            let allowance = self.allowance(from, spender);
+            assert!(allowance >= value, "insufficient allowance");
+            self.allowances
+                .insert((from, spender), &(allowance - value));
+            self.move_balance(from, to, value);
+        }
+
+        fn move_balance(&mut self, from: AccountId, to: AccountId, value: Balance) {
+            let from_balance = self.balance_of(from);
+            assert!(from_balance >= value, "insufficient balance");
+            self.balances.insert(from, &(from_balance - value));
+            let to_balance = self.balance_of(to);
+            self.balances.insert(to, &(to_balance + value));
+        }
+    }
+}